@@ -0,0 +1,137 @@
+//! Bearer tokens for the http api, and what they grant
+//!
+//! Tokens are configured in the `[server.tokens]` tables. When there are none,
+//! every request is allowed.
+use crate::config::{Scope, TokenConfig};
+
+use hashbrown::HashMap;
+use std::net::IpAddr;
+use types::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The configured tokens
+#[derive(Debug, Default)]
+pub struct Auth {
+    tokens: Vec<(String, TokenConfig)>,
+}
+
+impl Auth {
+    pub fn new(tokens: &HashMap<String, TokenConfig>) -> Self {
+        let tokens = tokens
+            .iter()
+            .map(|(name, token)| (name.clone(), token.clone()))
+            .collect();
+        Self { tokens }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Finds the token of an `authorization: Bearer <token>` header
+    pub fn grant(&self, header: Option<&str>) -> Result<Grant> {
+        if !self.is_enabled() {
+            return Ok(Grant::everything());
+        }
+
+        let bearer = header.and_then(bearer).ok_or(Error::Unauthorized)?;
+        let (name, token) = self
+            .tokens
+            .iter()
+            .find(|(_, token)| same(token.token.as_bytes(), bearer.as_bytes()))
+            .ok_or(Error::Unauthorized)?;
+
+        Ok(Grant {
+            name: Some(name.clone()),
+            scopes: token.scopes.clone(),
+            brains: token.brains.clone(),
+            address: None,
+        })
+    }
+}
+
+/// What the token of a request may do
+#[derive(Debug, Clone)]
+pub struct Grant {
+    // no name when auth is disabled
+    name: Option<String>,
+    scopes: Vec<Scope>,
+    brains: Option<Vec<String>>,
+    address: Option<IpAddr>,
+}
+
+impl Grant {
+    /// A grant for everything, used when no tokens are configured
+    pub fn everything() -> Self {
+        Self {
+            name: None,
+            scopes: vec![],
+            brains: None,
+            address: None,
+        }
+    }
+
+    /// Sets the address the request came from
+    pub fn with_address(mut self, address: Option<IpAddr>) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Who the request is from, for rate limiting
+    ///
+    /// This is the name of the token, or the address without tokens.
+    pub fn client(&self) -> String {
+        match (&self.name, self.address) {
+            (Some(name), _) => format!("token:{}", name),
+            (None, Some(address)) => address.to_string(),
+            (None, None) => "unknown".into(),
+        }
+    }
+
+    pub fn scope(&self, scope: Scope) -> Result<()> {
+        match &self.name {
+            Some(name) if !self.scopes.contains(&scope) => Err(Error::Forbidden {
+                reason: format!("the token '{}' doesn't have the '{}' scope", name, scope),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn brain(&self, brain: &str) -> Result<()> {
+        match (&self.name, &self.brains) {
+            (Some(name), Some(brains)) if !brains.iter().any(|b| b == brain) => {
+                Err(Error::Forbidden {
+                    reason: format!("the token '{}' can't use the brain '{}'", name, brain),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks both the scope and the brain
+    pub fn check(&self, scope: Scope, brain: &str) -> Result<()> {
+        self.scope(scope).and_then(|_| self.brain(brain))
+    }
+}
+
+fn bearer(header: &str) -> Option<&str> {
+    let mut parts = header.trim().splitn(2, ' ');
+    let scheme = parts.next()?;
+    let token = parts.next()?.trim();
+    if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+// looks at every byte, so the time taken doesn't give away where they differ
+fn same(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0, |diff, (l, r)| diff | (l ^ r))
+            == 0
+}
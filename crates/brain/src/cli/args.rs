@@ -0,0 +1,105 @@
+use brain::config::Startup;
+use brain::load::Overrides;
+use std::path::PathBuf;
+
+pub enum Command {
+    Train(pico_args::Arguments),
+    CheckConfig(pico_args::Arguments),
+    Rename(pico_args::Arguments),
+    Copy(pico_args::Arguments),
+    Load(pico_args::Arguments),
+}
+
+/// Flags shared by the commands that read the config file
+pub struct Flags {
+    pub config_file: PathBuf,
+    pub overrides: Overrides,
+}
+
+impl Flags {
+    pub fn parse(args: &mut pico_args::Arguments) -> anyhow::Result<Self> {
+        let config_file = match args.opt_value_from_str("--config")? {
+            Some(config_file) => config_file,
+            None => std::env::var_os("BRAIN_CONFIG")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| "brain.toml".into()),
+        };
+
+        let startup = match (args.contains("--strict"), args.contains("--lenient")) {
+            (true, true) => anyhow::bail!("only one of --strict and --lenient can be used"),
+            (true, false) => Some(Startup::Strict),
+            (false, true) => Some(Startup::Lenient),
+            (false, false) => None,
+        };
+
+        let overrides = Overrides {
+            host: args.opt_value_from_str("--host")?,
+            port: args.opt_value_from_str(["-p", "--port"])?,
+            unix_socket: args.opt_value_from_str("--unix-socket")?,
+            startup,
+        };
+
+        Ok(Self {
+            config_file,
+            overrides,
+        })
+    }
+}
+
+pub fn parse_args() -> anyhow::Result<Command> {
+    let mut args = pico_args::Arguments::from_env();
+
+    if args.contains("-h") {
+        print_help_and_quit(Quit::ShowShortHelp, Status::Okay);
+    }
+
+    if args.contains("--help") {
+        print_help_and_quit(Quit::ShowFullHelp, Status::Okay);
+    }
+
+    if args.contains(["-v", "--version"]) {
+        print_help_and_quit(Quit::ShowVersion, Status::Okay);
+    }
+
+    match args.subcommand()?.as_ref().map(|s| s.as_str()) {
+        Some("train") => Ok(Command::Train(args)),
+        Some("check-config") => Ok(Command::CheckConfig(args)),
+        Some("rename") => Ok(Command::Rename(args)),
+        Some("copy") => Ok(Command::Copy(args)),
+        Some(..) => print_help_and_quit(Quit::ShowShortHelp, Status::Error(1)),
+        None => Ok(Command::Load(args)),
+    }
+}
+
+// TODO redo this
+enum Quit {
+    ShowVersion,
+    ShowShortHelp,
+    ShowFullHelp,
+}
+
+enum Status {
+    Okay,
+    Error(i32),
+}
+
+fn print_help_and_quit(quit: Quit, status: Status) -> ! {
+    let (name, version) = (env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    match quit {
+        Quit::ShowVersion => println!("{} v{}", name, version),
+        Quit::ShowShortHelp => {
+            println!("{} v{}", name, version);
+            println!("{}", super::usage::USAGE_SHORT)
+        }
+        Quit::ShowFullHelp => {
+            println!("{} v{}", name, version);
+            println!("{}", super::usage::USAGE_LONG)
+        }
+    }
+
+    match status {
+        Status::Okay => std::process::exit(0),
+        Status::Error(code) => (std::process::exit(code)),
+    }
+}
@@ -0,0 +1,42 @@
+use super::args::Flags;
+use brain::load::{load_brains, read_config};
+
+pub async fn check_config(mut args: pico_args::Arguments) -> anyhow::Result<()> {
+    let flags = Flags::parse(&mut args)?;
+    args.finish()?;
+
+    let config = match read_config(&flags.config_file, &flags.overrides).await {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!(target: "brain", "{}", err);
+            std::process::exit(1);
+        }
+    };
+    log::info!(target: "brain", "'{}' is valid", flags.config_file.display());
+
+    let total = config.brains.len();
+    let (loaded, failed) = load_brains(config.brains).await;
+    for brain in &loaded {
+        log::info!(
+            target: "brain",
+            "ok: '{}' from '{}'",
+            brain.config.name,
+            brain.config.brain_file.display()
+        );
+    }
+    for (config, reason) in &failed {
+        log::error!(
+            target: "brain",
+            "failed: '{}' from '{}': {}",
+            config.name,
+            config.brain_file.display(),
+            reason
+        );
+    }
+
+    log::info!(target: "brain", "{}/{} brains loaded", loaded.len(), total);
+    if !failed.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
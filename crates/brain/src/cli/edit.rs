@@ -0,0 +1,111 @@
+use super::args::Flags;
+use brain::config::{BrainConfig, Config, ConfigManager};
+use brain::load::read_config;
+use brain::save_markov;
+
+use std::path::PathBuf;
+use types::is_valid_name;
+
+pub async fn rename(mut args: pico_args::Arguments) -> anyhow::Result<()> {
+    let flags = Flags::parse(&mut args)?;
+    let name = free(&mut args, "the brain to rename")?;
+    let new = free(&mut args, "the new name")?;
+    args.finish()?;
+
+    let config = read_config(&flags.config_file, &flags.overrides).await?;
+    let brain = configured(&config, &name)?;
+    expect_unused(&config, &new)?;
+
+    // read-only brain files are left as they are, like the server does
+    if !brain.read_only {
+        let mut markov = markov::load(&brain.brain_file)?;
+        markov.name = new.clone();
+        markov.mark_saved();
+        // like the server does, keeping the old file as a .bak
+        save_markov(markov, &brain.brain_file)
+            .await
+            .map_err(|err| anyhow::anyhow!("cannot save the brain: {:?}", err))?;
+    }
+
+    ConfigManager::new(&flags.config_file)
+        .rename_brain(&name, &new)
+        .await
+        .map_err(|err| anyhow::anyhow!("cannot update the config: {:?}", err))?;
+
+    log::info!(target: "brain", "renamed '{}' to '{}'", name, new);
+    Ok(())
+}
+
+pub async fn copy(mut args: pico_args::Arguments) -> anyhow::Result<()> {
+    let flags = Flags::parse(&mut args)?;
+    let output: Option<PathBuf> = args.opt_value_from_str(["-o", "--output"])?;
+    let depth: Option<usize> = args.opt_value_from_str(["-d", "--depth"])?;
+    let name = free(&mut args, "the brain to copy")?;
+    let new = free(&mut args, "the name of the copy")?;
+    args.finish()?;
+
+    let config = read_config(&flags.config_file, &flags.overrides).await?;
+    let brain = configured(&config, &name)?;
+    expect_unused(&config, &new)?;
+
+    let output = output.unwrap_or_else(|| format!("{}.db", new).into());
+    if output.exists() {
+        anyhow::bail!("'{}' already exists", output.display())
+    }
+
+    let markov = markov::load(&brain.brain_file)?;
+    let mut markov = match depth {
+        Some(depth) => markov
+            .with_depth(depth)
+            .ok_or_else(|| anyhow::anyhow!("the depth must be from 1 to {}", markov.depth))?,
+        None => markov,
+    };
+    markov.name = new.clone();
+    markov.meta.source = Some(format!("copied from {}", name));
+    markov.mark_saved();
+    markov::save(&markov, &output)?;
+
+    let copy = BrainConfig {
+        name: new.clone(),
+        brain_file: output,
+        read_only: false,
+        audit: None,
+        generate: brain.generate.clone(),
+    };
+    ConfigManager::new(&flags.config_file)
+        .copy_brain(&name, &copy)
+        .await
+        .map_err(|err| anyhow::anyhow!("cannot update the config: {:?}", err))?;
+
+    log::info!(
+        target: "brain",
+        "copied '{}' to '{}' at depth {}, in '{}'",
+        name,
+        new,
+        markov.depth,
+        copy.brain_file.display()
+    );
+    Ok(())
+}
+
+fn free(args: &mut pico_args::Arguments, what: &str) -> anyhow::Result<String> {
+    args.free_from_str()?
+        .ok_or_else(|| anyhow::anyhow!("{} must be provided", what))
+}
+
+fn configured<'a>(config: &'a Config, name: &str) -> anyhow::Result<&'a BrainConfig> {
+    config
+        .brains
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("'{}' isn't in the config", name))
+}
+
+fn expect_unused(config: &Config, name: &str) -> anyhow::Result<()> {
+    if !is_valid_name(name) {
+        anyhow::bail!("names may only contain letters, digits, '-' and '_'")
+    }
+    if config.brains.contains_key(name) {
+        anyhow::bail!("'{}' is already in the config", name)
+    }
+    Ok(())
+}
@@ -0,0 +1,5 @@
+pub mod args;
+pub mod check;
+pub mod edit;
+pub mod train;
+pub mod usage;
@@ -0,0 +1,130 @@
+pub const USAGE_SHORT: &str = r##"
+usage:
+    train a new brain from a file, using the defaults:
+        brain train --input foo.txt
+
+    load the brain.toml config and starts the http api
+        brain --port 9000
+
+    check the config and try to load every brain in it
+        brain check-config --config brain.toml
+
+    rename a brain in the config, and in its brain file
+        brain rename foo bar
+
+    copy a brain to a new brain file at a lower depth, and add it to the config
+        brain copy foo foo2 --output foo2.db --depth 2
+
+subcommands:
+    train
+    check-config
+    rename <name> <new name>
+    copy <name> <new name>
+
+flags:
+    -h,--help
+    -v,--version
+
+required:
+    -i,--input <filename>
+
+optional:
+    -o,--output <filename> [default: input file stem, or the new name for copy]
+    -n,--name <string> [default: input file stem]
+    -d,--depth <number> [default: 3]
+    -p,--port <number> [default: 9090]
+    --host <address> [default: 127.0.0.1]
+    --unix-socket <path>
+    --config <filename> [default: brain.toml]
+    --strict
+    --lenient
+"##;
+
+pub const USAGE_LONG: &str = r##"
+usage:
+    train a new brain from a file, using the defaults:
+        brain train --input foo.txt
+
+    load the brain.toml config and starts the http api
+        brain --port 9000
+
+    check the config and try to load every brain in it
+        brain check-config --config brain.toml
+
+    rename a brain in the config, and in its brain file
+        brain rename foo bar
+
+    copy a brain to a new brain file at a lower depth, and add it to the config
+        brain copy foo foo2 --output foo2.db --depth 2
+
+subcommands:
+    train
+    check-config
+    rename <name> <new name>
+    copy <name> <new name>
+
+flags:
+    -h,--help
+        display this message
+    -v,--version
+        display the version
+
+required:
+    -i,--input <filename>
+        the input file to train from
+
+optional:
+    -o,--output <filename> [default: input file stem]
+        output file to save to, e.g. foo.db 
+        (.db will be appended if its not provided)
+        for copy, the new brain file [default: the new name, with .db]
+
+    -n,--name <string> [default: input file stem]
+        the name of the database
+
+    -d,--depth <number> [default: 5]
+        the training depth
+        for copy, a lower depth to re-derive the copy at [default: the same depth]
+
+    -p,--port <number> [default: 9090]
+        port to listen on
+        (overrides BRAIN_PORT and server.port)
+
+    --host <address> [default: 127.0.0.1]
+        address to listen on
+        (overrides BRAIN_LISTEN_HOST and server.host)
+
+    --unix-socket <path>
+        unix domain socket to listen on, instead of the host and port
+        (overrides BRAIN_UNIX_SOCKET and server.unix_socket.path)
+
+    --config <filename> [default: brain.toml]
+        the configuration file to load
+        (overrides BRAIN_CONFIG)
+
+    --strict
+        stop if any brain fails to load (the default)
+        (overrides BRAIN_STARTUP and server.startup)
+
+    --lenient
+        keep running with the brains that loaded, and report the others in /list
+        (overrides BRAIN_STARTUP and server.startup)
+
+environment:
+    settings are taken from the flags, then the environment,
+    then the [server] section of the config file, then the defaults.
+
+    BRAIN_CONFIG            the configuration file to load
+    BRAIN_LISTEN_HOST       address to listen on
+    BRAIN_PORT              port to listen on
+    BRAIN_UNIX_SOCKET       unix domain socket to listen on
+    BRAIN_BODY_LIMIT        maximum size of a json body, in bytes
+    BRAIN_BATCH_LIMIT       maximum sentences in a batch generate
+    BRAIN_BATCH_BODY_LIMIT  maximum size of a batch train body, in bytes
+    BRAIN_DEFAULT_MIN       default minimum words to generate
+    BRAIN_DEFAULT_MAX       default maximum words to generate
+    BRAIN_STARTUP           'strict' or 'lenient'
+
+    a listening socket passed with LISTEN_FDS and LISTEN_PID (socket activation)
+    is used instead of the host and port, or the unix socket.
+"##;
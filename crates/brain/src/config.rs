@@ -1,7 +1,12 @@
-use crate::Result;
+use crate::{Error, Result};
 use hashbrown::HashMap;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt as _;
+use toml_edit::{value, Document, Item, Table};
+
+pub use types::generate::{GenerateConfig, GenerateDefaults};
 
 const SAMPLE_CONFIG: &str = include_str!("../sample_config.toml");
 
@@ -16,10 +21,275 @@ pub struct BrainConfig {
     pub name: String,
     pub brain_file: PathBuf,
     pub read_only: bool,
+    /// Where the lines it is trained with are recorded, if anywhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<AuditConfig>,
+    /// How it generates, when it differs from `server.defaults`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generate: Option<GenerateConfig>,
+}
+
+/// A JSON-lines log file, rotated once it reaches `max_size` bytes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogConfig {
+    pub file: PathBuf,
+    #[serde(default = "LogConfig::default_max_size")]
+    pub max_size: u64,
+    /// How many rotated files are kept, as `file.1` up to `file.<keep>`
+    #[serde(default = "LogConfig::default_keep")]
+    pub keep: usize,
+}
+
+impl LogConfig {
+    fn default_max_size() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn default_keep() -> usize {
+        5
+    }
+}
+
+/// A brain's audit log, of who trained it with what
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditConfig {
+    #[serde(flatten)]
+    pub log: LogConfig,
+    /// Record the lines themselves, rather than just their hashes
+    #[serde(default)]
+    pub text: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub body_limit: u64,
+    pub batch_limit: usize,
+    pub batch_body_limit: u64,
+    pub startup: Startup,
+    pub defaults: GenerateDefaults,
+    pub tokens: HashMap<String, TokenConfig>,
+    pub rate_limits: RateLimits,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<LogConfig>,
+    /// Listen here instead of on `host` and `port`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<UnixSocketConfig>,
+    /// Serve HTTPS, which needs the `tls` feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Let browsers call the api from other origins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".into(),
+            port: 9090,
+            body_limit: 1024 * 16,
+            batch_limit: 100,
+            batch_body_limit: 1024 * 1024,
+            startup: Startup::Strict,
+            defaults: GenerateDefaults::default(),
+            tokens: HashMap::new(),
+            rate_limits: RateLimits::default(),
+            access_log: None,
+            unix_socket: None,
+            tls: None,
+            cors: None,
+        }
+    }
+}
+
+/// The origins browsers may call the api from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CorsConfig {
+    /// Origins like `https://example.com`, or `*` for any origin
+    pub origins: Vec<String>,
+    /// How long browsers may cache a preflight response, in seconds
+    #[serde(default = "CorsConfig::default_max_age")]
+    pub max_age: u64,
+}
+
+impl CorsConfig {
+    fn default_max_age() -> u64 {
+        600
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+    }
+}
+
+/// A PEM certificate chain and private key, reloaded when either file changes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// A unix domain socket for the server to listen on
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnixSocketConfig {
+    pub path: PathBuf,
+    /// The socket's permissions, in octal, like `"660"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+impl UnixSocketConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            mode: None,
+        }
+    }
+
+    /// The permissions, if they were set
+    pub fn mode(&self) -> std::result::Result<Option<u32>, String> {
+        let mode = match &self.mode {
+            Some(mode) => mode,
+            None => return Ok(None),
+        };
+        match u32::from_str_radix(mode, 8) {
+            Ok(bits) if bits <= 0o777 => Ok(Some(bits)),
+            _ => Err(format!("'{}' isn't an octal mode, like \"660\"", mode)),
+        }
+    }
+}
+
+/// What to do when a brain fails to load at startup
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Startup {
+    /// Stop the server
+    Strict,
+    /// Keep running with the brains that did load, and report the others
+    Lenient,
+}
+
+impl std::str::FromStr for Startup {
+    type Err = String;
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input {
+            "strict" => Ok(Self::Strict),
+            "lenient" => Ok(Self::Lenient),
+            _ => Err("expected 'strict' or 'lenient'".into()),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn address(&self) -> std::result::Result<SocketAddr, std::net::AddrParseError> {
+        let ip: IpAddr = self.host.parse()?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
+}
+
+/// A bearer token for the http api, and what it may do
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct TokenConfig {
+    pub token: String,
+    pub scopes: Vec<Scope>,
+    /// The brains the token may use, or every brain when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brains: Option<Vec<String>>,
+}
+
+// the token itself is kept out of the logs
+impl std::fmt::Debug for TokenConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenConfig")
+            .field("scopes", &self.scopes)
+            .field("brains", &self.brains)
+            .finish()
+    }
+}
+
+/// What a token may do
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Generate from a brain, and subscribe to its training
+    Generate,
+    /// Train a brain, and follow or cancel its jobs
+    Train,
+    /// Save a brain to its file
+    Save,
+    /// Create, delete, reload and unload brains, and reload the config
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Generate => "generate",
+            Self::Train => "train",
+            Self::Save => "save",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Token bucket rate limits, for each kind of route
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RateLimits {
+    /// For each client: its token, or its address when there are no tokens
+    pub client: ScopeLimits,
+    /// For each brain, shared by every client
+    pub brain: ScopeLimits,
+}
+
+/// The limit for each scope, where a missing limit is unlimited
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ScopeLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generate: Option<Limit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub train: Option<Limit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub save: Option<Limit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<Limit>,
+}
+
+impl ScopeLimits {
+    pub fn get(&self, scope: Scope) -> Option<&Limit> {
+        match scope {
+            Scope::Generate => self.generate.as_ref(),
+            Scope::Train => self.train.as_ref(),
+            Scope::Save => self.save.as_ref(),
+            Scope::Admin => self.admin.as_ref(),
+        }
+    }
+}
+
+/// Allows `burst` requests at once, refilling at `per_second`
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Limit {
+    pub per_second: f64,
+    pub burst: u32,
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
     pub brains: HashMap<String, BrainConfig>,
 }
 
@@ -34,9 +304,220 @@ impl Config {
         Ok(res)
     }
 
-    #[allow(dead_code)]
+    /// Loads the config, then runs the validation pass over it
+    pub async fn load_validated(path: impl AsRef<Path>) -> Result<Self> {
+        let source = tokio::fs::read_to_string(path).await?;
+        let config = toml::from_str(&source)?;
+        match crate::validate::validate(&config, &source) {
+            issues if issues.is_empty() => Ok(config),
+            issues => Err(Error::Invalid(issues)),
+        }
+    }
+
     pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
         tokio::fs::write(path, toml::to_string_pretty(&self)?.as_bytes()).await?;
         Ok(())
     }
 }
+
+/// Serializes all runtime changes to the config file
+///
+/// Edits are applied to the parsed document, so comments and formatting are
+/// preserved. The file is rewritten by writing a temporary file next to it and
+/// renaming it over the original.
+pub struct ConfigManager {
+    path: PathBuf,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl ConfigManager {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn load(&self) -> std::result::Result<Config, types::Error> {
+        Config::load_validated(&self.path)
+            .await
+            .map_err(|err| self.invalid(err))
+    }
+
+    /// Adds a new brain, which must pass the checks the file gets when it is loaded
+    pub async fn add_brain(&self, config: &BrainConfig) -> std::result::Result<(), types::Error> {
+        self.edit(Some(&config.name), |brains| {
+            brains.insert(&config.name, Item::Table(brain_table(config)));
+        })
+        .await
+    }
+
+    /// Adds `config` with the generation options of `from`, but not its audit log
+    ///
+    /// Like `add_brain`, the copy must pass the checks the file gets when it is loaded.
+    pub async fn copy_brain(
+        &self,
+        from: &str,
+        config: &BrainConfig,
+    ) -> std::result::Result<(), types::Error> {
+        self.edit(Some(&config.name), |brains| {
+            let mut table = brain_table(config);
+            match brains.get(from).and_then(|brain| brain.get("generate")) {
+                // a fresh table, so it is written after the new brain
+                Some(Item::Table(generate)) => {
+                    let mut copy = Table::new();
+                    for (key, item) in generate.iter() {
+                        copy[key] = item.clone();
+                    }
+                    table["generate"] = Item::Table(copy);
+                }
+                Some(generate) => table["generate"] = generate.clone(),
+                None => {}
+            }
+            brains.insert(&config.name, Item::Table(table));
+        })
+        .await
+    }
+
+    /// Moves a brain's table to a new name, along with its comments
+    ///
+    /// Returns whether the brain was in the file.
+    pub async fn rename_brain(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> std::result::Result<bool, types::Error> {
+        self.edit(Some(to), |brains| match brains.remove(from) {
+            Some(brain) => {
+                brains.insert(to, brain);
+                true
+            }
+            None => false,
+        })
+        .await
+    }
+
+    pub async fn remove_brain(&self, name: &str) -> std::result::Result<bool, types::Error> {
+        self.edit(None, |brains| brains.remove(name).is_some())
+            .await
+    }
+
+    // `added` is a brain the edit adds, which mustn't already be in the file
+    async fn edit<F, T>(
+        &self,
+        added: Option<&str>,
+        apply: F,
+    ) -> std::result::Result<T, types::Error>
+    where
+        F: FnOnce(&mut Table) -> T,
+    {
+        let _guard = self.lock.lock().await;
+
+        let data = match tokio::fs::read_to_string(&self.path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(self.invalid(err)),
+        };
+
+        let mut doc = data.parse::<Document>().map_err(|err| self.invalid(err))?;
+        let brains = doc
+            .entry("brains")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| self.invalid("'brains' must be a table"))?;
+        brains.set_implicit(true);
+
+        if let Some(name) = added {
+            if brains.contains_key(name) {
+                return Err(types::Error::AlreadyExists { name: name.into() });
+            }
+        }
+
+        let out = apply(brains);
+        let data = doc.to_string();
+        if let Some(name) = added {
+            self.check_brain(name, &data)?;
+        }
+        self.write(data)
+            .await
+            .map_err(|err| types::Error::CannotUpdateConfig {
+                file: self.path.to_string_lossy().to_string(),
+                reason: err.to_string(),
+            })?;
+        Ok(out)
+    }
+
+    async fn write(&self, data: String) -> std::io::Result<()> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+
+        // brain.toml can hold tokens, so the new file keeps the old one's
+        // permissions, and is private until it has them
+        let permissions = match tokio::fs::metadata(&self.path).await {
+            Ok(metadata) => Some(metadata.permissions()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        // left behind by a write that didn't finish
+        match tokio::fs::remove_file(&temp).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = tokio::fs::OpenOptions::from(options).open(&temp).await?;
+        if let Some(permissions) = permissions {
+            tokio::fs::set_permissions(&temp, permissions).await?;
+        }
+        file.write_all(data.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp, &self.path).await
+    }
+
+    // the edited file would be refused when it is next loaded
+    fn check_brain(&self, name: &str, data: &str) -> std::result::Result<(), types::Error> {
+        let config = toml::from_str(data).map_err(|err| self.invalid(err))?;
+        let issues = crate::validate::validate_brain(&config, data, name);
+        if issues.is_empty() {
+            return Ok(());
+        }
+
+        let issues = issues
+            .iter()
+            .map(|issue| format!("{}: {}", issue.path, issue.message))
+            .collect::<Vec<_>>();
+        Err(types::Error::InvalidBody {
+            reason: issues.join("; "),
+        })
+    }
+
+    fn invalid(&self, err: impl ToString) -> types::Error {
+        types::Error::InvalidConfig {
+            file: self.path.to_string_lossy().to_string(),
+            reason: err.to_string(),
+        }
+    }
+}
+
+fn brain_table(config: &BrainConfig) -> Table {
+    let mut table = Table::new();
+    table["brain_file"] = value(config.brain_file.to_string_lossy().to_string());
+    table["read_only"] = value(config.read_only);
+    table
+}
+
+impl std::fmt::Debug for ConfigManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConfigManager")
+            .field("path", &self.path)
+            .finish()
+    }
+}
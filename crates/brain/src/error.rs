@@ -3,6 +3,7 @@ pub enum Error {
     Io(std::io::Error),
     Serialize(toml::ser::Error),
     Deserialize(toml::de::Error),
+    Invalid(Vec<crate::validate::Issue>),
 }
 
 impl From<std::io::Error> for Error {
@@ -29,6 +30,13 @@ impl std::fmt::Display for Error {
             Error::Io(err) => write!(f, "io error: {}", err),
             Error::Serialize(err) => write!(f, "serialize error: {}", err),
             Error::Deserialize(err) => write!(f, "deserialize error: {}", err),
+            Error::Invalid(issues) => {
+                write!(f, "invalid config:")?;
+                for issue in issues {
+                    write!(f, "\n  {}", issue)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -39,6 +47,7 @@ impl std::error::Error for Error {
             Error::Io(err) => Some(err),
             Error::Serialize(err) => Some(err),
             Error::Deserialize(err) => Some(err),
+            Error::Invalid(..) => None,
         }
     }
 }
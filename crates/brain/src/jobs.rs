@@ -0,0 +1,369 @@
+//! Training from large uploads, in the background
+//!
+//! An upload is spooled to a temporary file first, so the request can finish
+//! before training starts. The job then trains the brain a chunk of lines at a
+//! time, so generating from it isn't blocked until the whole upload is done.
+use crate::stats::{Sample, Stats};
+use crate::BrainDb;
+
+use bytes::Buf;
+use futures::prelude::*;
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader};
+use tokio::sync::Mutex;
+use types::{responses, Error};
+
+type Result<T> = std::result::Result<T, Error>;
+
+// lines trained each time the brain is locked
+const CHUNK_SIZE: usize = 1000;
+
+// finished jobs that are remembered, so their result can still be fetched
+const HISTORY: usize = 100;
+
+struct Entry {
+    job: responses::Job,
+    started: Instant,
+    cancel: Arc<AtomicBool>,
+}
+
+/// The background training jobs
+pub struct Jobs {
+    next_id: AtomicU64,
+    // shared with the running jobs, so they can report their progress
+    jobs: Arc<Mutex<BTreeMap<u64, Entry>>>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            jobs: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Spools the upload to a file, then trains `brain` with it in the background
+    ///
+    /// `gzip` is whether the upload is compressed, and `source` who sent it, for
+    /// the audit log. Training events aren't sent for the lines of a job.
+    pub async fn start<S, B, E>(
+        &self,
+        brain: BrainDb,
+        body: S,
+        gzip: bool,
+        source: &str,
+    ) -> Result<responses::Job>
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Unpin,
+        B: Buf,
+        E: std::fmt::Display,
+    {
+        if brain.config.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        // the temp dir is shared, so the name can't be guessed ahead of time
+        let path = std::env::temp_dir().join(format!(
+            "brain-job-{}-{}-{:016x}.txt",
+            std::process::id(),
+            id,
+            rand::random::<u64>()
+        ));
+
+        let file = create(&path).await?;
+        let total = match spool(file, &path, body, gzip).await {
+            Ok(total) => total,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                return Err(err);
+            }
+        };
+
+        let job = responses::Job {
+            id,
+            brain: brain.config.name.clone(),
+            state: responses::JobState::Running,
+            lines: 0,
+            total,
+            lines_per_sec: 0.0,
+            elapsed: Duration::default(),
+            reason: None,
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        {
+            let mut jobs = self.jobs.lock().await;
+            prune(&mut jobs);
+            let entry = Entry {
+                job: job.clone(),
+                started: Instant::now(),
+                cancel: Arc::clone(&cancel),
+            };
+            jobs.insert(id, entry);
+        }
+
+        log::info!(target: "brain", "training '{}' with {} lines as job {}", job.brain, total, id);
+        let jobs = Arc::clone(&self.jobs);
+        let source = source.to_string();
+        tokio::spawn(async move {
+            let job = (id, source.as_str());
+            let result = train(&jobs, job, &brain, &path, total, &cancel).await;
+            let _ = tokio::fs::remove_file(&path).await;
+            finish(&jobs, id, result).await;
+        });
+
+        Ok(job)
+    }
+
+    pub async fn get(&self, id: u64) -> Result<responses::Job> {
+        let jobs = self.jobs.lock().await;
+        let entry = jobs.get(&id).ok_or(Error::JobNotFound { id })?;
+        Ok(snapshot(entry))
+    }
+
+    /// Stops the job after the chunk it is training
+    ///
+    /// The job is `Cancelling` until that chunk is done, and then `Cancelled`.
+    /// Cancelling a finished job does nothing.
+    pub async fn cancel(&self, id: u64) -> Result<responses::Job> {
+        let mut jobs = self.jobs.lock().await;
+        let entry = jobs.get_mut(&id).ok_or(Error::JobNotFound { id })?;
+        if entry.job.state == responses::JobState::Running {
+            entry.cancel.store(true, Ordering::SeqCst);
+            entry.job.state = responses::JobState::Cancelling;
+            log::info!(target: "brain", "cancelling job {}", id);
+        }
+        Ok(snapshot(entry))
+    }
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Jobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jobs")
+            .field("next_id", &self.next_id)
+            .finish()
+    }
+}
+
+fn snapshot(entry: &Entry) -> responses::Job {
+    let mut job = entry.job.clone();
+    if !job.state.is_finished() {
+        job.elapsed = entry.started.elapsed();
+    }
+    job
+}
+
+// forgets the oldest finished jobs
+fn prune(jobs: &mut BTreeMap<u64, Entry>) {
+    let finished = jobs
+        .iter()
+        .filter(|(_, entry)| entry.job.state.is_finished())
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    let excess = finished.len().saturating_sub(HISTORY);
+    for id in finished.into_iter().take(excess) {
+        jobs.remove(&id);
+    }
+}
+
+/// Creates the file an upload is spooled to, which only the owner can read
+///
+/// It has to be a new file, so a file or link that was put in its place
+/// isn't written through.
+async fn create(path: &Path) -> Result<tokio::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    tokio::fs::OpenOptions::from(options)
+        .open(path)
+        .await
+        .map_err(|err| Error::Internal {
+            reason: format!("cannot create {}: {}", path.display(), err),
+        })
+}
+
+/// Writes the (decompressed) upload to `file`, returning how many lines it has
+async fn spool<S, B, E>(
+    mut file: tokio::fs::File,
+    path: &Path,
+    mut body: S,
+    gzip: bool,
+) -> Result<usize>
+where
+    S: Stream<Item = std::result::Result<B, E>> + Unpin,
+    B: Buf,
+    E: std::fmt::Display,
+{
+    let invalid = |reason: String| Error::InvalidBody { reason };
+    let cannot_write = |err: std::io::Error| Error::Internal {
+        reason: format!("cannot write the upload to {}: {}", path.display(), err),
+    };
+
+    let mut decoder = if gzip {
+        Some(flate2::write::GzDecoder::new(vec![]))
+    } else {
+        None
+    };
+
+    let mut counter = Counter::default();
+    while let Some(chunk) = body.next().await {
+        let mut chunk = chunk.map_err(|err| invalid(err.to_string()))?;
+        while chunk.has_remaining() {
+            let data = chunk.bytes();
+            let len = data.len();
+            let data = match &mut decoder {
+                Some(decoder) => {
+                    decoder
+                        .write_all(data)
+                        .map_err(|err| invalid(err.to_string()))?;
+                    std::mem::take(decoder.get_mut())
+                }
+                None => data.to_vec(),
+            };
+            chunk.advance(len);
+
+            counter.count(&data);
+            file.write_all(&data).await.map_err(cannot_write)?;
+        }
+    }
+
+    if let Some(decoder) = decoder {
+        let data = decoder.finish().map_err(|err| invalid(err.to_string()))?;
+        counter.count(&data);
+        file.write_all(&data).await.map_err(cannot_write)?;
+    }
+    file.flush().await.map_err(cannot_write)?;
+
+    Ok(counter.lines())
+}
+
+#[derive(Default)]
+struct Counter {
+    newlines: usize,
+    last: Option<u8>,
+}
+
+impl Counter {
+    fn count(&mut self, data: &[u8]) {
+        self.newlines += data.iter().filter(|&&b| b == b'\n').count();
+        if let Some(&b) = data.last() {
+            self.last.replace(b);
+        }
+    }
+
+    fn lines(&self) -> usize {
+        // the last line doesn't need a newline
+        match self.last {
+            Some(b'\n') | None => self.newlines,
+            Some(..) => self.newlines + 1,
+        }
+    }
+}
+
+async fn train(
+    jobs: &Mutex<BTreeMap<u64, Entry>>,
+    (id, source): (u64, &str),
+    brain: &BrainDb,
+    path: &Path,
+    total: usize,
+    cancel: &AtomicBool,
+) -> std::result::Result<Sample, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut lines = BufReader::new(file).lines();
+
+    // about a hundred samples over the whole job
+    let (stats, samples) = Stats::new((total / 100).max(1));
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        while chunk.len() < CHUNK_SIZE {
+            match lines.next().await {
+                Some(Ok(line)) => chunk.push(line),
+                Some(Err(err)) => return Err(err.to_string()),
+                None => break,
+            }
+        }
+        if chunk.is_empty() {
+            break;
+        }
+
+        {
+            let mut markov = brain.lock().await;
+            for line in &chunk {
+                stats.tick();
+                markov.train_text(line);
+            }
+            let bytes = chunk.iter().map(String::len).sum();
+            brain.metrics.trained(chunk.len(), bytes);
+        }
+        let lines = chunk.iter().map(|line| (line.as_str(), Ok(())));
+        brain.audit(source, Some(id), lines).await;
+        // not held across the await, the receiver can't be shared
+        let sample = samples.try_iter().last();
+        if let Some(sample) = sample {
+            progress(jobs, id, sample).await;
+        }
+    }
+
+    Ok(stats.done())
+}
+
+async fn progress(jobs: &Mutex<BTreeMap<u64, Entry>>, id: u64, sample: Sample) {
+    if let Some(entry) = jobs.lock().await.get_mut(&id) {
+        entry.job.lines = sample.count;
+        entry.job.lines_per_sec = sample.lines_per_sec();
+    }
+}
+
+async fn finish(
+    jobs: &Mutex<BTreeMap<u64, Entry>>,
+    id: u64,
+    result: std::result::Result<Sample, String>,
+) {
+    let mut jobs = jobs.lock().await;
+    let entry = match jobs.get_mut(&id) {
+        Some(entry) => entry,
+        None => return,
+    };
+
+    let job = &mut entry.job;
+    job.elapsed = entry.started.elapsed();
+    match result {
+        Ok(sample) => {
+            job.lines = sample.count;
+            job.lines_per_sec = sample.lines_per_sec();
+            job.state = match job.state {
+                responses::JobState::Cancelling => responses::JobState::Cancelled,
+                _ => responses::JobState::Done,
+            };
+            log::info!(
+                target: "brain",
+                "job {} trained '{}' with {} lines in {:.2?}",
+                id, job.brain, job.lines, job.elapsed
+            );
+        }
+        Err(reason) => {
+            log::warn!(target: "brain", "job {} failed: {}", id, reason);
+            job.state = responses::JobState::Failed;
+            job.reason.replace(reason);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+pub mod auth;
+pub mod config;
+pub mod jobs;
+pub mod limit;
+pub mod load;
+pub mod logfile;
+pub mod metrics;
+pub mod stats;
+pub mod train;
+pub mod validate;
+
+mod error;
+pub use error::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+mod manager;
+pub use manager::{failure, save_markov, Brain, BrainDb, BrainManager, TrainingEvent};
+
+#[cfg(feature = "http")]
+pub mod server;
+#[cfg(feature = "http")]
+pub use server::Server;
@@ -0,0 +1,79 @@
+//! Token bucket rate limiting
+//!
+//! Every client and every brain gets a bucket for each kind of route. The
+//! limits themselves are in the `[server.rate_limits]` tables.
+use crate::config::{Limit, Scope};
+
+use hashbrown::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use types::Error;
+
+// buckets that have filled back up are forgotten past this many
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Subject {
+    Client(String),
+    Brain(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // when it will be full again, if nothing else is taken
+    full_at: Instant,
+}
+
+/// The buckets of every client and brain
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(Scope, Subject), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes a request from the client's bucket
+    pub fn client(&self, scope: Scope, client: &str, limit: &Limit) -> Result<(), Error> {
+        self.take((scope, Subject::Client(client.to_string())), limit)
+    }
+
+    /// Takes a request from the brain's bucket
+    pub fn brain(&self, scope: Scope, brain: &str, limit: &Limit) -> Result<(), Error> {
+        self.take((scope, Subject::Brain(brain.to_string())), limit)
+    }
+
+    fn take(&self, key: (Scope, Subject), limit: &Limit) -> Result<(), Error> {
+        let now = Instant::now();
+        let burst = f64::from(limit.burst);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: burst,
+            updated: now,
+            full_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            let wait = (1.0 - bucket.tokens) / limit.per_second;
+            return Err(Error::RateLimited { retry_after: wait });
+        }
+
+        bucket.tokens -= 1.0;
+        let refill = (burst - bucket.tokens) / limit.per_second;
+        bucket.full_at = now + Duration::from_secs_f64(refill);
+        Ok(())
+    }
+}
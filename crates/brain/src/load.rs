@@ -1,43 +1,178 @@
-use crate::config::{BrainConfig, Config, ConfiguredMarkov};
+use crate::config::{
+    BrainConfig, Config, ConfiguredMarkov, ServerConfig, Startup, UnixSocketConfig,
+};
+use crate::validate::validate;
 use futures::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-pub struct Arguments {
-    pub port: u16,
+/// Everything read from the config file at startup
+///
+/// The brains themselves are loaded later, by `BrainManager::load_pending`.
+pub struct Loaded {
+    pub server: ServerConfig,
+    /// The server section without the overrides, as the file has it
+    pub file_server: ServerConfig,
     pub config_file: PathBuf,
-    pub brains: Vec<ConfiguredMarkov>,
+    pub brains: Vec<BrainConfig>,
 }
 
-pub async fn load(mut args: pico_args::Arguments) -> anyhow::Result<Arguments> {
-    let port: u16 = args.opt_value_from_str(["-p", "--port"])?.unwrap_or(9090);
+/// Settings that take precedence over the environment and the config file
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub unix_socket: Option<PathBuf>,
+    pub startup: Option<Startup>,
+}
+
+/// Reads the config file, applies the overrides and validates the result
+///
+/// Settings are taken from the overrides, then the environment, then the config file.
+pub async fn read_config(path: &Path, overrides: &Overrides) -> anyhow::Result<Config> {
+    read(path, overrides).await.map(|(config, _)| config)
+}
+
+// the config with the overrides, and the server section without them
+async fn read(path: &Path, overrides: &Overrides) -> anyhow::Result<(Config, ServerConfig)> {
+    let source = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|err| anyhow::anyhow!("cannot read '{}': {}", path.display(), err))?;
+
+    let mut config: Config = toml::from_str(&source)
+        .map_err(|err| anyhow::anyhow!("cannot parse '{}': {}", path.display(), err))?;
+
+    let file_server = config.server.clone();
+    let server = &mut config.server;
+    override_from_env(server)?;
+    if let Some(host) = overrides.host.clone() {
+        server.host = host;
+    }
+    if let Some(port) = overrides.port {
+        server.port = port;
+    }
+    if let Some(path) = overrides.unix_socket.clone() {
+        set_unix_socket(server, path);
+    }
+    if let Some(startup) = overrides.startup {
+        server.startup = startup;
+    }
 
-    // TODO maybe get this from an --config argument
-    let config_file = "brain.toml".into();
+    let issues = validate(&config, &source);
+    if !issues.is_empty() {
+        for issue in &issues {
+            log::error!(target: "brain", "{}: {}", path.display(), issue);
+        }
+        anyhow::bail!("'{}' has {} problem(s)", path.display(), issues.len());
+    }
 
-    // make a default one if it doesn't exist
-    tokio::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .write(true)
-        .open(&config_file)
-        .await?;
+    Ok((config, file_server))
+}
 
-    let config = Config::load(&config_file).await.unwrap_or_default();
+/// Loads every configured brain, returning the ones that failed separately
+pub async fn load_brains(
+    brains: impl IntoIterator<Item = (String, BrainConfig)>,
+) -> (Vec<ConfiguredMarkov>, Vec<(BrainConfig, String)>) {
+    let results = brains
+        .into_iter()
+        .map(|(name, mut config)| {
+            config.name = name;
+            let copy = config.clone();
+            load_brain(config).map(move |res| (copy, res))
+        })
+        .collect::<stream::FuturesUnordered<_>>()
+        .collect::<Vec<_>>()
+        .await;
 
-    let set = futures::stream::FuturesUnordered::new();
-    for (name, mut config) in config.brains {
-        config.name = name;
-        set.push(load_brain(config));
+    let (mut loaded, mut failed) = (vec![], vec![]);
+    for (config, res) in results {
+        match res {
+            Ok(brain) => loaded.push(brain),
+            Err(err) => failed.push((config, err.to_string())),
+        }
     }
+    (loaded, failed)
+}
+
+/// Reads the config file, without loading the brains in it
+pub async fn load(
+    config_file: impl Into<PathBuf>,
+    overrides: &Overrides,
+) -> anyhow::Result<Loaded> {
+    let config_file = config_file.into();
+    let (config, file_server) = read(&config_file, overrides).await?;
+    let brains = config
+        .brains
+        .into_iter()
+        .map(|(name, mut config)| {
+            config.name = name;
+            config
+        })
+        .collect();
 
-    Ok(Arguments {
-        port,
+    Ok(Loaded {
+        server: config.server,
+        file_server,
         config_file,
-        brains: set.try_collect().await?,
+        brains,
     })
 }
 
-async fn load_brain(config: BrainConfig) -> anyhow::Result<ConfiguredMarkov> {
+fn override_from_env(server: &mut ServerConfig) -> anyhow::Result<()> {
+    if let Some(host) = env_var("BRAIN_LISTEN_HOST")? {
+        server.host = host;
+    }
+    if let Some(port) = env_var("BRAIN_PORT")? {
+        server.port = port;
+    }
+    if let Some(path) = env_var::<PathBuf>("BRAIN_UNIX_SOCKET")? {
+        set_unix_socket(server, path);
+    }
+    if let Some(body_limit) = env_var("BRAIN_BODY_LIMIT")? {
+        server.body_limit = body_limit;
+    }
+    if let Some(batch_limit) = env_var("BRAIN_BATCH_LIMIT")? {
+        server.batch_limit = batch_limit;
+    }
+    if let Some(batch_body_limit) = env_var("BRAIN_BATCH_BODY_LIMIT")? {
+        server.batch_body_limit = batch_body_limit;
+    }
+    if let Some(min) = env_var("BRAIN_DEFAULT_MIN")? {
+        server.defaults.min = min;
+    }
+    if let Some(max) = env_var("BRAIN_DEFAULT_MAX")? {
+        server.defaults.max = max;
+    }
+    if let Some(startup) = env_var("BRAIN_STARTUP")? {
+        server.startup = startup;
+    }
+    Ok(())
+}
+
+// keeps the mode from the config file, if it has one
+fn set_unix_socket(server: &mut ServerConfig, path: PathBuf) {
+    match &mut server.unix_socket {
+        Some(socket) => socket.path = path,
+        None => server.unix_socket = Some(UnixSocketConfig::new(path)),
+    }
+}
+
+fn env_var<T>(key: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|err| anyhow::anyhow!("invalid value for {}: '{}': {}", key, value, err)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(err) => Err(anyhow::anyhow!("invalid value for {}: {}", key, err)),
+    }
+}
+
+pub async fn load_brain(config: BrainConfig) -> anyhow::Result<ConfiguredMarkov> {
     let (tx, rx) = tokio::sync::oneshot::channel();
 
     tokio::task::spawn_blocking(|| {
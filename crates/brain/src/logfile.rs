@@ -0,0 +1,260 @@
+//! JSON-lines log files, for the access log and the audit logs of the brains
+//!
+//! A file is rotated once the next line would take it past its `max_size`:
+//! `file.1` becomes `file.2` and so on, up to `keep` of them, and `file`
+//! becomes `file.1`.
+//!
+//! The files are written on the blocking threads, so a slow disk doesn't hold
+//! up the other requests.
+use crate::config::{AuditConfig, LogConfig};
+
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use types::Error;
+
+/// A log file, opened on the first write
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    // shared with the blocking writes
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    config: LogConfig,
+    // the file, and how big it is
+    file: Option<(File, u64)>,
+}
+
+impl LogFile {
+    pub fn new(config: LogConfig) -> Self {
+        Self {
+            path: config.file.clone(),
+            state: Arc::new(Mutex::new(State { config, file: None })),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Uses the new size and number of files to keep from now on
+    ///
+    /// The file itself can't be changed, a new `LogFile` is needed for that.
+    pub fn configure(&self, config: &LogConfig) {
+        let mut state = self.state.lock().unwrap();
+        state.config.max_size = config.max_size;
+        state.config.keep = config.keep;
+    }
+
+    /// Appends the entry as a line
+    ///
+    /// Failures are logged rather than returned, so a request doesn't fail
+    /// because of its log line.
+    pub async fn write(&self, entry: &impl Serialize) {
+        self.write_lines(line(entry).into_iter().collect()).await
+    }
+
+    /// Appends lines that were already serialized, in order
+    async fn write_lines(&self, lines: Vec<Vec<u8>>) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let state = Arc::clone(&self.state);
+        let write = tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap();
+            lines.iter().try_for_each(|line| state.append(line))
+        });
+        let err = match write.await {
+            Ok(Ok(())) => return,
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        };
+        log::warn!(target: "brain", "cannot write to '{}': {}", self.path.display(), err);
+    }
+}
+
+// the entry as a line of JSON
+fn line(entry: &impl Serialize) -> Option<Vec<u8>> {
+    match serde_json::to_vec(entry) {
+        Ok(mut line) => {
+            line.push(b'\n');
+            Some(line)
+        }
+        Err(err) => {
+            log::warn!(target: "brain", "cannot serialize a log line: {}", err);
+            None
+        }
+    }
+}
+
+impl State {
+    // the file is left closed on errors, so the next line tries to open it again
+    fn append(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let len = line.len() as u64;
+        let (mut handle, mut size) = match self.file.take() {
+            Some(open) => open,
+            None => self.open()?,
+        };
+
+        if size > 0 && size + len > self.config.max_size {
+            drop(handle);
+            self.rotate()?;
+            let (new, empty) = self.open()?;
+            handle = new;
+            size = empty;
+        }
+
+        handle.write_all(line)?;
+        self.file = Some((handle, size + len));
+        Ok(())
+    }
+
+    fn open(&self) -> std::io::Result<(File, u64)> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.file)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        let path = &self.config.file;
+        let keep = self.config.keep;
+        if keep == 0 {
+            return std::fs::remove_file(path);
+        }
+
+        // renaming over a file doesn't work everywhere
+        let _ = std::fs::remove_file(numbered(path, keep));
+        for n in (1..keep).rev() {
+            let from = numbered(path, n);
+            if from.exists() {
+                std::fs::rename(from, numbered(path, n + 1))?;
+            }
+        }
+        std::fs::rename(path, numbered(path, 1))
+    }
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    path.into()
+}
+
+/// A line of the access log
+#[derive(Debug, Serialize)]
+pub struct Access<'a> {
+    pub time: f64,
+    pub address: Option<String>,
+    pub method: &'a str,
+    pub path: &'a str,
+    /// The `operationId` of the route
+    pub route: &'a str,
+    pub brain: Option<&'a str>,
+    pub status: u16,
+    pub elapsed_ms: f64,
+    pub user_agent: Option<&'a str>,
+}
+
+/// A line of a brain's audit log
+#[derive(Debug, Serialize)]
+struct Audit<'a> {
+    time: f64,
+    brain: &'a str,
+    /// Who trained it: a token, or an address without tokens
+    source: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job: Option<u64>,
+    accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a Error>,
+    bytes: usize,
+    hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+}
+
+/// A brain's audit log, of every line it was trained with or refused
+#[derive(Debug)]
+pub struct AuditLog {
+    // shared with the brain's earlier configs, so there is one writer for it
+    log: Arc<LogFile>,
+    brain: String,
+    text: bool,
+}
+
+impl AuditLog {
+    pub fn new(brain: &str, config: &AuditConfig) -> Self {
+        Self {
+            log: Arc::new(LogFile::new(config.log.clone())),
+            brain: brain.into(),
+            text: config.text,
+        }
+    }
+
+    /// The log for a brain's new config, which keeps writing with this one's
+    /// file when it is the same
+    pub fn reconfigure(&self, brain: &str, config: &AuditConfig) -> Self {
+        if self.log.path() != config.log.file {
+            return Self::new(brain, config);
+        }
+        self.log.configure(&config.log);
+        Self {
+            log: Arc::clone(&self.log),
+            brain: brain.into(),
+            text: config.text,
+        }
+    }
+
+    /// Records lines, from an upload when there's a `job`
+    pub async fn record<'a, I>(&self, source: &str, job: Option<u64>, lines: I)
+    where
+        I: IntoIterator<Item = (&'a str, Result<(), &'a Error>)>,
+    {
+        let lines = lines
+            .into_iter()
+            .filter_map(|(data, result)| {
+                line(&Audit {
+                    time: now(),
+                    brain: &self.brain,
+                    source,
+                    job,
+                    accepted: result.is_ok(),
+                    error: result.err(),
+                    bytes: data.len(),
+                    hash: hash(data),
+                    text: if self.text { Some(data) } else { None },
+                })
+            })
+            .collect();
+        self.log.write_lines(lines).await;
+    }
+}
+
+/// Seconds since the unix epoch
+pub fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// The 64-bit FNV-1a hash of the text, in hex
+///
+/// It doesn't change between builds, so a line can be found in an audit log
+/// by hashing it.
+pub fn hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
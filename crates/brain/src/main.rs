@@ -1,44 +1,38 @@
-mod args;
-mod config;
-mod load;
-mod stats;
-mod train;
-mod usage;
+mod cli;
+use cli::args;
 
-mod server;
-pub use server::Server;
-
-mod error;
-use error::Error;
-
-type Result<T> = std::result::Result<T, Error>;
+use brain::{BrainManager, Server};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     alto_logger::init(alto_logger::Style::MultiLine, Default::default()).expect("init logger");
 
-    let load::Arguments {
-        port,
-        config_file,
-        brains,
-    } = match args::parse_args()? {
-        args::Command::Train(args) => return train::train(args).await,
-        args::Command::Load(args) => match load::load(args).await {
-            Ok(args) => args,
-            Err(err) => {
-                log::error!(target: "brain", "cannot load configuration file at 'brain.toml': {}", err);
-                log::error!(target: "brain", "verify the file exists and well-formed.");
-                log::error!(target: "brain", "here's a sample config:");
-                config::Config::print_default();
-                std::process::exit(1);
+    let manager = match args::parse_args()? {
+        args::Command::Train(args) => return cli::train::train(args).await,
+        args::Command::CheckConfig(args) => return cli::check::check_config(args).await,
+        args::Command::Rename(args) => return cli::edit::rename(args).await,
+        args::Command::Copy(args) => return cli::edit::copy(args).await,
+        args::Command::Load(mut args) => {
+            let flags = args::Flags::parse(&mut args)?;
+            args.finish()?;
+
+            match BrainManager::load(flags.config_file, &flags.overrides).await {
+                Ok(manager) => manager,
+                Err(err) => {
+                    log::error!(target: "brain", "cannot start: {}", err);
+                    log::error!(target: "brain", "verify the file exists and well-formed.");
+                    log::error!(target: "brain", "`brain check-config` will list every problem.");
+                    log::error!(target: "brain", "here's a sample config:");
+                    brain::config::Config::print_default();
+                    std::process::exit(1);
+                }
             }
-        },
+        }
     };
 
-    let mut server = Server::default();
-    for brain in brains {
-        server.add_brain(brain)
+    if let Err(err) = Server::new(manager).run().await {
+        log::error!(target: "brain", "stopping: {}", err);
+        std::process::exit(1);
     }
-    server.run(config_file, port).await;
     Ok(())
 }
@@ -0,0 +1,1199 @@
+use crate::config::{
+    BrainConfig, ConfigManager, ConfiguredMarkov, GenerateDefaults, Scope, ServerConfig, Startup,
+};
+use crate::jobs::Jobs;
+use crate::limit::RateLimiter;
+use crate::load::{load_brain, load_brains, Overrides};
+use crate::logfile::AuditLog;
+use crate::metrics::{BrainMetrics, BrainSample, Metrics};
+
+use futures::{stream::FuturesUnordered, FutureExt as _, Stream, StreamExt as _};
+use hashbrown::HashMap;
+use markov::Markov;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+use types::generate::{Generation, OptionsSource};
+use types::{input, responses, Error};
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub type BrainDb = Arc<Brain>;
+
+pub struct Brain {
+    pub config: BrainConfig,
+    // shared so the options can be replaced without touching the chain
+    pub markov: Arc<Mutex<Markov>>,
+    // shared along with the chain
+    pub metrics: Arc<BrainMetrics>,
+    audit: Option<AuditLog>,
+}
+
+impl From<ConfiguredMarkov> for Brain {
+    fn from(brain: ConfiguredMarkov) -> Self {
+        let ConfiguredMarkov { config, markov } = brain;
+        Self::new(config, markov)
+    }
+}
+
+impl std::fmt::Debug for Brain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Brain")
+            .field("name", &self.config.name)
+            .field("read_only", &self.config.read_only)
+            .finish()
+    }
+}
+
+impl Brain {
+    pub fn new(config: BrainConfig, markov: Markov) -> Self {
+        Self::with_markov(config, Arc::new(Mutex::new(markov)), Arc::default(), None)
+    }
+
+    // keeps the chain and its metrics when only the options change, and the
+    // audit log when it is still written to the same file
+    fn with_markov(
+        config: BrainConfig,
+        markov: Arc<Mutex<Markov>>,
+        metrics: Arc<BrainMetrics>,
+        previous: Option<&AuditLog>,
+    ) -> Self {
+        let audit = config.audit.as_ref().map(|audit| match previous {
+            Some(previous) => previous.reconfigure(&config.name, audit),
+            None => AuditLog::new(&config.name, audit),
+        });
+        Self {
+            config,
+            markov,
+            metrics,
+            audit,
+        }
+    }
+
+    /// Records lines in the audit log, if the brain has one
+    ///
+    /// `source` is who sent them, and `job` the upload they came from. This
+    /// shouldn't be called with the chain locked, the log might be slow.
+    pub async fn audit<'a, I>(&self, source: &str, job: Option<u64>, lines: I)
+    where
+        I: IntoIterator<Item = (&'a str, std::result::Result<(), &'a Error>)>,
+    {
+        if let Some(audit) = &self.audit {
+            audit.record(source, job, lines).await;
+        }
+    }
+
+    /// Locks the chain, recording how long that took
+    pub async fn lock(&self) -> MutexGuard<'_, Markov> {
+        let now = Instant::now();
+        let markov = self.markov.lock().await;
+        self.metrics.waited(now.elapsed());
+        markov
+    }
+
+    /// Fills in a request's options from the brain's defaults, then applies its
+    /// limits
+    pub fn options(
+        &self,
+        opts: &input::GenerateOptions,
+        defaults: &GenerateDefaults,
+        source: OptionsSource,
+    ) -> Result<Generation> {
+        let config = self.config.generate.clone().unwrap_or_default();
+        config.options(opts, defaults, source)
+    }
+
+    pub async fn generate(
+        &self,
+        opts: &input::GenerateOptions,
+        defaults: &GenerateDefaults,
+        source: OptionsSource,
+    ) -> Result<responses::Generated> {
+        use rand::prelude::*;
+        let Generation { min, max, context } = self.options(opts, defaults, source)?;
+        let data = self
+            .lock()
+            .await
+            .generate(&mut thread_rng(), min, max, context.as_deref());
+
+        match data {
+            Some(data) => Ok(responses::Generated {
+                name: self.config.name.to_string(),
+                data,
+            }),
+            None => {
+                log::warn!(target: "brain", "not enough state");
+                self.metrics.generate_failed();
+                Err(Error::NotEnoughState)
+            }
+        }
+    }
+
+    /// Generates `count` sentences, taking the lock once for all of them
+    pub async fn generate_batch(
+        &self,
+        opts: &input::GenerateOptions,
+        count: usize,
+        defaults: &GenerateDefaults,
+    ) -> Result<responses::GeneratedBatch> {
+        use rand::prelude::*;
+        // batches are only requested with a query
+        let source = OptionsSource::Query;
+        let Generation { min, max, context } = self.options(opts, defaults, source)?;
+
+        let markov = self.lock().await;
+        let mut rng = thread_rng();
+        let results = (0..count)
+            .map(|_| {
+                let data = markov.generate(&mut rng, min, max, context.as_deref());
+                if data.is_none() {
+                    self.metrics.generate_failed();
+                }
+                data.ok_or(Error::NotEnoughState)
+            })
+            .map(responses::BatchItem::from)
+            .collect();
+
+        Ok(responses::GeneratedBatch {
+            name: self.config.name.to_string(),
+            results,
+        })
+    }
+
+    /// Generates a sentence, sending each word as soon as the chain picks it
+    pub async fn stream(
+        &self,
+        opts: &input::GenerateOptions,
+        defaults: &GenerateDefaults,
+    ) -> Result<impl Stream<Item = String>> {
+        // streams are only requested with a query
+        let source = OptionsSource::Query;
+        let Generation { min, max, context } = self.options(opts, defaults, source)?;
+        if self.lock().await.starts.is_empty() {
+            log::warn!(target: "brain", "not enough state");
+            self.metrics.generate_failed();
+            return Err(Error::NotEnoughState);
+        }
+
+        let markov = Arc::clone(&self.markov);
+        let metrics = Arc::clone(&self.metrics);
+
+        // unbounded so a slow reader doesn't keep the brain locked
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            use rand::prelude::*;
+            let now = Instant::now();
+            let markov = markov.lock().await;
+            metrics.waited(now.elapsed());
+            let mut rng = thread_rng();
+            for word in markov.walk(&mut rng, min, max, context.as_deref()) {
+                if tx.send(word).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    pub async fn train(&self, input: input::TrainData, source: &str) -> Result<responses::Trained> {
+        if self.config.read_only {
+            let err = Error::ReadOnly;
+            let line = (input.data.as_str(), Err(&err));
+            self.audit(source, None, std::iter::once(line)).await;
+            return Err(err);
+        }
+
+        let now = Instant::now();
+        self.lock().await.train_text(&input.data);
+        self.metrics
+            .trained(input.data.lines().count(), input.data.len());
+        let line = (input.data.as_str(), Ok(()));
+        self.audit(source, None, std::iter::once(line)).await;
+        Ok(responses::Trained {
+            data: input.data,
+            time: now.elapsed(),
+        })
+    }
+
+    /// Trains with every line, taking the lock once for all of them
+    pub async fn train_batch(
+        &self,
+        input: input::TrainBatch,
+        source: &str,
+    ) -> Result<responses::TrainedBatch> {
+        if self.config.read_only {
+            let err = Error::ReadOnly;
+            let lines = input.data.iter().map(|data| (data.as_str(), Err(&err)));
+            self.audit(source, None, lines).await;
+            return Err(err);
+        }
+
+        let now = Instant::now();
+        let mut markov = self.lock().await;
+        let times = input
+            .data
+            .iter()
+            .map(|data| {
+                if data.trim().is_empty() {
+                    return Err(Error::InvalidBody {
+                        reason: "the line is empty".into(),
+                    });
+                }
+                let start = Instant::now();
+                markov.train_text(data);
+                self.metrics.trained(1, data.len());
+                Ok(start.elapsed())
+            })
+            .collect::<Vec<_>>();
+        drop(markov);
+
+        let lines = input.data.iter().zip(&times);
+        let lines = lines.map(|(data, time)| (data.as_str(), time.as_ref().map(drop)));
+        self.audit(source, None, lines).await;
+
+        let results = input
+            .data
+            .into_iter()
+            .zip(times)
+            .map(|(data, time)| time.map(|time| responses::Trained { data, time }))
+            .map(responses::BatchItem::from)
+            .collect();
+
+        Ok(responses::TrainedBatch {
+            results,
+            time: now.elapsed(),
+        })
+    }
+
+    pub async fn save(&self) -> Result<responses::Saved> {
+        let name = &self.config.brain_file;
+        let (markov, last_saved) = {
+            let mut markov = self.lock().await;
+            let last_saved = markov.mark_saved();
+            (markov.clone(), last_saved)
+        };
+
+        match save_markov(markov, name).await {
+            Ok(time) => {
+                self.metrics.saved(time);
+                Ok(responses::Saved {
+                    name: name.to_string_lossy().to_string(),
+                    time,
+                })
+            }
+            Err(err) => {
+                // it wasn't saved after all
+                let mut markov = self.lock().await;
+                markov.meta.last_saved = last_saved;
+                markov.dirty = true;
+                Err(err)
+            }
+        }
+    }
+
+    /// Describes the brain, along with its metadata
+    pub async fn describe(&self) -> responses::ListItem {
+        let metadata = {
+            let markov = self.lock().await;
+            let meta = &markov.meta;
+            responses::Metadata {
+                depth: markov.depth,
+                created: meta.created,
+                last_trained: meta.last_trained,
+                last_saved: meta.last_saved,
+                lines: meta.lines,
+                source: meta.source.clone(),
+                file_size: None,
+                memory_size: markov.memory_size() as u64,
+                dirty: markov.dirty,
+            }
+        };
+        let file_size = tokio::fs::metadata(&self.config.brain_file)
+            .await
+            .ok()
+            .map(|file| file.len());
+
+        responses::ListItem {
+            metadata: Some(responses::Metadata {
+                file_size,
+                ..metadata
+            }),
+            ..describe(&self.config)
+        }
+    }
+}
+
+fn describe(config: &BrainConfig) -> responses::ListItem {
+    responses::ListItem {
+        name: config.name.clone(),
+        brain_file: config.brain_file.clone(),
+        read_only: config.read_only,
+        metadata: None,
+    }
+}
+
+pub fn failure(config: &BrainConfig, reason: impl ToString) -> responses::Failed {
+    responses::Failed {
+        name: config.name.clone(),
+        brain_file: config.brain_file.clone(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Saves a chain to its brain file, without blocking the async threads
+///
+/// The file it replaces is kept, with a `.bak` extension.
+pub async fn save_markov(markov: Markov, path: &Path) -> Result<Duration> {
+    // TODO pass in options for determining if we should rotate
+    // if the file exists, try rotating it
+    if tokio::fs::metadata(path).await.is_ok() {
+        if let Err(err) = rotate(path).await {
+            return Err(Error::CannotRotate {
+                file: path.to_string_lossy().to_string(),
+                reason: err.to_string(),
+            });
+        }
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let file_name = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let now = Instant::now();
+        let res = markov::save(&markov, file_name).map(|_| now.elapsed());
+        let _ = tx.send(res);
+    });
+
+    // unwrap is for the channel, not the value
+    rx.await.unwrap().map_err(|err| Error::CannotSave {
+        file: path.to_string_lossy().to_string(),
+        reason: err.to_string(),
+    })
+}
+
+async fn rotate(input: &Path) -> std::io::Result<()> {
+    let mut new = input.to_owned();
+    new.set_extension("bak");
+    tokio::fs::rename(&input, new).await
+}
+
+/// A brain was trained
+#[derive(Debug, Clone)]
+pub struct TrainingEvent {
+    pub brain: String,
+    pub trained: responses::Trained,
+}
+
+// slow subscribers miss events rather than holding up training
+const EVENT_CAPACITY: usize = 64;
+
+/// Owns every loaded brain, and keeps the config file in sync with them
+pub struct BrainManager {
+    pub(crate) brains: Mutex<HashMap<String, BrainDb>>,
+    // configured brains that couldn't be loaded
+    pub(crate) failed: Mutex<HashMap<String, responses::Failed>>,
+    // configured brains that haven't been loaded yet
+    pub(crate) loading: Mutex<HashMap<String, BrainConfig>>,
+    pub(crate) config: ConfigManager,
+    pub(crate) settings: ServerConfig,
+    // the server section as the config file had it, without the overrides
+    pub(crate) file_settings: ServerConfig,
+    pub(crate) events: broadcast::Sender<TrainingEvent>,
+    pub(crate) jobs: Jobs,
+    pub(crate) limiter: RateLimiter,
+    pub(crate) metrics: Metrics,
+}
+
+impl BrainManager {
+    pub fn new(config_file: impl Into<PathBuf>, settings: ServerConfig) -> Self {
+        Self {
+            brains: Mutex::new(HashMap::new()),
+            failed: Mutex::new(HashMap::new()),
+            loading: Mutex::new(HashMap::new()),
+            config: ConfigManager::new(config_file),
+            file_settings: settings.clone(),
+            settings,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            jobs: Jobs::new(),
+            limiter: RateLimiter::new(),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Reads the config file, leaving every brain in it to `load_pending`
+    pub async fn load(
+        config_file: impl Into<PathBuf>,
+        overrides: &Overrides,
+    ) -> anyhow::Result<Self> {
+        let loaded = crate::load::load(config_file, overrides).await?;
+        let mut manager = Self::new(loaded.config_file, loaded.server).with_pending(loaded.brains);
+        manager.file_settings = loaded.file_server;
+        Ok(manager)
+    }
+
+    /// Loads every pending brain in parallel, following the startup policy
+    ///
+    /// Each brain can be used as soon as it has been loaded. With a strict
+    /// startup, this fails once they are all done if any of them failed.
+    pub async fn load_pending(&self) -> anyhow::Result<()> {
+        let pending = self
+            .loading
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut results = pending
+            .into_iter()
+            .map(|config| {
+                let copy = config.clone();
+                load_brain(config).map(move |res| (copy, res))
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut failures = 0;
+        while let Some((config, res)) = results.next().await {
+            let mut brains = self.brains.lock().await;
+            // it was removed from the config while it was loading
+            if self.loading.lock().await.remove(&config.name).is_none() {
+                continue;
+            }
+
+            match res {
+                Ok(brain) => {
+                    log::info!(target: "brain", "loaded brain '{}'", config.name);
+                    brains.insert(config.name.clone(), Arc::new(brain.into()));
+                }
+                Err(err) => {
+                    failures += 1;
+                    let failed = failure(&config, err);
+                    self.failed.lock().await.insert(config.name, failed);
+                }
+            }
+        }
+
+        if failures > 0 {
+            match self.settings.startup {
+                Startup::Strict => anyhow::bail!(
+                    "{} brain(s) failed to load. use lenient startup to run without them",
+                    failures
+                ),
+                Startup::Lenient => log::warn!(
+                    target: "brain",
+                    "running without {} brain(s) that failed to load",
+                    failures
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn with_brains<I>(mut self, brains: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Brain>,
+    {
+        let mut map = self.brains.into_inner();
+        for brain in brains {
+            let brain = brain.into();
+            map.insert(brain.config.name.clone(), Arc::new(brain));
+        }
+        self.brains = Mutex::new(map);
+        self
+    }
+
+    /// Adds brains that `load_pending` will load
+    pub fn with_pending(mut self, brains: impl IntoIterator<Item = BrainConfig>) -> Self {
+        let mut map = self.loading.into_inner();
+        for config in brains {
+            map.insert(config.name.clone(), config);
+        }
+        self.loading = Mutex::new(map);
+        self
+    }
+
+    pub fn with_failed(mut self, failed: impl IntoIterator<Item = (BrainConfig, String)>) -> Self {
+        let mut map = self.failed.into_inner();
+        for (config, reason) in failed {
+            map.insert(config.name.clone(), failure(&config, reason));
+        }
+        self.failed = Mutex::new(map);
+        self
+    }
+
+    pub fn settings(&self) -> &ServerConfig {
+        &self.settings
+    }
+
+    pub fn config_path(&self) -> &Path {
+        self.config.path()
+    }
+
+    /// Takes a request from the client's rate limit for `scope`, if it has one
+    pub fn limit_client(&self, scope: Scope, client: &str) -> Result<()> {
+        match self.settings.rate_limits.client.get(scope) {
+            Some(limit) => self.limiter.client(scope, client, limit),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a request from the brain's rate limit for `scope`, if it has one
+    pub fn limit_brain(&self, scope: Scope, brain: &str) -> Result<()> {
+        match self.settings.rate_limits.brain.get(scope) {
+            Some(limit) => self.limiter.brain(scope, brain, limit),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a request to `route`, for the metrics
+    pub fn request(&self, route: &'static str, brain: Option<&str>, status: u16, time: Duration) {
+        self.metrics.request(route, brain, status, time);
+    }
+
+    /// Renders the metrics of every route and loaded brain
+    ///
+    /// The sizes of the brains are counted here, so each one is locked in turn.
+    pub async fn metrics(&self) -> String {
+        let mut brains = self
+            .brains
+            .lock()
+            .await
+            .values()
+            .map(Arc::clone)
+            .collect::<Vec<_>>();
+        brains.sort_by(|l, r| l.config.name.cmp(&r.config.name));
+
+        let mut sizes = Vec::with_capacity(brains.len());
+        for brain in &brains {
+            let markov = brain.markov.lock().await;
+            // every word that is followed by something is a context of its own
+            let words = markov.chain.keys().filter(|key| key.len() == 1).count();
+            sizes.push((markov.chain.len(), words));
+        }
+
+        let samples = brains
+            .iter()
+            .zip(sizes)
+            .map(|(brain, (contexts, words))| BrainSample {
+                name: &brain.config.name,
+                contexts,
+                words,
+                metrics: &brain.metrics,
+            })
+            .collect::<Vec<_>>();
+        self.metrics.render(&samples)
+    }
+
+    pub async fn get(&self, name: &str) -> Option<BrainDb> {
+        self.brains.lock().await.get(name).map(Arc::clone)
+    }
+
+    pub async fn contains(&self, name: &str) -> bool {
+        self.brains.lock().await.contains_key(name)
+    }
+
+    pub async fn is_loading(&self, name: &str) -> bool {
+        self.loading.lock().await.contains_key(name)
+    }
+
+    /// The error for a brain that isn't loaded, `Loading` if it will be
+    pub async fn missing(&self, name: &str) -> Error {
+        if self.is_loading(name).await {
+            Error::Loading { name: name.into() }
+        } else {
+            Error::NotFound { name: name.into() }
+        }
+    }
+
+    /// How many brains have been loaded, and whether any are still loading
+    pub async fn health(&self) -> responses::Health {
+        let loading = self.loading.lock().await.len();
+        responses::Health {
+            ready: loading == 0,
+            loaded: self.brains.lock().await.len(),
+            loading,
+            failed: self.failed.lock().await.len(),
+        }
+    }
+
+    pub async fn generate(
+        &self,
+        name: &str,
+        opts: &input::GenerateOptions,
+        source: OptionsSource,
+    ) -> Result<responses::Generated> {
+        let brain = self.brain(name).await?;
+        brain.generate(opts, &self.settings.defaults, source).await
+    }
+
+    pub async fn stream(
+        &self,
+        name: &str,
+        opts: &input::GenerateOptions,
+    ) -> Result<impl Stream<Item = String>> {
+        let brain = self.brain(name).await?;
+        brain.stream(opts, &self.settings.defaults).await
+    }
+
+    /// Trains `name`, with `source` being who sent it, for the audit log
+    pub async fn train(
+        &self,
+        name: &str,
+        input: input::TrainData,
+        source: &str,
+    ) -> Result<responses::Trained> {
+        let trained = self.brain(name).await?.train(input, source).await?;
+        let event = TrainingEvent {
+            brain: name.into(),
+            trained: trained.clone(),
+        };
+        // this only fails if nobody is subscribed
+        let _ = self.events.send(event);
+        Ok(trained)
+    }
+
+    pub async fn generate_batch(
+        &self,
+        name: &str,
+        opts: &input::GenerateBatchOptions,
+    ) -> Result<responses::GeneratedBatch> {
+        let brain = self.brain(name).await?;
+        let config = brain.config.generate.clone().unwrap_or_default();
+        config.check_batch(opts.count, self.settings.batch_limit)?;
+
+        brain
+            .generate_batch(&opts.options(), opts.count, &self.settings.defaults)
+            .await
+    }
+
+    pub async fn train_batch(
+        &self,
+        name: &str,
+        input: input::TrainBatch,
+        source: &str,
+    ) -> Result<responses::TrainedBatch> {
+        let batch = self.brain(name).await?.train_batch(input, source).await?;
+        for item in &batch.results {
+            if let responses::BatchItem::Ok(trained) = item {
+                let event = TrainingEvent {
+                    brain: name.into(),
+                    trained: trained.clone(),
+                };
+                let _ = self.events.send(event);
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Trains `name` with an upload of any size, in the background
+    pub async fn upload<S, B, E>(
+        &self,
+        name: &str,
+        body: S,
+        gzip: bool,
+        source: &str,
+    ) -> Result<responses::Job>
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Unpin,
+        B: bytes::Buf,
+        E: std::fmt::Display,
+    {
+        let brain = self.brain(name).await?;
+        self.jobs.start(brain, body, gzip, source).await
+    }
+
+    pub async fn job(&self, id: u64) -> Result<responses::Job> {
+        self.jobs.get(id).await
+    }
+
+    pub async fn cancel_job(&self, id: u64) -> Result<responses::Job> {
+        self.jobs.cancel(id).await
+    }
+
+    /// Receives an event whenever any brain is trained
+    pub fn subscribe(&self) -> broadcast::Receiver<TrainingEvent> {
+        self.events.subscribe()
+    }
+
+    pub async fn save(&self, name: &str) -> Result<responses::Saved> {
+        self.brain(name).await?.save().await
+    }
+
+    /// Creates an empty brain, saves it and adds it to the config file
+    ///
+    /// The name and brain file get the same checks as the config file does when
+    /// it is loaded, against every configured brain and not only loaded ones.
+    pub async fn new_brain(
+        &self,
+        name: &str,
+        input: input::NewBrain,
+    ) -> Result<responses::Created> {
+        // the name is part of the path
+        if !types::is_valid_name(name) {
+            return Err(Error::InvalidQuery {
+                reason: format!("'{}' isn't a valid brain name", name),
+            });
+        }
+        if self.contains(name).await || self.is_loading(name).await {
+            return Err(Error::AlreadyExists { name: name.into() });
+        }
+
+        let input::NewBrain { depth, brain_file } = input;
+        let path = PathBuf::from(&brain_file);
+        self.expect_unused_file(&path).await?;
+
+        let config = BrainConfig {
+            name: name.into(),
+            brain_file: path,
+            read_only: false,
+            audit: None,
+            generate: None,
+        };
+        let brain = Arc::new(Brain::new(config, Markov::new(depth, name)));
+        self.add(name, brain, None).await?;
+
+        Ok(responses::Created {
+            name: name.into(),
+            brain_file,
+        })
+    }
+
+    /// Renames a loaded brain, in the config file and in its chain
+    ///
+    /// The chain isn't saved, it is left dirty until the brain is saved next.
+    pub async fn rename(
+        &self,
+        name: &str,
+        input: input::RenameBrain,
+    ) -> Result<responses::Renamed> {
+        let new = input.name;
+        let old = self.brain(name).await?;
+        self.expect_unused(&new).await?;
+
+        let config_updated = self.config.rename_brain(name, &new).await?;
+        let config = BrainConfig {
+            name: new.clone(),
+            ..old.config.clone()
+        };
+        let markov = Arc::clone(&old.markov);
+        let metrics = Arc::clone(&old.metrics);
+        let brain = Brain::with_markov(config, markov, metrics, old.audit.as_ref());
+        {
+            let mut markov = brain.lock().await;
+            markov.name = new.clone();
+            markov.dirty = true;
+        }
+
+        {
+            let mut brains = self.brains.lock().await;
+            brains.remove(name);
+            brains.insert(new.clone(), Arc::new(brain));
+        }
+
+        log::info!(target: "brain", "renamed brain '{}' to '{}'", name, new);
+        Ok(responses::Renamed {
+            from: name.into(),
+            name: new,
+            config_updated,
+        })
+    }
+
+    /// Copies a loaded brain to a new name and file
+    ///
+    /// With a `depth`, the copy is re-derived at that depth, which can't be
+    /// higher than the brain's own. The copy can be trained even when the brain
+    /// is read-only, and doesn't share its audit log.
+    pub async fn copy(&self, name: &str, input: input::CopyBrain) -> Result<responses::Copied> {
+        let input::CopyBrain {
+            name: new,
+            brain_file,
+            depth,
+        } = input;
+        let from = self.brain(name).await?;
+        self.expect_unused(&new).await?;
+
+        let path = PathBuf::from(&brain_file);
+        self.expect_unused_file(&path).await?;
+
+        let mut markov = {
+            let markov = from.lock().await;
+            match depth {
+                Some(depth) => markov.with_depth(depth).ok_or_else(|| Error::InvalidBody {
+                    reason: format!("depth must be from 1 to {}", markov.depth),
+                })?,
+                None => markov.clone(),
+            }
+        };
+        markov.name = new.clone();
+        markov.meta.source = Some(format!("copied from {}", name));
+
+        let config = BrainConfig {
+            name: new.clone(),
+            brain_file: path,
+            read_only: false,
+            audit: None,
+            generate: from.config.generate.clone(),
+        };
+        let depth = markov.depth;
+        let brain = Arc::new(Brain::new(config, markov));
+        self.add(&new, brain, Some(name)).await?;
+
+        log::info!(target: "brain", "copied brain '{}' to '{}'", name, new);
+        Ok(responses::Copied {
+            from: name.into(),
+            name: new,
+            brain_file,
+            depth,
+        })
+    }
+
+    pub async fn list(&self) -> responses::List {
+        // not describing them under the lock, that waits for every chain
+        let loaded = self
+            .brains
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut brains = HashMap::new();
+        for brain in loaded {
+            brains.insert(brain.config.name.clone(), brain.describe().await);
+        }
+
+        let loading = self
+            .loading
+            .lock()
+            .await
+            .iter()
+            .map(|(k, v)| (k.clone(), describe(v)))
+            .collect();
+
+        responses::List {
+            brains,
+            failed: self.failed.lock().await.clone(),
+            loading,
+            config_path: self.config.path().to_path_buf(),
+        }
+    }
+
+    /// Unloads the brain, then deletes its file and removes it from the config file
+    ///
+    /// Once the brain is unloaded, a file or config file that can't be updated
+    /// doesn't stop the rest, and is reported in `errors`.
+    pub async fn delete(
+        &self,
+        name: &str,
+        opts: &input::DeleteOptions,
+    ) -> Result<responses::Deleted> {
+        let brain = self.brains.lock().await.remove(name);
+        let brain = match brain {
+            Some(brain) => brain,
+            None => return Err(self.missing(name).await),
+        };
+
+        let mut errors = vec![];
+        let brain_file = brain.config.brain_file.clone();
+        let mut file_deleted = false;
+        if opts.delete_file.unwrap_or(false) {
+            match tokio::fs::remove_file(&brain_file).await {
+                Ok(..) => file_deleted = true,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => errors.push(Error::CannotDelete {
+                    file: brain_file.to_string_lossy().to_string(),
+                    reason: err.to_string(),
+                }),
+            }
+        }
+
+        let mut config_updated = false;
+        if !opts.keep_config.unwrap_or(false) {
+            match self.config.remove_brain(name).await {
+                Ok(updated) => config_updated = updated,
+                Err(err) => errors.push(err),
+            }
+        }
+
+        for err in &errors {
+            log::error!(target: "brain", "cannot finish deleting brain '{}': {:?}", name, err);
+        }
+        log::info!(target: "brain", "deleted brain '{}'", name);
+        Ok(responses::Deleted {
+            name: name.into(),
+            brain_file,
+            file_deleted,
+            config_updated,
+            errors,
+        })
+    }
+
+    /// Unloads the brain, saving it first if it has unsaved training
+    ///
+    /// If it can't be saved, it stays loaded.
+    pub async fn unload(&self, name: &str) -> Result<responses::Unloaded> {
+        let brain = self.brains.lock().await.remove(name);
+        let brain = match brain {
+            Some(brain) => brain,
+            None => return Err(self.missing(name).await),
+        };
+
+        if !brain.config.read_only && brain.lock().await.dirty {
+            if let Err(err) = brain.save().await {
+                self.brains.lock().await.entry(name.into()).or_insert(brain);
+                return Err(err);
+            }
+        }
+
+        log::info!(target: "brain", "unloaded brain '{}'", name);
+        Ok(responses::Unloaded { name: name.into() })
+    }
+
+    pub async fn reload(&self, name: &str) -> Result<responses::Reloaded> {
+        if self.is_loading(name).await {
+            return Err(Error::Loading { name: name.into() });
+        }
+
+        // the config file is the source of truth, so an unloaded brain can be brought back
+        let mut config = self.config.load().await?;
+        let mut config = config
+            .brains
+            .remove(name)
+            .ok_or_else(|| Error::NotFound { name: name.into() })?;
+        config.name = name.into();
+
+        let now = Instant::now();
+        let brain_file = config.brain_file.clone();
+        let brain = match load_brain(config.clone()).await {
+            Ok(brain) => Arc::new(Brain::from(brain)),
+            Err(err) => {
+                if !self.contains(name).await {
+                    let failed = failure(&config, &err);
+                    self.failed.lock().await.insert(name.into(), failed);
+                }
+                return Err(Error::CannotLoad {
+                    file: brain_file.to_string_lossy().to_string(),
+                    reason: err.to_string(),
+                });
+            }
+        };
+
+        self.brains.lock().await.insert(name.into(), brain);
+        self.failed.lock().await.remove(name);
+
+        log::info!(target: "brain", "reloaded brain '{}'", name);
+        Ok(responses::Reloaded {
+            name: name.into(),
+            brain_file,
+            time: now.elapsed(),
+        })
+    }
+
+    /// Re-reads the config file and applies the differences to the loaded brains
+    ///
+    /// Brains missing from the config are unloaded, new ones are loaded, and
+    /// changed options are applied in place. A changed brain file is loaded from
+    /// scratch, keeping the old brain if that fails. Brains that are still
+    /// loading are left alone, unless they were removed.
+    ///
+    /// Like `unload`, brains that are unloaded or replaced are saved first if
+    /// they have unsaved training, and are kept as they are if that fails.
+    pub async fn reload_config(&self) -> Result<responses::ConfigReloaded> {
+        let config = self.config.load().await?;
+        if self.restart_required(&config.server) {
+            log::warn!(target: "brain", "server settings changed, restart to apply them");
+        }
+
+        let mut report = responses::ConfigReloaded::default();
+        let mut pending = vec![];
+        // loaded brains that are removed or replaced
+        let mut dropped = vec![];
+
+        let removed = {
+            let mut brains = self.brains.lock().await;
+
+            let removed = brains
+                .keys()
+                .filter(|name| !config.brains.contains_key(*name))
+                .cloned()
+                .collect::<Vec<_>>();
+            dropped.extend(removed.iter().map(|name| Arc::clone(&brains[name])));
+
+            // the ones still in the config are left to finish loading
+            let mut loading = self.loading.lock().await;
+            let stopped = loading
+                .keys()
+                .filter(|name| !config.brains.contains_key(*name))
+                .cloned()
+                .collect::<Vec<_>>();
+            for name in stopped {
+                loading.remove(&name);
+                report.removed.push(name);
+            }
+
+            self.failed
+                .lock()
+                .await
+                .retain(|name, _| config.brains.contains_key(name));
+
+            for (name, mut new) in config.brains {
+                if loading.contains_key(&name) {
+                    continue;
+                }
+                new.name = name.clone();
+                let existing = brains.get(&name).cloned();
+
+                match existing {
+                    Some(old) if old.config == new => {}
+                    Some(old) if old.config.brain_file == new.brain_file => {
+                        let markov = Arc::clone(&old.markov);
+                        let metrics = Arc::clone(&old.metrics);
+                        let brain = Brain::with_markov(new, markov, metrics, old.audit.as_ref());
+                        brains.insert(name.clone(), Arc::new(brain));
+                        report.updated.push(name);
+                    }
+                    old => {
+                        dropped.extend(old);
+                        pending.push(new);
+                    }
+                }
+            }
+            removed
+        };
+
+        for brain in dropped {
+            if brain.config.read_only || !brain.lock().await.dirty {
+                continue;
+            }
+            if let Err(err) = brain.save().await {
+                let name = brain.config.name.clone();
+                log::error!(target: "brain", "cannot save '{}', keeping it: {:?}", name, err);
+                report.unsaved.insert(name, err);
+            }
+        }
+
+        {
+            let mut brains = self.brains.lock().await;
+            for name in removed {
+                if !report.unsaved.contains_key(&name) {
+                    brains.remove(&name);
+                    report.removed.push(name);
+                }
+            }
+        }
+        pending.retain(|config| !report.unsaved.contains_key(&config.name));
+
+        let pending = pending
+            .into_iter()
+            .map(|config| (config.name.clone(), config));
+        let (loaded, failed) = load_brains(pending).await;
+
+        let mut brains = self.brains.lock().await;
+        let mut failures = self.failed.lock().await;
+        for brain in loaded {
+            let name = brain.config.name.clone();
+            failures.remove(&name);
+            match brains.insert(name.clone(), Arc::new(brain.into())) {
+                Some(..) => report.updated.push(name),
+                None => report.added.push(name),
+            }
+        }
+
+        for (config, reason) in failed {
+            if !brains.contains_key(&config.name) {
+                failures.insert(config.name.clone(), failure(&config, &reason));
+            }
+            report.failed.insert(config.name, reason);
+        }
+
+        report.added.sort();
+        report.removed.sort();
+        report.updated.sort();
+
+        log::info!(
+            target: "brain",
+            "reloaded config. added: {:?}, removed: {:?}, updated: {:?}, failed: {:?}, unsaved: {:?}",
+            report.added,
+            report.removed,
+            report.updated,
+            report.failed.keys().collect::<Vec<_>>(),
+            report.unsaved.keys().collect::<Vec<_>>(),
+        );
+        Ok(report)
+    }
+
+    /// Whether the config file's server section changed since it was loaded
+    ///
+    /// It is compared without the overrides, which only apply at startup.
+    pub(crate) fn restart_required(&self, server: &ServerConfig) -> bool {
+        *server != self.file_settings
+    }
+
+    async fn brain(&self, name: &str) -> Result<BrainDb> {
+        match self.get(name).await {
+            Some(brain) => Ok(brain),
+            None => Err(self.missing(name).await),
+        }
+    }
+
+    // the brain file of a brain that doesn't exist yet
+    //
+    // the config file is checked when the brain is added to it, this also
+    // covers brains that aren't in it
+    async fn expect_unused_file(&self, path: &Path) -> Result<()> {
+        let brains = self.brains.lock().await;
+        let mut configs = brains
+            .values()
+            .map(|brain| brain.config.clone())
+            .collect::<Vec<_>>();
+        drop(brains);
+        configs.extend(self.loading.lock().await.values().cloned());
+        match configs.iter().find(|config| config.brain_file == path) {
+            Some(other) => Err(Error::InvalidBody {
+                reason: format!("'{}' is the brain file of '{}'", path.display(), other.name),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // adds a new brain to the config file, copying the config of `from`, then
+    // saves it
+    //
+    // the config file is updated first, so a name or file it already has is
+    // refused before anything is written
+    async fn add(&self, name: &str, brain: BrainDb, from: Option<&str>) -> Result<()> {
+        match from {
+            Some(from) => self.config.copy_brain(from, &brain.config).await?,
+            None => self.config.add_brain(&brain.config).await?,
+        }
+        if let Err(err) = brain.save().await {
+            if let Err(err) = self.config.remove_brain(name).await {
+                log::error!(target: "brain", "cannot remove '{}' from the config: {:?}", name, err);
+            }
+            return Err(err);
+        }
+        self.brains.lock().await.insert(name.into(), brain);
+        Ok(())
+    }
+
+    // a name given in a body, for a brain that doesn't exist yet
+    async fn expect_unused(&self, name: &str) -> Result<()> {
+        if !types::is_valid_name(name) {
+            return Err(Error::InvalidBody {
+                reason: format!("'{}' isn't a valid brain name", name),
+            });
+        }
+        if self.contains(name).await || self.is_loading(name).await {
+            return Err(Error::AlreadyExists { name: name.into() });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for BrainManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BrainManager")
+            .field("config", &self.config)
+            .finish()
+    }
+}
@@ -0,0 +1,280 @@
+//! Counters and histograms for the `/metrics` route, in the Prometheus text format
+//!
+//! Requests are counted for each route. Everything else is kept for each brain,
+//! in the brain's `BrainMetrics`.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// upper bounds, in seconds
+const BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A Prometheus histogram of durations
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&mut self, time: Duration) {
+        let secs = time.as_secs_f64();
+        for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        for (count, bound) in self.counts.iter().zip(BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// What happened to one brain since it was loaded
+#[derive(Debug, Default)]
+pub struct BrainMetrics {
+    generate_failures: AtomicU64,
+    trained_lines: AtomicU64,
+    trained_bytes: AtomicU64,
+    // unix time in seconds, 0 when it hasn't been saved
+    last_save: AtomicU64,
+    lock_wait: Mutex<Histogram>,
+    saves: Mutex<Histogram>,
+}
+
+impl BrainMetrics {
+    /// A sentence couldn't be generated, because there wasn't enough state
+    pub fn generate_failed(&self) {
+        self.generate_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn trained(&self, lines: usize, bytes: usize) {
+        self.trained_lines
+            .fetch_add(lines as u64, Ordering::Relaxed);
+        self.trained_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Waited this long for the chain's lock
+    pub fn waited(&self, time: Duration) {
+        self.lock_wait.lock().unwrap().observe(time);
+    }
+
+    /// Was saved, taking this long
+    pub fn saved(&self, time: Duration) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        self.last_save.store(now, Ordering::Relaxed);
+        self.saves.lock().unwrap().observe(time);
+    }
+}
+
+/// A brain's metrics, with its size when it was scraped
+#[derive(Debug)]
+pub struct BrainSample<'a> {
+    pub name: &'a str,
+    pub contexts: usize,
+    pub words: usize,
+    pub metrics: &'a BrainMetrics,
+}
+
+#[derive(Debug, Default)]
+struct RouteMetrics {
+    statuses: BTreeMap<u16, u64>,
+    latency: Histogram,
+}
+
+/// The requests made to every route
+#[derive(Debug, Default)]
+pub struct Metrics {
+    // keyed by route and brain, the brain is empty when there isn't one
+    routes: Mutex<BTreeMap<(&'static str, String), RouteMetrics>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request(&self, route: &'static str, brain: Option<&str>, status: u16, time: Duration) {
+        let key = (route, brain.unwrap_or_default().to_string());
+        let mut routes = self.routes.lock().unwrap();
+        let entry = routes.entry(key).or_default();
+        *entry.statuses.entry(status).or_default() += 1;
+        entry.latency.observe(time);
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub fn render(&self, brains: &[BrainSample<'_>]) -> String {
+        let mut out = String::new();
+
+        let routes = self.routes.lock().unwrap();
+        let name = "brain_http_requests_total";
+        header(
+            &mut out,
+            name,
+            "counter",
+            "requests by route, brain and status",
+        );
+        for ((route, brain), metrics) in routes.iter() {
+            for (status, count) in &metrics.statuses {
+                let labels = format!(
+                    "route=\"{}\",brain=\"{}\",status=\"{}\"",
+                    route,
+                    escape(brain),
+                    status
+                );
+                sample(&mut out, name, &labels, count);
+            }
+        }
+
+        let name = "brain_http_request_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "time taken to reply, by route and brain",
+        );
+        for ((route, brain), metrics) in routes.iter() {
+            let labels = format!("route=\"{}\",brain=\"{}\"", route, escape(brain));
+            metrics.latency.write(&mut out, name, &labels);
+        }
+        drop(routes);
+
+        let brains = brains
+            .iter()
+            .map(|brain| (format!("brain=\"{}\"", escape(brain.name)), brain))
+            .collect::<Vec<_>>();
+
+        let relaxed = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        family(
+            &mut out,
+            &brains,
+            ("brain_generate_failures_total", "counter"),
+            "sentences that couldn't be generated, for a lack of state",
+            |b| relaxed(&b.metrics.generate_failures),
+        );
+        family(
+            &mut out,
+            &brains,
+            ("brain_trained_lines_total", "counter"),
+            "lines trained",
+            |b| relaxed(&b.metrics.trained_lines),
+        );
+        family(
+            &mut out,
+            &brains,
+            ("brain_trained_bytes_total", "counter"),
+            "bytes trained",
+            |b| relaxed(&b.metrics.trained_bytes),
+        );
+        family(
+            &mut out,
+            &brains,
+            ("brain_contexts", "gauge"),
+            "contexts in the chain",
+            |b| b.contexts,
+        );
+        family(
+            &mut out,
+            &brains,
+            ("brain_words", "gauge"),
+            "distinct words in the chain",
+            |b| b.words,
+        );
+        family(
+            &mut out,
+            &brains,
+            ("brain_last_save_timestamp_seconds", "gauge"),
+            "unix time of the last save, 0 if it hasn't been saved",
+            |b| relaxed(&b.metrics.last_save),
+        );
+
+        let name = "brain_lock_wait_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "time spent waiting for the chain's lock",
+        );
+        for (labels, brain) in &brains {
+            let histogram = brain.metrics.lock_wait.lock().unwrap().clone();
+            histogram.write(&mut out, name, labels);
+        }
+
+        let name = "brain_save_duration_seconds";
+        header(&mut out, name, "histogram", "time taken to save");
+        for (labels, brain) in &brains {
+            let histogram = brain.metrics.saves.lock().unwrap().clone();
+            histogram.write(&mut out, name, labels);
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// one sample for each brain
+fn family<T>(
+    out: &mut String,
+    brains: &[(String, &BrainSample<'_>)],
+    (name, kind): (&str, &str),
+    help: &str,
+    value: impl Fn(&BrainSample<'_>) -> T,
+) where
+    T: std::fmt::Display,
+{
+    header(out, name, kind, help);
+    for (labels, brain) in brains {
+        sample(out, name, labels, value(brain));
+    }
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+}
+
+// label values can't have a bare backslash, quote or newline
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,102 @@
+use super::{models::Error, rejection};
+use crate::config::CorsConfig;
+
+use std::sync::Arc;
+use warp::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use warp::{Rejection, Reply};
+
+// every method and header the routes use
+const ALLOW_METHODS: &str = "GET, POST, PUT, DELETE";
+const ALLOW_HEADERS: &str = "authorization, content-type";
+
+// so rate limited browsers know how long to wait
+const EXPOSE_HEADERS: &str = "retry-after";
+
+/// The CORS policy, which allows no origins unless it was configured
+#[derive(Clone)]
+pub struct Cors {
+    config: Option<Arc<CorsConfig>>,
+}
+
+impl Cors {
+    pub fn new(config: Option<&CorsConfig>) -> Self {
+        Self {
+            config: config.cloned().map(Arc::new),
+        }
+    }
+
+    /// Adds the CORS headers to a response, when its request came from an
+    /// allowed origin
+    ///
+    /// Requests from other origins are still answered, it is up to browsers
+    /// to keep the responses from them.
+    pub fn apply(&self, headers: &HeaderMap, reply: impl Reply) -> warp::reply::Response {
+        let mut resp = reply.into_response();
+        if self.config.is_none() {
+            return resp;
+        }
+
+        let resp_headers = resp.headers_mut();
+        resp_headers.append(header::VARY, HeaderValue::from_static("origin"));
+        if let Some(origin) = self.allowed(headers) {
+            resp_headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            resp_headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_static(EXPOSE_HEADERS),
+            );
+        }
+        resp
+    }
+
+    /// Answers a preflight request
+    ///
+    /// Other requests, even other `OPTIONS` requests, are rejected as not
+    /// found so they are left to the routes.
+    pub fn preflight(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+    ) -> Result<warp::reply::Response, Rejection> {
+        if method != Method::OPTIONS
+            || !headers.contains_key(header::ORIGIN)
+            || !headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return Err(warp::reject::not_found());
+        }
+
+        let max_age = match (&self.config, self.allowed(headers)) {
+            (Some(config), Some(..)) => config.max_age,
+            _ => {
+                return Err(rejection(Error::Forbidden {
+                    reason: "the origin isn't allowed".into(),
+                }))
+            }
+        };
+
+        let mut resp = StatusCode::NO_CONTENT.into_response();
+        let resp_headers = resp.headers_mut();
+        resp_headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(ALLOW_METHODS),
+        );
+        resp_headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(ALLOW_HEADERS),
+        );
+        resp_headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+        Ok(resp)
+    }
+
+    // the value for the allow-origin header
+    fn allowed(&self, headers: &HeaderMap) -> Option<HeaderValue> {
+        let config = self.config.as_ref()?;
+        let origin = headers.get(header::ORIGIN)?;
+        if config.origins.iter().any(|allowed| allowed == "*") {
+            return Some(HeaderValue::from_static("*"));
+        }
+        if config.allows(origin.to_str().ok()?) {
+            return Some(origin.clone());
+        }
+        None
+    }
+}
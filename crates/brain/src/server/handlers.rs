@@ -1,143 +1,174 @@
-use super::models::{self, Error};
-use super::server::{Brain, BrainDb, Topics};
-use super::{error, okay, rotate};
-use crate::config::{BrainConfig, Config};
-
+use super::models::{self, generate::OptionsSource};
+use super::{accepted, is_gzip, okay, rejection, reply, Caller};
+use crate::config::GenerateDefaults;
+use crate::{BrainDb, BrainManager};
+
+use bytes::Buf;
+use futures::{Stream, StreamExt as _};
+use std::convert::Infallible;
 use std::sync::Arc;
-use warp::Reply;
+use warp::{http::StatusCode, sse, Reply};
 
 type Result<R> = std::result::Result<R, warp::Rejection>;
 
-pub async fn generate(db: BrainDb, opts: models::input::GenerateOptions) -> Result<impl Reply> {
-    use rand::prelude::*;
-    let data = db.markov.lock().await.generate(
-        &mut thread_rng(),
-        opts.min.unwrap_or(5),
-        opts.max.unwrap_or(30),
-        opts.context.as_ref().map(|s| s.as_str()),
-    );
-
-    match data {
-        Some(data) => okay(models::responses::Generated {
-            name: db.config.name.to_string(),
-            data: data.to_string(),
-        }),
-        None => {
-            log::warn!(target: "brain", "not enough state");
-            error(Error::NotEnoughState)
-        }
-    }
-}
-
-pub async fn train(db: BrainDb, input: models::input::TrainData) -> Result<impl Reply> {
-    if db.config.read_only {
-        return error(Error::ReadOnly);
-    }
-
-    let now = std::time::Instant::now();
-    db.markov.lock().await.train_text(&input.data);
-    okay(models::responses::Trained {
-        data: input.data,
-        time: now.elapsed(),
-    })
+pub async fn generate(
+    db: BrainDb,
+    opts: models::input::GenerateOptions,
+    defaults: GenerateDefaults,
+    source: OptionsSource,
+) -> Result<impl Reply> {
+    reply(db.generate(&opts, &defaults, source).await)
+}
+
+pub async fn generate_batch(
+    (manager, name): (Arc<BrainManager>, String),
+    opts: models::input::GenerateBatchOptions,
+) -> Result<impl Reply> {
+    reply(manager.generate_batch(&name, &opts).await)
+}
+
+/// Sends each word as a `token` event, followed by a `done` event
+pub async fn stream(
+    db: BrainDb,
+    opts: models::input::GenerateOptions,
+    defaults: GenerateDefaults,
+) -> Result<impl Reply> {
+    let words = db.stream(&opts, &defaults).await.map_err(rejection)?;
+    let done = futures::stream::once(async { (sse::event("done"), sse::data(String::new())) });
+    let events = words
+        .map(|word| (sse::event("token"), sse::data(word)))
+        .chain(done)
+        .map(Ok::<_, Infallible>);
+    Ok(sse::reply(events))
+}
+
+pub async fn train(
+    (manager, name): (Arc<BrainManager>, String),
+    source: String,
+    input: models::input::TrainData,
+) -> Result<impl Reply> {
+    // through the manager, so subscribers hear about it
+    reply(manager.train(&name, input, &source).await)
+}
+
+pub async fn train_batch(
+    (manager, name): (Arc<BrainManager>, String),
+    source: String,
+    input: models::input::TrainBatch,
+) -> Result<impl Reply> {
+    reply(manager.train_batch(&name, input, &source).await)
+}
+
+/// Starts a training job with the upload, replying once it has been received
+pub async fn upload(
+    (manager, name): (Arc<BrainManager>, String),
+    source: String,
+    content_type: Option<String>,
+    encoding: Option<String>,
+    body: impl Stream<Item = std::result::Result<impl Buf, warp::Error>>,
+) -> Result<impl Reply> {
+    let gzip = is_gzip(content_type.as_deref(), encoding.as_deref()).map_err(rejection)?;
+    accepted(manager.upload(&name, Box::pin(body), gzip, &source).await)
+}
+
+pub async fn job((manager, id): (Arc<BrainManager>, u64), caller: Caller) -> Result<impl Reply> {
+    let job = manager.job(id).await.map_err(rejection)?;
+    caller.grant.brain(&job.brain).map_err(rejection)?;
+    okay(job)
+}
+
+pub async fn cancel_job(
+    (manager, id): (Arc<BrainManager>, u64),
+    caller: Caller,
+) -> Result<impl Reply> {
+    let job = manager.job(id).await.map_err(rejection)?;
+    caller.grant.brain(&job.brain).map_err(rejection)?;
+    reply(manager.cancel_job(id).await)
 }
 
 pub async fn new(
-    (topics, name): (Arc<Topics>, String),
+    (manager, name): (Arc<BrainManager>, String),
     input: models::input::NewBrain,
 ) -> Result<impl Reply> {
-    use tokio::io::AsyncWriteExt as _;
-    let models::input::NewBrain { depth, brain_file } = input;
-    let brain = Brain {
-        config: BrainConfig {
-            name: name.clone(),
-            brain_file: brain_file.clone().into(),
-            read_only: false,
-        },
-        markov: tokio::sync::Mutex::new(markov::Markov::new(depth, &name)),
-    };
+    reply(manager.new_brain(&name, input).await)
+}
 
-    let brain = std::sync::Arc::new(brain);
-    save(Arc::clone(&brain)).await?;
+pub async fn rename(
+    (manager, name): (Arc<BrainManager>, String),
+    input: models::input::RenameBrain,
+) -> Result<impl Reply> {
+    reply(manager.rename(&name, input).await)
+}
 
-    let mut config = Config::default();
-    config.brains.insert(name.clone(), brain.config.clone());
+pub async fn copy(
+    (manager, name): (Arc<BrainManager>, String),
+    input: models::input::CopyBrain,
+) -> Result<impl Reply> {
+    reply(manager.copy(&name, input).await)
+}
 
-    let data = format!(
-        "\n# generated from <user_input>\n# depth: {}\n{}",
-        depth,
-        toml::to_string_pretty(&config).unwrap()
-    );
+pub async fn save(db: BrainDb) -> Result<impl Reply> {
+    reply(db.save().await)
+}
 
-    // TODO handle this better
-    // notably the errors, reject any changes
-    tokio::fs::OpenOptions::new()
-        .write(true)
-        .append(true)
-        .open(&topics.config_path)
-        .await
-        .expect("must be able to open brain.toml")
-        .write_all(data.as_bytes())
-        .await
-        .expect("must be able to append to brain.toml");
+/// Describes the brain, with its metadata
+pub async fn info(db: BrainDb) -> Result<impl Reply> {
+    okay(db.describe().await)
+}
 
-    topics.brains.lock().await.insert(name.clone(), brain);
+/// Lists the brains the token can use
+pub async fn list(manager: Arc<BrainManager>, caller: Caller) -> Result<impl Reply> {
+    let mut list = manager.list().await;
+    list.brains
+        .retain(|name, _| caller.grant.brain(name).is_ok());
+    list.failed
+        .retain(|name, _| caller.grant.brain(name).is_ok());
+    list.loading
+        .retain(|name, _| caller.grant.brain(name).is_ok());
+    okay(list)
+}
 
-    okay(models::responses::Created { name, brain_file })
+pub async fn delete(
+    (manager, name): (Arc<BrainManager>, String),
+    opts: models::input::DeleteOptions,
+) -> Result<impl Reply> {
+    reply(manager.delete(&name, &opts).await)
 }
 
-pub async fn save(db: BrainDb) -> Result<impl Reply> {
-    let name = &db.config.brain_file;
-
-    // TODO pass in options for determining if we should rotate
-    // if the file exists, try rotating it
-    if tokio::fs::metadata(&name).await.is_ok() {
-        if let Err(err) = rotate(&name).await {
-            return error(Error::CannotRotate {
-                file: name.to_string_lossy().to_string(),
-                reason: err.to_string(),
-            });
-        }
-    }
-
-    let markov = db.markov.lock().await.clone();
-    let (tx, rx) = tokio::sync::oneshot::channel();
-    let file_name = name.clone();
-
-    tokio::task::spawn_blocking(move || {
-        let now = std::time::Instant::now();
-        let res = markov::save(&markov, file_name).map(|_| now.elapsed());
-        let _ = tx.send(res);
-    });
-
-    // unwrap is for the channel, not the value
-    match rx.await.unwrap() {
-        Ok(time) => okay(models::responses::Saved {
-            name: name.to_string_lossy().to_string(),
-            time,
-        }),
-        Err(err) => error(Error::CannotSave {
-            file: name.to_string_lossy().to_string(),
-            reason: err.to_string(),
-        }),
-    }
-}
-
-pub async fn list(topics: Arc<Topics>) -> Result<impl Reply> {
-    let mut brains = hashbrown::HashMap::new();
-
-    for (k, v) in &*topics.brains.lock().await {
-        brains.insert(
-            k.clone(),
-            models::responses::ListItem {
-                name: k.clone(),
-                brain_file: v.config.brain_file.clone().into(),
-                read_only: v.config.read_only,
-            },
-        );
-    }
-    okay(models::responses::List {
-        brains,
-        config_path: topics.config_path.clone().into(),
-    })
+pub async fn unload((manager, name): (Arc<BrainManager>, String)) -> Result<impl Reply> {
+    reply(manager.unload(&name).await)
+}
+
+pub async fn reload((manager, name): (Arc<BrainManager>, String)) -> Result<impl Reply> {
+    reply(manager.reload(&name).await)
+}
+
+pub async fn reload_config(manager: Arc<BrainManager>) -> Result<impl Reply> {
+    reply(manager.reload_config().await)
+}
+
+pub async fn healthz(manager: Arc<BrainManager>) -> Result<impl Reply> {
+    okay(manager.health().await)
+}
+
+/// Replies with `503 Service Unavailable` until every brain is done loading
+pub async fn readyz(manager: Arc<BrainManager>) -> Result<impl Reply> {
+    let health = manager.health().await;
+    let status = if health.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&health), status))
+}
+
+/// The metrics, in the Prometheus text format
+pub async fn metrics(manager: Arc<BrainManager>, _: Caller) -> Result<impl Reply> {
+    let metrics = manager.metrics().await;
+    let content_type = "text/plain; version=0.0.4";
+    Ok(warp::reply::with_header(
+        metrics,
+        "content-type",
+        content_type,
+    ))
 }
@@ -1,10 +1,14 @@
 mod util;
 use util::*;
 
+mod cors;
 mod handlers;
 mod routes;
+mod socket;
 
 mod server;
+#[cfg(feature = "tls")]
+mod tls;
 
 pub use server::Server;
 
@@ -1,50 +1,611 @@
-use super::server::Topics;
-use super::{expect_unique, filter, handlers, json_body, recover};
+//! The `v1` routes, and their legacy unversioned aliases
+//!
+//! | route                                      | legacy alias                     |
+//! | ------------------------------------------ | -------------------------------- |
+//! | `GET    /v1/brains`                        | `GET    /list`                   |
+//! | `POST   /v1/brains/{name}`                 | `POST   /new/{name}`             |
+//! | `GET    /v1/brains/{name}`                 | `GET    /brain/{name}`           |
+//! | `DELETE /v1/brains/{name}`                 | `DELETE /brain/{name}`           |
+//! | `GET    /v1/brains/{name}/generate`        | `GET    /generate/{name}`        |
+//! | `POST   /v1/brains/{name}/generate`        |                                  |
+//! | `GET    /v1/brains/{name}/generate/stream` | `GET    /generate/{name}/stream` |
+//! | `GET    /v1/brains/{name}/generate/batch`  | `GET    /generate/{name}/batch`  |
+//! | `POST   /v1/brains/{name}/train`           | `POST   /train/{name}`           |
+//! | `POST   /v1/brains/{name}/train/batch`     | `POST   /train/{name}/batch`     |
+//! | `POST   /v1/brains/{name}/upload`          | `POST   /upload/{name}`          |
+//! | `POST   /v1/brains/{name}/save`            | `PUT    /save/{name}`            |
+//! | `POST   /v1/brains/{name}/reload`          | `POST   /reload/{name}`          |
+//! | `POST   /v1/brains/{name}/unload`          | `POST   /unload/{name}`          |
+//! | `POST   /v1/brains/{name}/rename`          |                                  |
+//! | `POST   /v1/brains/{name}/copy`            |                                  |
+//! | `POST   /v1/config/reload`                 | `POST   /reload-config`          |
+//! | `GET    /v1/jobs/{id}`                     | `GET    /jobs/{id}`              |
+//! | `DELETE /v1/jobs/{id}`                     | `DELETE /jobs/{id}`              |
+//! | `GET    /v1/ws`                            |                                  |
+//! | `GET    /v1/metrics`                       | `GET    /metrics`                |
+//! | `GET    /v1/healthz`                       | `GET    /healthz`                |
+//! | `GET    /v1/readyz`                        | `GET    /readyz`                 |
+//! | `GET    /v1/openapi.json`                  |                                  |
+//!
+//! When tokens are configured, every route but the OpenAPI description and the
+//! health checks needs a bearer token. Generating needs the `generate` scope, training and jobs need
+//! `train`, saving needs `save`, and the rest need `admin`. Listing only needs
+//! a token, and lists the brains the token can use. The metrics need `admin`
+//! too, as they name every brain. Renaming or copying a brain also needs the
+//! token to be allowed to use the new name.
+//!
+//! Brains are loaded in the background, so the server can be reached right
+//! away. `readyz` replies with `503 Service Unavailable` until every brain has
+//! been loaded or failed to load, and requests for a brain that is still
+//! loading get a `loading` error.
+//!
+//! Every request is counted in the metrics, under the `operationId` of its
+//! route. Only successful requests are counted under their brain, so made up
+//! names don't add to the metrics.
+//!
+//! With `server.access_log` configured, every request is also written to the
+//! access log as a line of JSON, with its timing and status. Brains with an
+//! `audit` log record every line they are trained with or refuse, along with
+//! who sent it.
+//!
+//! Routes with a scope also take a request from the rate limits of the caller
+//! and of the brain for that scope, when they are configured.
+//!
+//! With `server.cors` configured, responses to allowed origins get CORS
+//! headers, and `OPTIONS` preflight requests are answered for every route.
+//! Preflights from other origins get `403 Forbidden`. Browsers can't send a
+//! body with a `GET`, so `POST` to `generate` takes the options as JSON.
+//!
+//! The OpenAPI description in `openapi.json` is checked against the `types`
+//! crate by the tests.
+use super::models::generate::OptionsSource;
+use super::{
+    allowed, allowed_both, authorize, batch_body, cors::Cors, expect_existing, expect_unique,
+    filter, handlers, json_body, models, recover, recover_all, remote, socket, source, Caller,
+};
+use crate::config::Scope;
+use crate::logfile::{self, Access, LogFile};
+use crate::BrainManager;
 
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use warp::{Filter, Rejection, Reply};
+use std::time::Instant;
+use warp::http::{header, HeaderMap, Method};
+use warp::{path::FullPath, ws::Ws, Filter, Rejection, Reply};
+
+/// The OpenAPI description of the `v1` routes
+pub const OPENAPI: &str = include_str!("../../openapi.json");
+
+/// Every route, with the rejections turned into JSON errors
+pub fn api(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+    let counter = Arc::clone(&manager);
+    let access = manager
+        .settings()
+        .access_log
+        .as_ref()
+        .map(|config| Arc::new(LogFile::new(config.clone())));
+    let cors = Cors::new(manager.settings().cors.as_ref());
+
+    let routes = generate(Arc::clone(&manager))
+        .or(generate_json(Arc::clone(&manager)))
+        .or(stream(Arc::clone(&manager)))
+        .or(generate_batch(Arc::clone(&manager)))
+        .or(save(Arc::clone(&manager)))
+        .or(train(Arc::clone(&manager)))
+        .or(train_batch(Arc::clone(&manager)))
+        .or(upload(Arc::clone(&manager)))
+        .or(new(Arc::clone(&manager)))
+        .or(rename(Arc::clone(&manager)))
+        .or(copy(Arc::clone(&manager)))
+        .or(list(Arc::clone(&manager)))
+        .or(info(Arc::clone(&manager)))
+        .or(delete(Arc::clone(&manager)))
+        .or(reload(Arc::clone(&manager)))
+        .or(unload(Arc::clone(&manager)))
+        .or(reload_config(Arc::clone(&manager)))
+        .or(job(Arc::clone(&manager)))
+        .or(cancel_job(Arc::clone(&manager)))
+        .or(websocket(Arc::clone(&manager)))
+        .or(metrics(Arc::clone(&manager)))
+        .or(healthz(Arc::clone(&manager)))
+        .or(readyz(Arc::clone(&manager)))
+        .or(openapi())
+        .or(preflight(manager))
+        .recover(recover_all);
+
+    // not `warp::log`, which only knows the addresses of the connections warp
+    // accepted itself
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::header::headers_cloned())
+        .and(remote())
+        .and(routes)
+        .and_then(
+            move |start: Instant,
+                  method: Method,
+                  path: FullPath,
+                  headers: HeaderMap,
+                  addr: Option<SocketAddr>,
+                  reply| {
+                let resp = cors.apply(&headers, reply);
+                let elapsed = start.elapsed();
+                let (access, counter) = (access.clone(), Arc::clone(&counter));
+                async move {
+                    let (route, brain) = route_name(&method, path.as_str());
+                    let status = resp.status();
+                    if let Some(access) = &access {
+                        let entry = Access {
+                            time: logfile::now(),
+                            address: addr.map(|addr| addr.ip().to_string()),
+                            method: method.as_str(),
+                            path: path.as_str(),
+                            route,
+                            brain,
+                            status: status.as_u16(),
+                            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+                            user_agent: headers
+                                .get(header::USER_AGENT)
+                                .and_then(|agent| agent.to_str().ok()),
+                        };
+                        access.write(&entry).await;
+                    }
+                    let brain = brain.filter(|_| status.is_success());
+                    counter.request(route, brain, status.as_u16(), elapsed);
+                    Ok::<_, Infallible>(resp)
+                }
+            },
+        )
+}
+
+/// The `operationId` of the route a request is for, and the brain it names
+fn route_name<'a>(method: &Method, path: &'a str) -> (&'static str, Option<&'a str>) {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let delete = method == Method::DELETE;
+    let get = method == Method::GET;
+    if method == Method::OPTIONS {
+        return ("preflight", None);
+    }
+    match (delete, segments.as_slice()) {
+        (_, ["v1", "brains"]) | (_, ["list"]) => ("list", None),
+        (true, ["v1", "brains", name]) | (true, ["brain", name]) => ("delete", Some(*name)),
+        (_, ["v1", "brains", name]) | (_, ["brain", name]) if get => ("info", Some(*name)),
+        (false, ["v1", "brains", name]) | (_, ["new", name]) => ("new_brain", Some(*name)),
+        (_, ["v1", "brains", name, "generate"]) if method == Method::POST => {
+            ("generate_json", Some(*name))
+        }
+        (_, ["v1", "brains", name, "generate"]) | (_, ["generate", name]) => {
+            ("generate", Some(*name))
+        }
+        (_, ["v1", "brains", name, "generate", "stream"]) | (_, ["generate", name, "stream"]) => {
+            ("generate_stream", Some(*name))
+        }
+        (_, ["v1", "brains", name, "generate", "batch"]) | (_, ["generate", name, "batch"]) => {
+            ("generate_batch", Some(*name))
+        }
+        (_, ["v1", "brains", name, "train"]) | (_, ["train", name]) => ("train", Some(*name)),
+        (_, ["v1", "brains", name, "train", "batch"]) | (_, ["train", name, "batch"]) => {
+            ("train_batch", Some(*name))
+        }
+        (_, ["v1", "brains", name, "upload"]) | (_, ["upload", name]) => ("upload", Some(*name)),
+        (_, ["v1", "brains", name, "save"]) | (_, ["save", name]) => ("save", Some(*name)),
+        (_, ["v1", "brains", name, "reload"]) | (_, ["reload", name]) => ("reload", Some(*name)),
+        (_, ["v1", "brains", name, "unload"]) | (_, ["unload", name]) => ("unload", Some(*name)),
+        (_, ["v1", "brains", name, "rename"]) => ("rename", Some(*name)),
+        (_, ["v1", "brains", name, "copy"]) => ("copy", Some(*name)),
+        (_, ["v1", "config", "reload"]) | (_, ["reload-config"]) => ("reload_config", None),
+        (true, ["v1", "jobs", _]) | (true, ["jobs", _]) => ("cancel_job", None),
+        (false, ["v1", "jobs", _]) | (false, ["jobs", _]) => ("job", None),
+        (_, ["v1", "ws"]) => ("websocket", None),
+        (_, ["v1", "metrics"]) | (_, ["metrics"]) => ("metrics", None),
+        (_, ["v1", "healthz"]) | (_, ["healthz"]) => ("healthz", None),
+        (_, ["v1", "readyz"]) | (_, ["readyz"]) => ("readyz", None),
+        (_, ["v1", "openapi.json"]) => ("openapi", None),
+        _ => ("unknown", None),
+    }
+}
 
 pub fn generate(
-    topics: Arc<Topics>,
+    manager: Arc<BrainManager>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    warp::path!("generate" / String)
+    let auth = authorize(&manager, Some(Scope::Generate));
+    let defaults = manager.settings().defaults.clone();
+    warp::path!("v1" / "brains" / String / "generate")
+        .or(warp::path!("generate" / String))
+        .unify()
         .and(warp::get())
-        .and_then(move |name| filter(Arc::clone(&topics), name))
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| filter(Arc::clone(&manager), name))
         .and(warp::query())
+        .and(warp::any().map(move || defaults.clone()))
+        .and(warp::any().map(|| OptionsSource::Query))
         .and_then(handlers::generate)
         .recover(recover)
 }
 
-pub fn train(topics: Arc<Topics>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    warp::path!("train" / String)
-        .and_then(move |name| filter(Arc::clone(&topics), name))
+/// Generates with the options in a JSON body, for browsers
+pub fn generate_json(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Generate));
+    let defaults = manager.settings().defaults.clone();
+    let limit = manager.settings().body_limit;
+    warp::path!("v1" / "brains" / String / "generate")
         .and(warp::post())
-        .and(json_body())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| filter(Arc::clone(&manager), name))
+        .and(json_body(limit))
+        .and(warp::any().map(move || defaults.clone()))
+        .and(warp::any().map(|| OptionsSource::Body))
+        .and_then(handlers::generate)
+        .recover(recover)
+}
+
+pub fn stream(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Generate));
+    let defaults = manager.settings().defaults.clone();
+    warp::path!("v1" / "brains" / String / "generate" / "stream")
+        .or(warp::path!("generate" / String / "stream"))
+        .unify()
+        .and(warp::get())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| filter(Arc::clone(&manager), name))
+        .and(warp::query())
+        .and(warp::any().map(move || defaults.clone()))
+        .and_then(handlers::stream)
+        .recover(recover)
+}
+
+/// Generates `count` sentences in one request
+pub fn generate_batch(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Generate));
+    warp::path!("v1" / "brains" / String / "generate" / "batch")
+        .or(warp::path!("generate" / String / "batch"))
+        .unify()
+        .and(warp::get())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_existing(Arc::clone(&manager), name))
+        .and(warp::query())
+        .and_then(handlers::generate_batch)
+        .recover(recover)
+}
+
+pub fn train(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Train));
+    let source = source(&manager);
+    let limit = manager.settings().body_limit;
+    warp::path!("v1" / "brains" / String / "train")
+        .or(warp::path!("train" / String))
+        .unify()
+        .and(warp::post())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_existing(Arc::clone(&manager), name))
+        .and(source)
+        .and(json_body(limit))
         .and_then(handlers::train)
         .recover(recover)
 }
 
-pub fn new(topics: Arc<Topics>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    warp::path!("new" / String)
-        .and_then(move |name| expect_unique(Arc::clone(&topics), name))
+/// Trains with many lines in one request, with a larger body limit
+pub fn train_batch(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Train));
+    let source = source(&manager);
+    let limit = manager.settings().batch_body_limit;
+    warp::path!("v1" / "brains" / String / "train" / "batch")
+        .or(warp::path!("train" / String / "batch"))
+        .unify()
+        .and(warp::post())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_existing(Arc::clone(&manager), name))
+        .and(source)
+        .and(batch_body(limit))
+        .and_then(handlers::train_batch)
+        .recover(recover)
+}
+
+/// Trains with an upload of any size in the background, replying with the job
+///
+/// The body is plain text, or gzip with either a gzip `content-type` or
+/// `content-encoding`.
+pub fn upload(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Train));
+    let source = source(&manager);
+    warp::path!("v1" / "brains" / String / "upload")
+        .or(warp::path!("upload" / String))
+        .unify()
+        .and(warp::post())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_existing(Arc::clone(&manager), name))
+        .and(source)
+        .and(warp::header::optional("content-type"))
+        .and(warp::header::optional("content-encoding"))
+        .and(warp::body::stream())
+        .and_then(handlers::upload)
+        .recover(recover)
+}
+
+pub fn new(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    let limit = manager.settings().body_limit;
+    warp::path!("v1" / "brains" / String)
+        .or(warp::path!("new" / String))
+        .unify()
         .and(warp::post())
-        .and(json_body())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_unique(Arc::clone(&manager), name))
+        .and(json_body(limit))
         .and_then(handlers::new)
         .recover(recover)
 }
 
-pub fn save(topics: Arc<Topics>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    warp::path!("save" / String)
-        .and(warp::put())
-        .and_then(move |name| filter(Arc::clone(&topics), name))
+pub fn rename(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    let limit = manager.settings().body_limit;
+    warp::path!("v1" / "brains" / String / "rename")
+        .and(warp::post())
+        .and(auth)
+        .and(json_body(limit))
+        .and_then(move |name, caller, input: models::input::RenameBrain| {
+            let manager = Arc::clone(&manager);
+            async move {
+                let name = allowed_both(name, caller, &input.name).await?;
+                handlers::rename((manager, name), input).await
+            }
+        })
+        .recover(recover)
+}
+
+pub fn copy(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    let limit = manager.settings().body_limit;
+    warp::path!("v1" / "brains" / String / "copy")
+        .and(warp::post())
+        .and(auth)
+        .and(json_body(limit))
+        .and_then(move |name, caller, input: models::input::CopyBrain| {
+            let manager = Arc::clone(&manager);
+            async move {
+                let name = allowed_both(name, caller, &input.name).await?;
+                handlers::copy((manager, name), input).await
+            }
+        })
+        .recover(recover)
+}
+
+pub fn save(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Save));
+    // the legacy route is a PUT
+    warp::path!("v1" / "brains" / String / "save")
+        .and(warp::post())
+        .or(warp::path!("save" / String).and(warp::put()))
+        .unify()
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| filter(Arc::clone(&manager), name))
         .and_then(handlers::save)
         .recover(recover)
 }
 
-pub fn list(topics: Arc<Topics>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    warp::path!("list")
+pub fn list(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, None);
+    warp::path!("v1" / "brains")
+        .or(warp::path!("list"))
+        .unify()
         .and(warp::get())
-        .map(move || Arc::clone(&topics))
+        .map(move || Arc::clone(&manager))
+        .and(auth)
         .and_then(handlers::list)
         .recover(recover)
 }
+
+/// Describes one brain, with its metadata
+pub fn info(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, None);
+    warp::path!("v1" / "brains" / String)
+        .or(warp::path!("brain" / String))
+        .unify()
+        .and(warp::get())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| filter(Arc::clone(&manager), name))
+        .and_then(handlers::info)
+        .recover(recover)
+}
+
+pub fn delete(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    warp::path!("v1" / "brains" / String)
+        .or(warp::path!("brain" / String))
+        .unify()
+        .and(warp::delete())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_existing(Arc::clone(&manager), name))
+        .and(warp::query())
+        .and_then(handlers::delete)
+        .recover(recover)
+}
+
+pub fn reload(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    warp::path!("v1" / "brains" / String / "reload")
+        .or(warp::path!("reload" / String))
+        .unify()
+        .and(warp::post())
+        .and(auth)
+        .and_then(allowed)
+        .map(move |name| (Arc::clone(&manager), name))
+        .and_then(handlers::reload)
+        .recover(recover)
+}
+
+pub fn unload(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    warp::path!("v1" / "brains" / String / "unload")
+        .or(warp::path!("unload" / String))
+        .unify()
+        .and(warp::post())
+        .and(auth)
+        .and_then(allowed)
+        .and_then(move |name| expect_existing(Arc::clone(&manager), name))
+        .and_then(handlers::unload)
+        .recover(recover)
+}
+
+pub fn reload_config(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    warp::path!("v1" / "config" / "reload")
+        .or(warp::path!("reload-config"))
+        .unify()
+        .and(warp::post())
+        .and(auth)
+        .map(move |_: Caller| Arc::clone(&manager))
+        .and_then(handlers::reload_config)
+        .recover(recover)
+}
+
+pub fn job(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Train));
+    warp::path!("v1" / "jobs" / u64)
+        .or(warp::path!("jobs" / u64))
+        .unify()
+        .and(warp::get())
+        .map(move |id| (Arc::clone(&manager), id))
+        .and(auth)
+        .and_then(handlers::job)
+        .recover(recover)
+}
+
+/// Cancels a job, keeping what it has trained so far
+pub fn cancel_job(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Train));
+    warp::path!("v1" / "jobs" / u64)
+        .or(warp::path!("jobs" / u64))
+        .unify()
+        .and(warp::delete())
+        .map(move |id| (Arc::clone(&manager), id))
+        .and(auth)
+        .and_then(handlers::cancel_job)
+        .recover(recover)
+}
+
+/// A websocket speaking the protocol in `types::ws`
+///
+/// Any token can connect, and each request is checked against its scopes.
+pub fn websocket(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, None);
+    let limit = manager.settings().body_limit;
+    warp::path!("v1" / "ws")
+        .and(warp::ws())
+        .and(auth)
+        .map(move |ws: Ws, caller: Caller| {
+            let manager = Arc::clone(&manager);
+            ws.max_message_size(limit as usize)
+                .on_upgrade(move |socket| socket::serve(manager, caller.grant, socket))
+        })
+}
+
+/// The metrics of every route and loaded brain, in the Prometheus text format
+pub fn metrics(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let auth = authorize(&manager, Some(Scope::Admin));
+    warp::path!("v1" / "metrics")
+        .or(warp::path!("metrics"))
+        .unify()
+        .and(warp::get())
+        .map(move || Arc::clone(&manager))
+        .and(auth)
+        .and_then(handlers::metrics)
+        .recover(recover)
+}
+
+pub fn healthz(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("v1" / "healthz")
+        .or(warp::path!("healthz"))
+        .unify()
+        .and(warp::get())
+        .and_then(move || handlers::healthz(Arc::clone(&manager)))
+}
+
+pub fn readyz(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("v1" / "readyz")
+        .or(warp::path!("readyz"))
+        .unify()
+        .and(warp::get())
+        .and_then(move || handlers::readyz(Arc::clone(&manager)))
+}
+
+/// Answers CORS preflight requests for every route
+pub fn preflight(
+    manager: Arc<BrainManager>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let cors = Cors::new(manager.settings().cors.as_ref());
+    // not `warp::options()`, which would turn unknown routes into
+    // `405 Method Not Allowed`
+    warp::method()
+        .and(warp::header::headers_cloned())
+        .and_then(move |method, headers| {
+            let result = cors.preflight(&method, &headers);
+            async move { result }
+        })
+        .recover(recover)
+}
+
+pub fn openapi() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("v1" / "openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::with_header(OPENAPI, "content-type", "application/json"))
+}
@@ -1,84 +1,349 @@
-use hashbrown::HashMap;
-use markov::Markov;
-use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use warp::Filter;
+#[cfg(feature = "tls")]
+use super::tls;
+use super::{routes, RemoteAddr};
+#[cfg(unix)]
+use crate::config::UnixSocketConfig;
+use crate::{config::ServerConfig, BrainManager};
 
-use super::routes;
-use crate::config::{BrainConfig, ConfiguredMarkov};
+use futures::prelude::*;
+use hyper::service::{make_service_fn, service_fn, Service as _};
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 
-pub type BrainDb = Arc<Brain>;
+// how long to wait after a connection couldn't be accepted
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
 
-pub struct Brain {
-    pub config: BrainConfig,
-    pub markov: Mutex<Markov>,
+/// Serves the brains in a `BrainManager` over HTTP
+pub struct Server {
+    manager: Arc<BrainManager>,
 }
 
-impl std::fmt::Debug for Brain {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Brain")
-            .field("name", &self.config.name)
-            .field("read_only", &self.config.read_only)
-            .finish()
+impl Server {
+    pub fn new(manager: impl Into<Arc<BrainManager>>) -> Self {
+        Self {
+            manager: manager.into(),
+        }
+    }
+
+    /// Listens right away, loading the pending brains in the background
+    ///
+    /// A socket passed by the service manager (`LISTEN_FDS`) is used before
+    /// `server.unix_socket`, which is used before `server.host` and
+    /// `server.port`. Unix sockets don't know the address a request came
+    /// from, so requests without tokens share a rate limit on them.
+    ///
+    /// This only returns if the address can't be used, or if a brain fails to
+    /// load with a strict startup.
+    pub async fn run(self) -> anyhow::Result<()> {
+        let settings = self.manager.settings();
+        let mut listener = Listener::new(settings)?;
+        #[cfg(feature = "tls")]
+        let tls = settings.tls.as_ref().map(tls::Acceptor::new).transpose()?;
+        let manager = Arc::clone(&self.manager);
+
+        #[cfg(unix)]
+        tokio::spawn(reload_on_hangup(Arc::clone(&self.manager)));
+
+        let serve = async move {
+            let incoming = match &mut listener {
+                // as warp does for the connections it accepts
+                Listener::Tcp(listener) => connections(listener.incoming().map_ok(|conn| {
+                    let _ = conn.set_nodelay(true);
+                    conn
+                })),
+                #[cfg(unix)]
+                Listener::Unix(listener) => connections(listener.incoming()),
+            };
+
+            #[cfg(feature = "tls")]
+            {
+                if let Some(tls) = tls {
+                    return serve(manager, tls.accept(incoming)).await;
+                }
+            }
+            serve(manager, incoming).await
+        };
+        future::try_join(serve, self.manager.load_pending()).await?;
+        Ok(())
     }
 }
 
-pub struct Topics {
-    pub brains: Mutex<HashMap<String, BrainDb>>,
-    pub config_path: PathBuf,
+/// A connection the server accepted
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {
+    /// The address of the peer, for the connections that have one
+    fn remote_addr(&self) -> Option<SocketAddr>;
 }
 
-impl Topics {
-    pub fn new(path: impl Into<PathBuf>, brains: HashMap<String, BrainDb>) -> Self {
-        Self {
-            config_path: path.into(),
-            brains: Mutex::new(brains),
-        }
+impl Connection for tokio::net::TcpStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok()
     }
 }
 
-impl std::fmt::Debug for Topics {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Topics").finish()
+#[cfg(unix)]
+impl Connection for tokio::net::UnixStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
     }
 }
 
-#[derive(Default)]
-pub struct Server {
-    brains: HashMap<String, BrainDb>,
+impl Connection for Box<dyn Connection> {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        (**self).remote_addr()
+    }
 }
 
-impl Server {
-    pub fn add_brain(&mut self, brain: ConfiguredMarkov) {
-        let ConfiguredMarkov { config, markov } = brain;
-        let name = config.name.clone();
-        let brain = Arc::new(Brain {
-            config,
-            markov: Mutex::new(markov),
-        });
-        self.brains.insert(name, brain);
-    }
+/// Serves the api on the connections
+///
+/// warp only knows the address of the connections it accepts itself, so each
+/// request gets its connection's address in its extensions instead.
+pub async fn serve<S, C>(manager: Arc<BrainManager>, incoming: S) -> anyhow::Result<()>
+where
+    S: Stream<Item = io::Result<C>> + Send,
+    C: Connection + 'static,
+{
+    let routes = routes::api(manager);
+    let make_service = make_service_fn(move |conn: &C| {
+        let addr = RemoteAddr(conn.remote_addr());
+        let mut service = warp::service(routes.clone());
+        future::ok::<_, Infallible>(service_fn(move |mut req| {
+            req.extensions_mut().insert(addr);
+            service.call(req)
+        }))
+    });
 
-    pub async fn run(self, config: impl Into<PathBuf>, port: u16) {
-        let brains = Arc::new(Topics::new(config, self.brains));
-        let routes = routes::generate(Arc::clone(&brains))
-            .or(routes::save(Arc::clone(&brains)))
-            .or(routes::train(Arc::clone(&brains)))
-            .or(routes::new(Arc::clone(&brains)))
-            .or(routes::list(Arc::clone(&brains)));
-
-        let address = std::env::var("BRAIN_LISTEN_HOST").unwrap_or_else(|_| "127.0.0.1".into());
-        let addr = format!("{}:{}", address, port);
-        let addr = addr
-            .parse::<std::net::SocketAddr>()
-            .map_err(|err| {
-                log::error!(target: "brain", "cannot parse: '{}': {}", addr, err);
-                err
-            })
-            .unwrap();
+    hyper::Server::builder(hyper::server::accept::from_stream(incoming))
+        .serve(make_service)
+        .await?;
+    Ok(())
+}
+
+/// The listener's connections, skipping the ones that couldn't be accepted
+///
+/// An error here would stop the server, so it is only logged.
+fn connections<'a, C>(
+    incoming: impl Stream<Item = io::Result<C>> + Send + 'a,
+) -> stream::BoxStream<'a, io::Result<Box<dyn Connection>>>
+where
+    C: Connection + 'static,
+{
+    incoming
+        .filter_map(|conn| async move {
+            match conn {
+                Ok(conn) => Some(Ok(Box::new(conn) as Box<dyn Connection>)),
+                Err(err) => {
+                    log::warn!(target: "brain", "cannot accept a connection: {}", err);
+                    // running out of file descriptors fails every accept
+                    // until some are closed
+                    tokio::time::delay_for(ACCEPT_BACKOFF).await;
+                    None
+                }
+            }
+        })
+        .boxed()
+}
+
+pub(crate) enum Listener {
+    Tcp(tokio::net::TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    pub(crate) fn new(settings: &ServerConfig) -> anyhow::Result<Self> {
+        // before any listener, so none of them serves plain http instead
+        #[cfg(not(feature = "tls"))]
+        {
+            if settings.tls.is_some() {
+                anyhow::bail!("the server was built without tls support")
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            if let Some(listener) = Self::inherited()? {
+                return Ok(listener);
+            }
+            if let Some(socket) = &settings.unix_socket {
+                return Self::bind_unix(socket);
+            }
+        }
+
+        #[cfg(not(unix))]
+        {
+            if settings.unix_socket.is_some() {
+                anyhow::bail!("unix sockets aren't supported on this platform")
+            }
+        }
 
+        let addr = settings.address().map_err(|err| {
+            anyhow::anyhow!(
+                "cannot parse: '{}:{}': {}",
+                settings.host,
+                settings.port,
+                err
+            )
+        })?;
+        let listener = std::net::TcpListener::bind(addr)
+            .map_err(|err| anyhow::anyhow!("cannot bind '{}': {}", addr, err))?;
+        listener.set_nonblocking(true)?;
         log::info!(target: "brain", "listening on: {}", addr);
-        warp::serve(routes).run(addr).await;
+        Ok(Self::Tcp(tokio::net::TcpListener::from_std(listener)?))
+    }
+
+    /// The first socket passed by the service manager, if it passed any to
+    /// this process
+    #[cfg(unix)]
+    fn inherited() -> anyhow::Result<Option<Self>> {
+        // the passed sockets start after stdin, stdout and stderr
+        const LISTEN_FDS_START: i32 = 3;
+
+        let var = |key| std::env::var(key).ok().and_then(|s| s.parse::<u32>().ok());
+        let (pid, fds) = (var("LISTEN_PID"), var("LISTEN_FDS").unwrap_or(0));
+        if pid != Some(std::process::id()) {
+            return Ok(None);
+        }
+        // so anything this runs isn't told about them too
+        for key in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(key);
+        }
+
+        match fds {
+            0 => return Ok(None),
+            1 => {}
+            fds => log::warn!(target: "brain", "passed {} sockets, only the first is used", fds),
+        }
+
+        Self::from_fd(LISTEN_FDS_START).map(Some)
+    }
+
+    /// Listens on a socket this process was given, which has to be a tcp or
+    /// a unix socket
+    #[cfg(unix)]
+    pub(crate) fn from_fd(fd: std::os::unix::io::RawFd) -> anyhow::Result<Self> {
+        use std::os::unix::io::FromRawFd as _;
+
+        let family = socket_family(fd)
+            .map_err(|err| anyhow::anyhow!("cannot use the passed socket: {}", err))?;
+        match family {
+            libc::AF_UNIX => {
+                // safety: the socket was given to this process, and nothing
+                // else here owns it
+                let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+                log::info!(target: "brain", "listening on the passed socket: {:?}", unix.local_addr()?);
+                unix.set_nonblocking(true)?;
+                Ok(Self::Unix(tokio::net::UnixListener::from_std(unix)?))
+            }
+            libc::AF_INET | libc::AF_INET6 => {
+                // safety: as above
+                let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+                log::info!(target: "brain", "listening on the passed socket: {}", tcp.local_addr()?);
+                tcp.set_nonblocking(true)?;
+                Ok(Self::Tcp(tokio::net::TcpListener::from_std(tcp)?))
+            }
+            family => anyhow::bail!(
+                "the passed socket isn't a tcp or unix socket (its family is {})",
+                family
+            ),
+        }
+    }
+
+    #[cfg(unix)]
+    fn bind_unix(socket: &UnixSocketConfig) -> anyhow::Result<Self> {
+        use std::os::unix::fs::FileTypeExt as _;
+
+        let path = &socket.path;
+        let mode = socket.mode().map_err(|err| anyhow::anyhow!(err))?;
+
+        // a socket left behind by an earlier run can be replaced, unless
+        // something is still listening on it
+        let stale = std::fs::symlink_metadata(path)
+            .map(|meta| meta.file_type().is_socket())
+            .unwrap_or(false);
+        if stale {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                anyhow::bail!("'{}' is already being listened on", path.display())
+            }
+            std::fs::remove_file(path)
+                .map_err(|err| anyhow::anyhow!("cannot remove '{}': {}", path.display(), err))?;
+        }
+
+        let listener = match mode {
+            Some(mode) => Self::bind_private(path, mode),
+            None => tokio::net::UnixListener::bind(path),
+        };
+        let listener =
+            listener.map_err(|err| anyhow::anyhow!("cannot bind '{}': {}", path.display(), err))?;
+
+        log::info!(target: "brain", "listening on: {}", path.display());
+        Ok(Self::Unix(listener))
+    }
+
+    // binds in a directory only this user can use, and moves the socket into
+    // place once it has its mode, so nothing can connect to it before then
+    #[cfg(unix)]
+    fn bind_private(path: &std::path::Path, mode: u32) -> io::Result<tokio::net::UnixListener> {
+        use std::os::unix::fs::{DirBuilderExt as _, PermissionsExt as _};
+        use std::path::Path;
+
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let dir = parent.join(format!(".brain-{}", std::process::id()));
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+        let temp = dir.join("sock");
+        let bind = || -> io::Result<_> {
+            let listener = tokio::net::UnixListener::bind(&temp)?;
+            std::fs::set_permissions(&temp, std::fs::Permissions::from_mode(mode))?;
+            std::fs::rename(&temp, path)?;
+            Ok(listener)
+        };
+        let listener = bind();
+
+        // the socket is only left here if something failed
+        let _ = std::fs::remove_file(&temp);
+        let _ = std::fs::remove_dir(&dir);
+        listener
+    }
+}
+
+/// The address family of a socket, like `AF_UNIX`
+#[cfg(unix)]
+fn socket_family(fd: std::os::unix::io::RawFd) -> io::Result<libc::c_int> {
+    // safety: sockaddr_storage is plain data, and is big enough for the
+    // address of any family
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of_val(&addr) as libc::socklen_t;
+    let ret =
+        unsafe { libc::getsockname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(libc::c_int::from(addr.ss_family))
+}
+
+#[cfg(unix)]
+async fn reload_on_hangup(manager: Arc<BrainManager>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            log::warn!(target: "brain", "cannot listen for SIGHUP: {}", err);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        log::info!(target: "brain", "got SIGHUP, reloading config");
+        if let Err(err) = manager.reload_config().await {
+            log::error!(target: "brain", "cannot reload config: {:?}", err);
+        }
     }
 }
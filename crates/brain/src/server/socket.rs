@@ -0,0 +1,151 @@
+use super::models::{generate::OptionsSource, ws, Error};
+use crate::auth::Grant;
+use crate::config::Scope;
+use crate::BrainManager;
+
+use futures::{SinkExt as _, StreamExt as _};
+use hashbrown::HashSet;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use warp::ws::{Message, WebSocket};
+
+type Outgoing = mpsc::UnboundedSender<ws::Response>;
+
+/// Serves requests from one websocket until it is closed
+///
+/// Requests the token doesn't grant get a `forbidden` error, and generate or
+/// train requests over a rate limit get a `rate_limited` error.
+pub async fn serve(manager: Arc<BrainManager>, grant: Grant, socket: WebSocket) {
+    let (mut sink, mut incoming) = socket.split();
+
+    // requests are handled concurrently, so their responses are queued up here
+    let (out, mut outgoing) = mpsc::unbounded_channel::<ws::Response>();
+    tokio::spawn(async move {
+        while let Some(resp) = outgoing.recv().await {
+            let text = serde_json::to_string(&resp).expect("serialize response");
+            if sink.send(Message::text(text)).await.is_err() {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    let mut events = manager.subscribe();
+    let mut subscribed = HashSet::new();
+
+    loop {
+        tokio::select! {
+            msg = incoming.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) if !msg.is_close() => msg,
+                    _ => break,
+                };
+                // pings are answered for us
+                let text = match msg.to_str() {
+                    Ok(text) => text,
+                    Err(..) => continue,
+                };
+
+                match serde_json::from_str(text) {
+                    Ok(req) => match check(&manager, &grant, &req) {
+                        Ok(()) => handle(&manager, &grant, &mut subscribed, &out, req).await,
+                        Err(error) => {
+                            let id = Some(req.id());
+                            let _ = out.send(ws::Response::Error { id, error });
+                        }
+                    },
+                    Err(err) => {
+                        let error = Error::InvalidBody {
+                            reason: err.to_string(),
+                        };
+                        let _ = out.send(ws::Response::Error { id: None, error });
+                    }
+                }
+            }
+
+            event = events.recv() => match event {
+                Ok(event) if subscribed.contains(&event.brain) => {
+                    let _ = out.send(ws::Response::Training {
+                        brain: event.brain,
+                        trained: event.trained,
+                    });
+                }
+                Ok(..) => {}
+                Err(broadcast::RecvError::Lagged(n)) => {
+                    log::warn!(target: "brain", "websocket missed {} training events", n);
+                }
+                Err(broadcast::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+fn check(manager: &BrainManager, grant: &Grant, req: &ws::Request) -> Result<(), Error> {
+    let (scope, brain) = match req {
+        ws::Request::Generate { brain, .. } => (Scope::Generate, brain),
+        ws::Request::Train { brain, .. } => (Scope::Train, brain),
+        ws::Request::Subscribe { brain, .. } => return grant.check(Scope::Generate, brain),
+        ws::Request::Unsubscribe { .. } => return Ok(()),
+    };
+    grant.check(scope, brain)?;
+    manager.limit_client(scope, &grant.client())?;
+    manager.limit_brain(scope, brain)
+}
+
+async fn handle(
+    manager: &Arc<BrainManager>,
+    grant: &Grant,
+    subscribed: &mut HashSet<String>,
+    out: &Outgoing,
+    req: ws::Request,
+) {
+    match req {
+        ws::Request::Generate { id, brain, opts } => {
+            let (manager, out) = (Arc::clone(manager), out.clone());
+            tokio::spawn(async move {
+                let resp = match manager.generate(&brain, &opts, OptionsSource::Body).await {
+                    Ok(generated) => ws::Response::Generated { id, generated },
+                    Err(error) => ws::Response::Error {
+                        id: Some(id),
+                        error,
+                    },
+                };
+                let _ = out.send(resp);
+            });
+        }
+
+        ws::Request::Train { id, brain, data } => {
+            let (manager, out) = (Arc::clone(manager), out.clone());
+            let source = grant.client();
+            tokio::spawn(async move {
+                let resp = match manager.train(&brain, data, &source).await {
+                    Ok(trained) => ws::Response::Trained { id, trained },
+                    Err(error) => ws::Response::Error {
+                        id: Some(id),
+                        error,
+                    },
+                };
+                let _ = out.send(resp);
+            });
+        }
+
+        ws::Request::Subscribe { id, brain } => {
+            // brains that are still loading can be subscribed to already
+            let resp = if manager.contains(&brain).await || manager.is_loading(&brain).await {
+                subscribed.insert(brain.clone());
+                ws::Response::Subscribed { id, brain }
+            } else {
+                ws::Response::Error {
+                    id: Some(id),
+                    error: Error::NotFound { name: brain },
+                }
+            };
+            let _ = out.send(resp);
+        }
+
+        ws::Request::Unsubscribe { id, brain } => {
+            subscribed.remove(&brain);
+            let _ = out.send(ws::Response::Unsubscribed { id, brain });
+        }
+    }
+}
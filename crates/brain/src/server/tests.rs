@@ -1,16 +1,14 @@
 use super::{
-    models::{self, Error},
-    routes,
-    server::{Brain, Topics},
+    models::{self, generate::OptionsSource, Error},
+    routes, server,
 };
-use crate::config;
+use crate::{config, Brain, BrainManager};
 
-use hashbrown::HashMap;
 use markov::Markov;
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tempdir::TempDir;
-use tokio::sync::Mutex;
 use warp::http::StatusCode;
 use warp::test::request;
 
@@ -45,30 +43,27 @@ fn make_brain<'a>(
         markov.train_text(data)
     }
 
-    Brain {
-        config: config::BrainConfig {
-            name: name.to_string(),
-            brain_file,
-            read_only,
-        },
-        markov: Mutex::new(markov),
-    }
+    let config = config::BrainConfig {
+        name: name.to_string(),
+        brain_file,
+        read_only,
+        audit: None,
+        generate: None,
+    };
+    Brain::new(config, markov)
 }
 
-fn make_db(test_dir: &TempDir, state: impl Into<Option<&'static str>> + Copy) -> Arc<Topics> {
+fn make_db(test_dir: &TempDir, state: impl Into<Option<&'static str>> + Copy) -> Arc<BrainManager> {
     let test1 = make_brain(test_dir, "test1", "test1.db", false, state);
     let test2 = make_brain(test_dir, "test2", "test2.db", true, state);
     let test_no_file = make_brain(test_dir, "test_no_file", "test_no_file.db", false, state);
 
-    let mut map = HashMap::new();
-    map.insert("test1".into(), Arc::new(test1));
-    map.insert("test2".into(), Arc::new(test2));
-    map.insert("test_no_file".into(), Arc::new(test_no_file));
-
     let brain_config_path = test_dir.path().join("brain.toml");
     std::fs::File::create(&brain_config_path).unwrap();
 
-    Arc::new(Topics::new(brain_config_path, map))
+    let brains = vec![test1, test2, test_no_file];
+    let manager = BrainManager::new(brain_config_path, Default::default()).with_brains(brains);
+    Arc::new(manager)
 }
 
 fn body_as_json<'de, T>(resp: &'de warp::http::Response<bytes::Bytes>) -> T
@@ -93,7 +88,7 @@ async fn generate_no_state() {
         .path("/generate/test1")
         .reply(&api)
         .await;
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
 #[tokio::test]
@@ -105,7 +100,7 @@ async fn generate_context_no_state() {
         .path("/generate/test1?context=foo%20bar")
         .reply(&api)
         .await;
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
 #[tokio::test]
@@ -117,7 +112,7 @@ async fn generate_readonly_no_state() {
         .path("/generate/test2")
         .reply(&api)
         .await;
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
 #[tokio::test]
@@ -168,6 +163,134 @@ async fn generate_unknown() {
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 }
 
+fn events(resp: &warp::http::Response<bytes::Bytes>) -> Vec<(String, String)> {
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+    let body = std::str::from_utf8(resp.body()).unwrap();
+    body.split("\n\n")
+        .filter(|event| !event.is_empty())
+        .map(|event| {
+            let field = |name: &str| {
+                event
+                    .lines()
+                    .find(|line| line.starts_with(name))
+                    .map(|line| line[name.len()..].trim().to_string())
+                    .unwrap_or_default()
+            };
+            (field("event:"), field("data:"))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn generate_stream() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::stream(make_db(&dir, LOREM_IPSUM));
+    let resp = request()
+        .method("GET")
+        .path("/generate/test1/stream?max=10")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let events = events(&resp);
+    let (last, tokens) = events.split_last().unwrap();
+    assert_eq!(last.0, "done");
+    assert!(!tokens.is_empty());
+    assert!(tokens.len() <= 10);
+    for (event, data) in tokens {
+        assert_eq!(event, "token");
+        assert!(LOREM_IPSUM.contains(data.as_str()));
+    }
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate/stream")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn generate_stream_no_state() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::stream(make_db(&dir, None));
+    let resp = request()
+        .method("GET")
+        .path("/generate/test1/stream")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::NotEnoughState);
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/test3/stream")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn generate_batch() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::generate_batch(make_db(&dir, LOREM_IPSUM));
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate/batch?count=5&max=10")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let batch: models::responses::GeneratedBatch = body_as_json(&resp);
+    assert_eq!(batch.name, "test1");
+    assert_eq!(batch.results.len(), 5);
+    for item in batch.results {
+        let data = item.into_result().unwrap();
+        assert!(!data.is_empty());
+    }
+
+    // each item fails on its own
+    let api = routes::generate_batch(make_db(&dir, None));
+    let resp = request()
+        .method("GET")
+        .path("/generate/test1/batch?count=2")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let batch: models::responses::GeneratedBatch = body_as_json(&resp);
+    assert_eq!(batch.results.len(), 2);
+    for item in batch.results {
+        matches::assert_matches!(item.into_result(), Err(Error::NotEnoughState));
+    }
+}
+
+#[tokio::test]
+async fn generate_batch_limit() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    Arc::get_mut(&mut db).unwrap().settings.batch_limit = 3;
+
+    let api = routes::generate_batch(db);
+    for path in &["/generate/test1/batch?count=4", "/generate/test1/batch?count=0"] {
+        let resp = request().method("GET").path(path).reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: Error = body_as_json(&resp);
+        matches::assert_matches!(err, Error::InvalidQuery{..});
+    }
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/test3/batch?count=1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn train_readonly() {
     let dir = TempDir::new("brain_tests").unwrap();
@@ -179,7 +302,7 @@ async fn train_readonly() {
         .json(&make_input())
         .reply(&api)
         .await;
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
 
     let err: Error = body_as_json(&resp);
     matches::assert_matches!(err, Error::ReadOnly);
@@ -225,6 +348,225 @@ async fn train_no_body() {
     assert_eq!(resp.status(), 411);
 }
 
+#[tokio::test]
+async fn train_batch() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let mut events = db.subscribe();
+
+    let api = routes::train_batch(Arc::clone(&db));
+    let input = models::input::TrainBatch {
+        data: vec!["hello world".into(), " ".into(), LOREM_IPSUM.into()],
+    };
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/train/batch")
+        .json(&input)
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let batch: models::responses::TrainedBatch = body_as_json(&resp);
+    let mut results = batch.results.into_iter().map(|item| item.into_result());
+    assert_eq!(results.next().unwrap().unwrap().data, "hello world");
+    matches::assert_matches!(results.next().unwrap(), Err(Error::InvalidBody{..}));
+    assert_eq!(results.next().unwrap().unwrap().data, LOREM_IPSUM);
+    assert!(results.next().is_none());
+
+    // only the trained lines are announced
+    assert_eq!(events.recv().await.unwrap().trained.data, "hello world");
+    assert_eq!(events.recv().await.unwrap().trained.data, LOREM_IPSUM);
+
+    let resp = request()
+        .method("POST")
+        .path("/train/test2/batch")
+        .json(&input)
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::ReadOnly);
+}
+
+#[tokio::test]
+async fn train_batch_plain_text() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, None);
+    // bigger than a single train request may be
+    Arc::get_mut(&mut db).unwrap().settings.body_limit = 16;
+
+    let api = routes::train_batch(db);
+    let resp = request()
+        .method("POST")
+        .path("/train/test1/batch")
+        .header("content-type", "text/plain; charset=utf-8")
+        .body("hello world\r\n\nfoo bar baz\n")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let batch: models::responses::TrainedBatch = body_as_json(&resp);
+    let lines = batch
+        .results
+        .into_iter()
+        .map(|item| item.into_result().unwrap().data)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec!["hello world", "foo bar baz"]);
+
+    let resp = request()
+        .method("POST")
+        .path("/train/test1/batch")
+        .header("content-type", "text/csv")
+        .body("hello,world")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+async fn wait_for_job<F>(api: &F, id: u64) -> models::responses::Job
+where
+    F: warp::Filter<Error = std::convert::Infallible> + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    for _ in 0..100 {
+        let resp = request()
+            .method("GET")
+            .path(&format!("/v1/jobs/{}", id))
+            .reply(api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let job: models::responses::Job = body_as_json(&resp);
+        if job.state.is_finished() {
+            return job;
+        }
+        tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("job {} didn't finish", id)
+}
+
+#[tokio::test]
+async fn upload() {
+    use models::responses::{Job, JobState};
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let api = routes::api(Arc::clone(&db));
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/upload")
+        .header("content-type", "text/plain")
+        .body(LOREM_IPSUM.replace(". ", ".\n"))
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let job: Job = body_as_json(&resp);
+    assert_eq!(job.brain, "test1");
+    assert_eq!(job.total, 3);
+
+    let job = wait_for_job(&api, job.id).await;
+    assert_eq!(job.state, JobState::Done);
+    assert_eq!(job.lines, 3);
+    let generated = db
+        .generate("test1", &Default::default(), OptionsSource::Query)
+        .await;
+    assert!(generated.is_ok());
+
+    let resp = request()
+        .method("POST")
+        .path("/upload/test2")
+        .body(LOREM_IPSUM)
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = request()
+        .method("GET")
+        .path("/jobs/1234")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::JobNotFound{ id: 1234 });
+}
+
+#[tokio::test]
+async fn upload_gzip() {
+    use std::io::Write as _;
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, None));
+
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(b"hello world\nfoo bar baz\n").unwrap();
+    let body = encoder.finish().unwrap();
+
+    for (name, value) in &[("content-type", "application/gzip"), ("content-encoding", "gzip")] {
+        let resp = request()
+            .method("POST")
+            .path("/v1/brains/test1/upload")
+            .header(*name, *value)
+            .body(body.clone())
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let job: models::responses::Job = body_as_json(&resp);
+        assert_eq!(job.total, 2);
+        assert_eq!(wait_for_job(&api, job.id).await.lines, 2);
+    }
+
+    // not actually gzip
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/upload")
+        .header("content-encoding", "gzip")
+        .body(LOREM_IPSUM)
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn upload_cancel() {
+    use models::responses::JobState;
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, None));
+
+    let body = format!("{}\n", LOREM_IPSUM).repeat(50_000);
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/upload")
+        .body(body)
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let job: models::responses::Job = body_as_json(&resp);
+
+    let resp = request()
+        .method("DELETE")
+        .path(&format!("/v1/jobs/{}", job.id))
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let job: models::responses::Job = body_as_json(&resp);
+    assert_eq!(job.state, JobState::Cancelling);
+
+    // it is still training its chunk, but it isn't running any more
+    let resp = request()
+        .method("GET")
+        .path(&format!("/v1/jobs/{}", job.id))
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let job: models::responses::Job = body_as_json(&resp);
+    matches::assert_matches!(job.state, JobState::Cancelling | JobState::Cancelled);
+
+    let job = wait_for_job(&api, job.id).await;
+    assert_eq!(job.state, JobState::Cancelled);
+    assert!(job.lines < job.total);
+}
+
 #[tokio::test]
 async fn save_cannot_rotate() {
     let dir = TempDir::new("brain_tests").unwrap();
@@ -244,7 +586,7 @@ async fn save_cannot_rotate() {
         .reply(&api)
         .await;
 
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     let err: Error = body_as_json(&resp);
     matches::assert_matches!(err, Error::CannotRotate{..});
     if let Error::CannotRotate { file, .. } = err {
@@ -283,6 +625,21 @@ async fn save_rotate() {
     tokio::fs::metadata(right).await.unwrap_err();
 }
 
+#[tokio::test]
+async fn save_markov_keeps_backup() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let brain_file = dir.path().join("test1.db");
+    let mut markov = Markov::new(3, "test1");
+    markov::save(&markov, &brain_file).unwrap();
+
+    markov.train_text(LOREM_IPSUM);
+    crate::save_markov(markov, &brain_file).await.unwrap();
+
+    assert!(!markov::load(&brain_file).unwrap().chain.is_empty());
+    let backup = markov::load(dir.path().join("test1.bak")).unwrap();
+    assert!(backup.chain.is_empty());
+}
+
 // this is incase the 'input' is read-only
 #[tokio::test]
 async fn save_cannnot_save() {
@@ -308,7 +665,7 @@ async fn save_cannnot_save() {
         .path("/save/test1")
         .reply(&api)
         .await;
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     let err: Error = body_as_json(&resp);
     matches::assert_matches!(err, Error::CannotRotate{..});
     if let Error::CannotSave { file, .. } = err {
@@ -391,7 +748,7 @@ async fn new_append() {
         .await;
     assert_eq!(resp.status(), StatusCode::OK);
 
-    let data = tokio::fs::read_to_string(&db.config_path).await.unwrap();
+    let data = tokio::fs::read_to_string(&db.config.path()).await.unwrap();
     let config: config::Config = toml::from_str(&data).unwrap();
     assert_eq!(config.brains.len(), 1);
 
@@ -417,7 +774,7 @@ async fn new_append() {
         .await;
     assert_eq!(resp.status(), StatusCode::OK);
 
-    let data = tokio::fs::read_to_string(&db.config_path).await.unwrap();
+    let data = tokio::fs::read_to_string(&db.config.path()).await.unwrap();
     let config: config::Config = toml::from_str(&data).unwrap();
     assert_eq!(config.brains.len(), 2);
 
@@ -437,7 +794,7 @@ async fn new_conflict() {
         .json(&make_input())
         .reply(&api)
         .await;
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
 
     let err: Error = body_as_json(&resp);
     matches::assert_matches!(err, Error::AlreadyExists{..});
@@ -446,6 +803,43 @@ async fn new_conflict() {
     }
 }
 
+#[tokio::test]
+async fn new_checks_config() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    // configured, but not loaded
+    let test4 = dir.path().join("test4.db");
+    write_config(&db, &[("test4", &test4)]).await;
+    let api = routes::new(Arc::clone(&db));
+
+    let new = |brain_file: &Path| models::input::NewBrain {
+        brain_file: brain_file.to_string_lossy().to_string(),
+        depth: 3,
+    };
+    let (test1, test5) = (dir.path().join("test1.db"), dir.path().join("test5.db"));
+    for (path, input, status) in &[
+        ("/new/test.5", new(&test5), StatusCode::BAD_REQUEST),
+        ("/new/test4", new(&test5), StatusCode::CONFLICT),
+        ("/new/test5", new(&test4), StatusCode::BAD_REQUEST),
+        ("/new/test5", new(&test1), StatusCode::BAD_REQUEST),
+    ] {
+        let resp = request()
+            .method("POST")
+            .path(path)
+            .json(input)
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), *status, "{} {:?}", path, input);
+    }
+
+    // nothing was written
+    tokio::fs::metadata(&test4).await.unwrap_err();
+    tokio::fs::metadata(&test5).await.unwrap_err();
+    let config = config::Config::load(&db.config.path()).await.unwrap();
+    assert_eq!(config.brains.len(), 1);
+    assert_eq!(config.brains["test4"].brain_file, test4);
+}
+
 #[tokio::test]
 async fn list_some() {
     let dir = TempDir::new("brain_tests").unwrap();
@@ -467,3 +861,2478 @@ async fn list_none() {
     let list = body_as_json::<models::responses::List>(&resp);
     assert_eq!(list.brains.len(), 0);
 }
+
+#[tokio::test]
+async fn info() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let api = routes::api(Arc::clone(&db));
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let item: models::responses::ListItem = body_as_json(&resp);
+    assert_eq!(item.name, "test1");
+    let metadata = item.metadata.unwrap();
+    assert_eq!(metadata.depth, 3);
+    assert_eq!(metadata.lines, 0);
+    assert!(metadata.created.is_some());
+    assert_eq!(metadata.last_trained, None);
+    assert_eq!(metadata.file_size, Some(0));
+    assert!(!metadata.dirty);
+
+    db.train("test1", make_input(), "test").await.unwrap();
+    let resp = request()
+        .method("GET")
+        .path("/brain/test1")
+        .reply(&api)
+        .await;
+    let metadata = body_as_json::<models::responses::ListItem>(&resp)
+        .metadata
+        .unwrap();
+    assert_eq!(metadata.lines, 1);
+    assert!(metadata.last_trained.is_some());
+    assert!(metadata.memory_size > 0);
+    assert!(metadata.dirty);
+
+    db.save("test1").await.unwrap();
+    let list = db.list().await;
+    let saved = list.brains["test1"].metadata.clone().unwrap();
+    assert!(!saved.dirty);
+    assert!(saved.last_saved.is_some());
+    assert!(saved.file_size.unwrap() > 0);
+
+    // the metadata is kept in the brain file
+    write_config(&db, &[("test1", &dir.path().join("test1.db"))]).await;
+    db.reload("test1").await.unwrap();
+    let reloaded = db.list().await.brains["test1"].metadata.clone().unwrap();
+    assert_eq!(reloaded.created, metadata.created);
+    assert_eq!(reloaded.last_saved, saved.last_saved);
+    assert_eq!(reloaded.lines, 1);
+    assert!(!reloaded.dirty);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/made_up")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+async fn write_config(db: &BrainManager, brains: &[(&str, &Path)]) {
+    let mut config = config::Config::default();
+    for (name, brain_file) in brains {
+        config.brains.insert(
+            name.to_string(),
+            config::BrainConfig {
+                name: name.to_string(),
+                brain_file: brain_file.to_path_buf(),
+                read_only: false,
+                audit: None,
+                generate: None,
+            },
+        );
+    }
+    config.save(&db.config.path()).await.unwrap();
+}
+
+#[tokio::test]
+async fn delete_keep_file() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+
+    let api = routes::delete(Arc::clone(&db));
+    let resp = request()
+        .method("DELETE")
+        .path("/brain/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let deleted: models::responses::Deleted = body_as_json(&resp);
+    assert_eq!(deleted.name, "test1");
+    assert!(!deleted.file_deleted);
+    assert!(deleted.config_updated);
+
+    assert!(!db.brains.lock().await.contains_key("test1"));
+    tokio::fs::metadata(&brain_file).await.unwrap();
+
+    let config = config::Config::load(&db.config.path()).await.unwrap();
+    assert!(config.brains.is_empty());
+}
+
+#[tokio::test]
+async fn delete_file_keep_config() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+
+    let api = routes::delete(Arc::clone(&db));
+    let resp = request()
+        .method("DELETE")
+        .path("/brain/test1?delete_file=true&keep_config=true")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let deleted: models::responses::Deleted = body_as_json(&resp);
+    assert!(deleted.file_deleted);
+    assert!(!deleted.config_updated);
+
+    tokio::fs::metadata(&brain_file).await.unwrap_err();
+
+    let config = config::Config::load(&db.config.path()).await.unwrap();
+    assert!(config.brains.contains_key("test1"));
+}
+
+#[tokio::test]
+async fn delete_file_fails() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+    // a directory can't be removed as a file
+    std::fs::remove_file(&brain_file).unwrap();
+    std::fs::create_dir(&brain_file).unwrap();
+
+    let api = routes::delete(Arc::clone(&db));
+    let resp = request()
+        .method("DELETE")
+        .path("/brain/test1?delete_file=true")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the file is reported, but the brain is still unloaded and removed from the config
+    let deleted: models::responses::Deleted = body_as_json(&resp);
+    assert!(!deleted.file_deleted);
+    assert!(deleted.config_updated);
+    assert_eq!(deleted.errors.len(), 1);
+    matches::assert_matches!(&deleted.errors[0], Error::CannotDelete { .. });
+
+    assert!(!db.brains.lock().await.contains_key("test1"));
+    let config = config::Config::load(&db.config.path()).await.unwrap();
+    assert!(config.brains.is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn config_keeps_mode() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+    let permissions = std::fs::Permissions::from_mode(0o640);
+    std::fs::set_permissions(db.config.path(), permissions).unwrap();
+
+    let api = routes::delete(Arc::clone(&db));
+    let resp = request()
+        .method("DELETE")
+        .path("/brain/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let metadata = std::fs::metadata(db.config.path()).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+}
+
+#[tokio::test]
+async fn delete_unknown() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::delete(make_db(&dir, None));
+    let resp = request()
+        .method("DELETE")
+        .path("/brain/test3")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unload() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let api = routes::unload(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/unload/test2")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!db.brains.lock().await.contains_key("test2"));
+
+    let resp = request()
+        .method("POST")
+        .path("/unload/test2")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unload_saves() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let brain = db.get("test1").await.unwrap();
+    brain.lock().await.train_text(LOREM_IPSUM);
+
+    let api = routes::unload(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/unload/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!db.brains.lock().await.contains_key("test1"));
+
+    let markov = markov::load(dir.path().join("test1.db")).unwrap();
+    assert!(!markov.chain.is_empty());
+}
+
+#[tokio::test]
+async fn unload_cannot_save() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let brain_file = dir.path().join("missing").join("test1.db");
+    let brain = make_brain(None, "test1", brain_file, false, LOREM_IPSUM);
+    let config_path = dir.path().join("brain.toml");
+    let db = Arc::new(BrainManager::new(config_path, Default::default()).with_brains(vec![brain]));
+
+    let api = routes::unload(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/unload/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // the unsaved training is still loaded
+    let brain = db.get("test1").await.unwrap();
+    assert!(brain.lock().await.dirty);
+}
+
+#[tokio::test]
+async fn rename() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, LOREM_IPSUM);
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+
+    let api = routes::rename(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/rename")
+        .json(&models::input::RenameBrain {
+            name: "test4".into(),
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let renamed: models::responses::Renamed = body_as_json(&resp);
+    assert_eq!(renamed.from, "test1");
+    assert_eq!(renamed.name, "test4");
+    assert!(renamed.config_updated);
+
+    assert!(!db.brains.lock().await.contains_key("test1"));
+    let brain = db.get("test4").await.unwrap();
+    assert_eq!(brain.config.brain_file, brain_file);
+    let markov = brain.lock().await;
+    assert_eq!(markov.name, "test4");
+    assert!(markov.dirty);
+    drop(markov);
+
+    let config = config::Config::load(&db.config.path()).await.unwrap();
+    assert!(!config.brains.contains_key("test1"));
+    assert_eq!(config.brains["test4"].brain_file, brain_file);
+
+    for (path, name, status) in &[
+        ("/v1/brains/test4/rename", "test2", StatusCode::CONFLICT),
+        ("/v1/brains/test4/rename", "test 5", StatusCode::BAD_REQUEST),
+        ("/v1/brains/test1/rename", "test5", StatusCode::NOT_FOUND),
+    ] {
+        let resp = request()
+            .method("POST")
+            .path(path)
+            .json(&models::input::RenameBrain {
+                name: name.to_string(),
+            })
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), *status, "{} to {}", path, name);
+    }
+}
+
+#[tokio::test]
+async fn copy() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, LOREM_IPSUM);
+    write_config(&db, &[("test2", &dir.path().join("test2.db"))]).await;
+
+    let brain_file = dir.path().join("test4.db");
+    let copy = |name: &str, brain_file: &Path, depth| models::input::CopyBrain {
+        name: name.into(),
+        brain_file: brain_file.to_string_lossy().to_string(),
+        depth,
+    };
+
+    let api = routes::copy(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test2/copy")
+        .json(&copy("test4", &brain_file, Some(2)))
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let copied: models::responses::Copied = body_as_json(&resp);
+    assert_eq!(copied.from, "test2");
+    assert_eq!(copied.name, "test4");
+    assert_eq!(copied.depth, 2);
+
+    // the copy of a read-only brain can be trained
+    let brain = db.get("test4").await.unwrap();
+    assert!(!brain.config.read_only);
+    let markov = brain.lock().await;
+    assert!(markov.chain.keys().all(|context| context.len() <= 2));
+    drop(markov);
+    db.train("test4", make_input(), "test").await.unwrap();
+
+    let markov = markov::load(&brain_file).unwrap();
+    assert_eq!(markov.name, "test4");
+    assert_eq!(markov.depth, 2);
+    assert_eq!(markov.meta.source.as_deref(), Some("copied from test2"));
+
+    let config = config::Config::load(&db.config.path()).await.unwrap();
+    assert_eq!(config.brains["test4"].brain_file, brain_file);
+    assert!(!config.brains["test4"].read_only);
+
+    let (test1, test5) = (dir.path().join("test1.db"), dir.path().join("test5.db"));
+    for (input, status) in &[
+        (copy("test5", &brain_file, None), StatusCode::BAD_REQUEST),
+        (copy("test5", &test1, None), StatusCode::BAD_REQUEST),
+        (copy("test5", &test5, Some(4)), StatusCode::BAD_REQUEST),
+        (copy("test1", &test5, None), StatusCode::CONFLICT),
+    ] {
+        let resp = request()
+            .method("POST")
+            .path("/v1/brains/test2/copy")
+            .json(input)
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), *status, "{:?}", input);
+    }
+    assert!(!db.contains("test5").await);
+}
+
+#[tokio::test]
+async fn reload_unloaded() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+
+    let brain_file = dir.path().join("test4.db");
+    let mut markov = Markov::new(3, "test4");
+    markov.train_text(LOREM_IPSUM);
+    markov::save(&markov, &brain_file).unwrap();
+    write_config(&db, &[("test4", &brain_file)]).await;
+
+    let api = routes::reload(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/reload/test4")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let reloaded: models::responses::Reloaded = body_as_json(&resp);
+    assert_eq!(reloaded.name, "test4");
+    assert_eq!(reloaded.brain_file, brain_file);
+
+    let lock = db.brains.lock().await;
+    let brain = lock.get("test4").unwrap();
+    assert!(!brain.markov.lock().await.chain.is_empty());
+}
+
+#[tokio::test]
+async fn reload_not_configured() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::reload(make_db(&dir, None));
+    // test1 is loaded, but not in the config file
+    let resp = request()
+        .method("POST")
+        .path("/reload/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reload_bad_file() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    // this is an empty file
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+
+    let api = routes::reload(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/reload/test1")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::CannotLoad{..});
+}
+
+#[tokio::test]
+async fn config_edits_preserve_comments() {
+    const CONFIG: &str = "# a comment about test1\n\
+                          [brains.test1]\n\
+                          # where it lives\n\
+                          brain_file = \"test1.db\"\n\
+                          read_only = false\n";
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    tokio::fs::write(db.config.path(), CONFIG).await.unwrap();
+
+    let brain_file = dir.path().join("test3.db").to_string_lossy().to_string();
+    let resp = request()
+        .method("POST")
+        .path("/new/test3")
+        .json(&models::input::NewBrain {
+            brain_file: brain_file.clone(),
+            depth: 5,
+        })
+        .reply(&routes::new(Arc::clone(&db)))
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let data = tokio::fs::read_to_string(db.config.path()).await.unwrap();
+    assert!(data.starts_with(CONFIG));
+    let config: config::Config = toml::from_str(&data).unwrap();
+    assert_eq!(config.brains.len(), 2);
+
+    let resp = request()
+        .method("DELETE")
+        .path("/brain/test3")
+        .reply(&routes::delete(Arc::clone(&db)))
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let data = tokio::fs::read_to_string(db.config.path()).await.unwrap();
+    assert_eq!(data.trim_end(), CONFIG.trim_end());
+}
+
+#[tokio::test]
+async fn new_invalid_config() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    tokio::fs::write(db.config.path(), "[brains\n").await.unwrap();
+
+    let brain_file = dir.path().join("test3.db").to_string_lossy().to_string();
+    let resp = request()
+        .method("POST")
+        .path("/new/test3")
+        .json(&models::input::NewBrain {
+            brain_file,
+            depth: 5,
+        })
+        .reply(&routes::new(Arc::clone(&db)))
+        .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::InvalidConfig{..});
+    assert!(!db.brains.lock().await.contains_key("test3"));
+}
+
+#[tokio::test]
+async fn reload_config_diff() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+
+    let brain_file = dir.path().join("test4.db");
+    markov::save(&Markov::new(3, "test4"), &brain_file).unwrap();
+
+    let mut config = config::Config::default();
+    for (name, brain_file, read_only) in vec![
+        // test1 becomes read-only
+        ("test1", dir.path().join("test1.db"), true),
+        // test2 is unchanged
+        ("test2", dir.path().join("test2.db"), true),
+        // test4 is new
+        ("test4", brain_file, false),
+        // bad is an empty file
+        ("bad", dir.path().join("test_no_file.db"), false),
+    ] {
+        config.brains.insert(
+            name.into(),
+            config::BrainConfig {
+                name: name.into(),
+                brain_file,
+                read_only,
+                audit: None,
+                generate: None,
+            },
+        );
+    }
+    config.save(db.config.path()).await.unwrap();
+
+    let api = routes::reload_config(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/reload-config")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let report: models::responses::ConfigReloaded = body_as_json(&resp);
+    assert_eq!(report.added, vec!["test4".to_string()]);
+    assert_eq!(report.removed, vec!["test_no_file".to_string()]);
+    assert_eq!(report.updated, vec!["test1".to_string()]);
+    assert!(report.failed.contains_key("bad"));
+
+    assert!(db.failed.lock().await.contains_key("bad"));
+
+    let lock = db.brains.lock().await;
+    assert!(lock.get("test1").unwrap().config.read_only);
+    assert!(lock.contains_key("test2"));
+    assert!(lock.contains_key("test4"));
+    assert!(!lock.contains_key("test_no_file"));
+    assert!(!lock.contains_key("bad"));
+}
+
+#[tokio::test]
+async fn reload_config_saves() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let test1 = make_brain(&dir, "test1", "test1.db", false, LOREM_IPSUM);
+    let missing = dir.path().join("missing").join("test3.db");
+    let test3 = make_brain(None, "test3", missing, false, LOREM_IPSUM);
+    let config_path = dir.path().join("brain.toml");
+    let db = BrainManager::new(config_path, Default::default()).with_brains(vec![test1, test3]);
+    let db = Arc::new(db);
+
+    // test1 gets another file, and test3 is removed
+    let brain_file = dir.path().join("test4.db");
+    markov::save(&Markov::new(3, "test1"), &brain_file).unwrap();
+    write_config(&db, &[("test1", &brain_file)]).await;
+
+    let api = routes::reload_config(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/reload-config")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let report: models::responses::ConfigReloaded = body_as_json(&resp);
+    assert_eq!(report.updated, vec!["test1".to_string()]);
+    assert!(report.removed.is_empty());
+    assert_eq!(report.unsaved.len(), 1);
+    matches::assert_matches!(report.unsaved["test3"], Error::CannotSave { .. });
+
+    // test1 was saved to its old file before it was replaced
+    let markov = markov::load(dir.path().join("test1.db")).unwrap();
+    assert!(!markov.chain.is_empty());
+
+    // and test3 is kept, with its training
+    let brain = db.get("test3").await.unwrap();
+    assert!(brain.lock().await.dirty);
+}
+
+#[tokio::test]
+async fn reload_config_overrides() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let path = dir.path().join("brain.toml");
+    config::Config::default().save(&path).await.unwrap();
+
+    // like the --port flag
+    let overrides = crate::load::Overrides {
+        port: Some(1234),
+        ..Default::default()
+    };
+    let db = BrainManager::load(&path, &overrides).await.unwrap();
+    assert_eq!(db.settings().port, 1234);
+
+    let mut config = db.config.load().await.unwrap();
+    assert!(!db.restart_required(&config.server));
+
+    config.server.batch_limit += 1;
+    assert!(db.restart_required(&config.server));
+}
+
+#[tokio::test]
+async fn generate_defaults() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    Arc::get_mut(&mut db).unwrap().settings.defaults = config::GenerateDefaults {
+        min: 1,
+        max: 3,
+        ..Default::default()
+    };
+
+    let api = routes::generate(db);
+    for _ in 0..10 {
+        let resp = request()
+            .method("GET")
+            .path("/generate/test1")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let generated: models::responses::Generated = body_as_json(&resp);
+        assert!(generated.data.split_whitespace().count() <= 3);
+    }
+}
+
+#[tokio::test]
+async fn generate_limits() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+
+    let brain = make_brain(&dir, "test1", "test1.db", false, LOREM_IPSUM);
+    let mut config = brain.config.clone();
+    config.generate = Some(config::GenerateConfig {
+        max: Some(3),
+        max_words: Some(5),
+        hard_max_words: Some(20),
+        batch_limit: Some(2),
+        ..Default::default()
+    });
+    let markov = std::mem::replace(&mut *brain.lock().await, Markov::new(3, "test1"));
+    let brain = Arc::new(Brain::new(config, markov));
+    db.brains.lock().await.insert("test1".into(), brain);
+    let api = routes::api(db);
+
+    // the brain's defaults, then its soft limit
+    for (path, words) in &[
+        ("/v1/brains/test1/generate", 3),
+        ("/generate/test1?max=10", 5),
+    ] {
+        for _ in 0..10 {
+            let resp = request().method("GET").path(path).reply(&api).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+
+            let generated: models::responses::Generated = body_as_json(&resp);
+            assert!(generated.data.split_whitespace().count() <= *words);
+        }
+    }
+
+    for path in &[
+        "/generate/test1?max=21",
+        "/generate/test1?min=21",
+        "/generate/test1/batch?count=3",
+    ] {
+        let resp = request().method("GET").path(path).reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: Error = body_as_json(&resp);
+        matches::assert_matches!(err, Error::InvalidQuery{..});
+    }
+
+    // the same limit, for options in a body
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/generate")
+        .json(&models::input::GenerateOptions {
+            context: None,
+            min: None,
+            max: Some(21),
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::InvalidBody { .. });
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/test1/batch?count=2&max=20")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let batch: models::responses::GeneratedBatch = body_as_json(&resp);
+    for item in batch.results {
+        assert!(item.into_result().unwrap().split_whitespace().count() <= 5);
+    }
+}
+
+#[tokio::test]
+async fn generate_json() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, LOREM_IPSUM));
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/generate")
+        .json(&models::input::GenerateOptions {
+            max: Some(3),
+            ..Default::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let generated: models::responses::Generated = body_as_json(&resp);
+    assert!(generated.data.split_whitespace().count() <= 3);
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/generate")
+        .body("max=3")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::InvalidBody { .. });
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test4/generate")
+        .json(&models::input::GenerateOptions::default())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn train_body_limit() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, None);
+    Arc::get_mut(&mut db).unwrap().settings.body_limit = 16;
+
+    let api = routes::train(db);
+    let resp = request()
+        .method("POST")
+        .path("/train/test1")
+        .json(&make_input())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn reload_config_invalid() {
+    const CONFIG: &str = "[server.defaults]\n\
+                          min = 10\n\
+                          max = 5\n\
+                          \n\
+                          [brains.\"bad name\"]\n\
+                          brain_file = \"test1.db\"\n\
+                          read_only = false\n\
+                          \n\
+                          [server.unix_socket]\n\
+                          path = \"brain.sock\"\n\
+                          mode = \"999\"\n\
+                          \n\
+                          [server.cors]\n\
+                          origins = [\"example.com\"]\n";
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    tokio::fs::write(db.config.path(), CONFIG).await.unwrap();
+
+    let api = routes::reload_config(Arc::clone(&db));
+    let resp = request()
+        .method("POST")
+        .path("/reload-config")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let err: Error = body_as_json(&resp);
+    match err {
+        Error::InvalidConfig { reason, .. } => {
+            assert!(reason.contains("line 2: server.defaults.min"), "{}", reason);
+            assert!(reason.contains("line 5: brains.bad name"), "{}", reason);
+            assert!(
+                reason.contains("line 11: server.unix_socket.mode"),
+                "{}",
+                reason
+            );
+            assert!(
+                reason.contains("line 14: server.cors.origins"),
+                "{}",
+                reason
+            );
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    // nothing was changed
+    assert_eq!(db.brains.lock().await.len(), 3);
+}
+
+#[tokio::test]
+async fn list_failed() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    // this is an empty file
+    let brain_file = dir.path().join("test1.db");
+    write_config(&db, &[("test1", &brain_file)]).await;
+
+    db.brains.lock().await.remove("test1");
+    let resp = request()
+        .method("POST")
+        .path("/reload/test1")
+        .reply(&routes::reload(Arc::clone(&db)))
+        .await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let resp = request()
+        .method("GET")
+        .path("/list")
+        .reply(&routes::list(Arc::clone(&db)))
+        .await;
+    let list = body_as_json::<models::responses::List>(&resp);
+    assert_eq!(list.brains.len(), 2);
+    let failed = list.failed.get("test1").unwrap();
+    assert_eq!(failed.brain_file, brain_file);
+}
+
+#[tokio::test]
+async fn manager_without_http() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+
+    db.train("test1", make_input(), "test").await.unwrap();
+    let opts = models::input::GenerateOptions {
+        context: None,
+        min: None,
+        max: None,
+    };
+    let source = OptionsSource::Query;
+    let generated = db.generate("test1", &opts, source).await.unwrap();
+    assert_eq!(generated.name, "test1");
+
+    let err = db.train("test2", make_input(), "test").await.unwrap_err();
+    matches::assert_matches!(err, Error::ReadOnly);
+
+    let err = db.save("unknown").await.unwrap_err();
+    matches::assert_matches!(err, Error::NotFound{..});
+}
+
+#[tokio::test]
+async fn api_errors_are_json() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, None);
+    Arc::get_mut(&mut db).unwrap().settings.body_limit = 64;
+    let api = routes::api(db);
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/test3")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::NotFound{..});
+
+    let resp = request().method("GET").path("/foobar").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::UnknownRoute);
+
+    let resp = request()
+        .method("POST")
+        .path("/train/test1")
+        .body("not json")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::InvalidBody{..});
+
+    let resp = request()
+        .method("POST")
+        .path("/train/test1")
+        .json(&models::input::TrainData {
+            data: LOREM_IPSUM.into(),
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::PayloadTooLarge);
+
+    let resp = request().method("DELETE").path("/list").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::MethodNotAllowed);
+}
+
+fn with_tokens(db: &mut Arc<BrainManager>) {
+    use config::{Scope, TokenConfig};
+
+    let tokens = &mut Arc::get_mut(db).unwrap().settings.tokens;
+    tokens.insert(
+        "reader".into(),
+        TokenConfig {
+            token: "reader-token".into(),
+            scopes: vec![Scope::Generate],
+            brains: Some(vec!["test1".into()]),
+        },
+    );
+    tokens.insert(
+        "admin".into(),
+        TokenConfig {
+            token: "admin-token".into(),
+            scopes: vec![Scope::Generate, Scope::Train, Scope::Save, Scope::Admin],
+            brains: None,
+        },
+    );
+}
+
+#[tokio::test]
+async fn auth_tokens() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    with_tokens(&mut db);
+    let api = routes::api(db);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers().get("www-authenticate").unwrap(), "Bearer");
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::Unauthorized);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .header("authorization", "Bearer not-a-token")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .header("authorization", "Bearer reader-token")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the legacy routes need the token too
+    let resp = request()
+        .method("GET")
+        .path("/generate/test1")
+        .header("authorization", "bearer reader-token")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // not in the token's brains
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test2/generate")
+        .header("authorization", "Bearer reader-token")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::Forbidden{..});
+
+    // without the train scope
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/train")
+        .header("authorization", "Bearer reader-token")
+        .json(&make_input())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::Forbidden{..});
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/train")
+        .header("authorization", "Bearer admin-token")
+        .json(&make_input())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/config/reload")
+        .header("authorization", "Bearer reader-token")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // the metrics name brains outside the token's brains
+    for (token, status) in &[
+        ("reader-token", StatusCode::FORBIDDEN),
+        ("admin-token", StatusCode::OK),
+    ] {
+        let resp = request()
+            .method("GET")
+            .path("/metrics")
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), *status);
+    }
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/openapi.json")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn auth_list() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, None);
+    with_tokens(&mut db);
+    let api = routes::list(db);
+
+    let resp = request().method("GET").path("/v1/brains").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains")
+        .header("authorization", "Bearer reader-token")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let list: models::responses::List = body_as_json(&resp);
+    assert_eq!(list.brains.keys().collect::<Vec<_>>(), vec!["test1"]);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains")
+        .header("authorization", "Bearer admin-token")
+        .reply(&api)
+        .await;
+    let list: models::responses::List = body_as_json(&resp);
+    assert_eq!(list.brains.len(), 3);
+}
+
+#[tokio::test]
+async fn rate_limits() {
+    use config::Limit;
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    with_tokens(&mut db);
+    let limits = &mut Arc::get_mut(&mut db).unwrap().settings.rate_limits;
+    limits.client.generate.replace(Limit {
+        per_second: 0.1,
+        burst: 2,
+    });
+    limits.brain.train.replace(Limit {
+        per_second: 0.1,
+        burst: 1,
+    });
+    let api = routes::api(db);
+
+    let generate = |token| {
+        request()
+            .method("GET")
+            .path("/v1/brains/test1/generate")
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&api)
+    };
+
+    assert_eq!(generate("reader-token").await.status(), StatusCode::OK);
+    assert_eq!(generate("reader-token").await.status(), StatusCode::OK);
+
+    let resp = generate("reader-token").await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "10");
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::RateLimited { retry_after } if retry_after > 9.0);
+
+    // each client has its own limit
+    assert_eq!(generate("admin-token").await.status(), StatusCode::OK);
+
+    let train = |brain| {
+        request()
+            .method("POST")
+            .path(&format!("/v1/brains/{}/train", brain))
+            .header("authorization", "Bearer admin-token")
+            .json(&make_input())
+            .reply(&api)
+    };
+
+    assert_eq!(train("test1").await.status(), StatusCode::OK);
+    let resp = train("test1").await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // and each brain has its own limit
+    assert_eq!(train("test_no_file").await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn metrics() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, None));
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/train")
+        .json(&make_input())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/save")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/made_up")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = request().method("GET").path("/metrics").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+
+    let body = std::str::from_utf8(resp.body()).unwrap();
+    let lines = body.lines().collect::<Vec<_>>();
+    let expected = &[
+        r#"brain_http_requests_total{route="generate",brain="",status="422"} 1"#,
+        r#"brain_http_requests_total{route="train",brain="test1",status="200"} 1"#,
+        r#"brain_http_requests_total{route="save",brain="test1",status="200"} 1"#,
+        r#"brain_http_requests_total{route="generate",brain="",status="404"} 1"#,
+        r#"brain_http_request_duration_seconds_count{route="train",brain="test1"} 1"#,
+        r#"brain_generate_failures_total{brain="test1"} 1"#,
+        r#"brain_generate_failures_total{brain="test2"} 0"#,
+        r#"brain_trained_lines_total{brain="test1"} 1"#,
+        r#"brain_save_duration_seconds_count{brain="test1"} 1"#,
+        r#"brain_contexts{brain="test2"} 0"#,
+        r#"# TYPE brain_lock_wait_seconds histogram"#,
+    ];
+    for line in expected {
+        assert!(lines.contains(line), "missing {}", line);
+    }
+
+    let trained = format!(
+        r#"brain_trained_bytes_total{{brain="test1"}} {}"#,
+        LOREM_IPSUM.len()
+    );
+    assert!(lines.contains(&trained.as_str()));
+    assert!(!body.contains("made_up"));
+    assert!(!lines.contains(&r#"brain_last_save_timestamp_seconds{brain="test1"} 0"#));
+}
+
+fn read_log(path: &Path) -> Vec<serde_json::Value> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn access_and_audit_logs() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, None);
+
+    let log = |name: &str| config::LogConfig {
+        file: dir.path().join(name),
+        max_size: 1024 * 1024,
+        keep: 1,
+    };
+    Arc::get_mut(&mut db).unwrap().settings.access_log = Some(log("access.log"));
+
+    for (name, read_only) in &[("test1", false), ("test2", true)] {
+        let brain = make_brain(&dir, name, format!("{}.db", name), *read_only, None);
+        let mut config = brain.config.clone();
+        config.audit = Some(config::AuditConfig {
+            log: log(&format!("{}.audit.log", name)),
+            text: *name == "test1",
+        });
+        let brain = Arc::new(Brain::new(config, Markov::new(3, *name)));
+        db.brains.lock().await.insert(name.to_string(), brain);
+    }
+    let api = routes::api(Arc::clone(&db));
+    let addr = "127.0.0.1:4321".parse().unwrap();
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/train")
+        .remote_addr(addr)
+        .json(&make_input())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test1/train/batch")
+        .remote_addr(addr)
+        .json(&models::input::TrainBatch {
+            data: vec!["hello world".into(), " ".into()],
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("POST")
+        .path("/train/test2")
+        .remote_addr(addr)
+        .header("user-agent", "tests")
+        .json(&make_input())
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/made_up")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let access = read_log(&dir.path().join("access.log"));
+    assert_eq!(access.len(), 4);
+    assert_eq!(access[0]["route"], "train");
+    assert_eq!(access[0]["brain"], "test1");
+    assert_eq!(access[0]["method"], "POST");
+    assert_eq!(access[0]["status"], 200);
+    assert_eq!(access[0]["address"], "127.0.0.1");
+    assert!(access[0]["elapsed_ms"].as_f64().unwrap() >= 0.0);
+    assert_eq!(access[1]["route"], "train_batch");
+    assert_eq!(access[2]["path"], "/train/test2");
+    assert_eq!(access[2]["status"], 403);
+    assert_eq!(access[2]["user_agent"], "tests");
+    // unlike the metrics, the access log keeps the names of missing brains
+    assert_eq!(access[3]["brain"], "made_up");
+    assert_eq!(access[3]["status"], 404);
+    assert_eq!(access[3]["address"], serde_json::Value::Null);
+
+    let audit = read_log(&dir.path().join("test1.audit.log"));
+    assert_eq!(audit.len(), 3);
+    assert!(audit.iter().all(|line| line["brain"] == "test1"));
+    assert!(audit.iter().all(|line| line["source"] == "127.0.0.1"));
+    assert_eq!(audit[0]["accepted"], true);
+    assert_eq!(audit[0]["bytes"], LOREM_IPSUM.len());
+    assert_eq!(audit[0]["hash"], crate::logfile::hash(LOREM_IPSUM));
+    assert_eq!(audit[0]["text"], LOREM_IPSUM);
+    assert_eq!(audit[1]["text"], "hello world");
+    assert_eq!(audit[2]["accepted"], false);
+    assert!(audit[2]["error"].is_object());
+
+    // only the hashes, without `text`
+    let audit = read_log(&dir.path().join("test2.audit.log"));
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0]["accepted"], false);
+    assert_eq!(audit[0]["hash"], crate::logfile::hash(LOREM_IPSUM));
+    assert!(audit[0].get("text").is_none());
+
+    assert_eq!(crate::logfile::hash(""), "cbf29ce484222325");
+    assert_eq!(crate::logfile::hash("a"), "af63dc4c8601ec8c");
+}
+
+#[tokio::test]
+async fn log_rotation() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let file = dir.path().join("test.log");
+    let log = crate::logfile::LogFile::new(config::LogConfig {
+        file: file.clone(),
+        max_size: 16,
+        keep: 2,
+    });
+
+    for n in 0..4 {
+        log.write(&format!("line {}", n)).await;
+    }
+
+    let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+    assert_eq!(read("test.log"), "\"line 3\"\n");
+    assert_eq!(read("test.log.1"), "\"line 2\"\n");
+    assert_eq!(read("test.log.2"), "\"line 1\"\n");
+    assert!(!dir.path().join("test.log.3").exists());
+}
+
+#[tokio::test]
+async fn audit_log_reconfigured() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let file = dir.path().join("test1.audit.log");
+
+    let mut config = config::Config::default();
+    let mut brain = config::BrainConfig {
+        name: "test1".into(),
+        brain_file: dir.path().join("test1.db"),
+        read_only: false,
+        audit: Some(config::AuditConfig {
+            log: config::LogConfig {
+                file: file.clone(),
+                max_size: 1024 * 1024,
+                keep: 1,
+            },
+            text: false,
+        }),
+        generate: None,
+    };
+    config.brains.insert("test1".into(), brain.clone());
+    config.save(db.config.path()).await.unwrap();
+    db.reload_config().await.unwrap();
+    db.train("test1", make_input(), "tests").await.unwrap();
+
+    // every line is rotated out by the next one now
+    brain.audit.as_mut().unwrap().log.max_size = 1;
+    config.brains.insert("test1".into(), brain);
+    config.save(db.config.path()).await.unwrap();
+    let report = db.reload_config().await.unwrap();
+    assert_eq!(report.updated, vec!["test1".to_string()]);
+    db.train("test1", make_input(), "tests").await.unwrap();
+
+    assert_eq!(read_log(&file).len(), 1);
+    assert_eq!(read_log(&dir.path().join("test1.audit.log.1")).len(), 1);
+}
+
+#[tokio::test]
+async fn reload_config_shared_log() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+
+    let mut config = config::Config::default();
+    for name in &["test1", "test2"] {
+        let brain = config::BrainConfig {
+            name: name.to_string(),
+            brain_file: dir.path().join(format!("{}.db", name)),
+            read_only: false,
+            audit: Some(config::AuditConfig {
+                log: config::LogConfig {
+                    file: dir.path().join("audit.log"),
+                    max_size: 1024 * 1024,
+                    keep: 1,
+                },
+                text: false,
+            }),
+            generate: None,
+        };
+        config.brains.insert(name.to_string(), brain);
+    }
+    config.save(db.config.path()).await.unwrap();
+
+    let err = db.reload_config().await.unwrap_err();
+    match err {
+        Error::InvalidConfig { reason, .. } => {
+            let message = "brains.test2.audit.file: '";
+            assert!(reason.contains(message), "{}", reason);
+            assert!(reason.contains("is already the log file of brains.test1.audit"));
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+fn with_pending(dir: &TempDir, startup: config::Startup) -> Arc<BrainManager> {
+    let brain_file = dir.path().join("test4.db");
+    let mut markov = Markov::new(3, "test4");
+    markov.train_text(LOREM_IPSUM);
+    markov::save(&markov, &brain_file).unwrap();
+
+    let pending = vec![
+        config::BrainConfig {
+            name: "test4".into(),
+            brain_file,
+            read_only: false,
+            audit: None,
+            generate: None,
+        },
+        // an empty file, so it can't be loaded
+        config::BrainConfig {
+            name: "bad".into(),
+            brain_file: dir.path().join("test1.db"),
+            read_only: false,
+            audit: None,
+            generate: None,
+        },
+    ];
+
+    let mut db = Arc::try_unwrap(make_db(dir, None)).unwrap();
+    db.settings.startup = startup;
+    Arc::new(db.with_pending(pending))
+}
+
+#[tokio::test]
+async fn loading() {
+    use models::responses::{Health, List};
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = with_pending(&dir, config::Startup::Lenient);
+    let api = routes::api(Arc::clone(&db));
+
+    let resp = request().method("GET").path("/healthz").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request().method("GET").path("/readyz").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let health: Health = body_as_json(&resp);
+    let expected = Health {
+        ready: false,
+        loaded: 3,
+        loading: 2,
+        failed: 0,
+    };
+    assert_eq!(health, expected);
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/test4")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::Loading { ref name } if name == "test4");
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test4")
+        .json(&models::input::NewBrain {
+            brain_file: "test4.db".into(),
+            depth: 3,
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = request().method("GET").path("/v1/brains").reply(&api).await;
+    let list: List = body_as_json(&resp);
+    assert!(list.loading.contains_key("test4"));
+    assert!(list.loading.contains_key("bad"));
+
+    db.load_pending().await.unwrap();
+
+    let resp = request().method("GET").path("/v1/readyz").reply(&api).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let health: Health = body_as_json(&resp);
+    let expected = Health {
+        ready: true,
+        loaded: 4,
+        loading: 0,
+        failed: 1,
+    };
+    assert_eq!(health, expected);
+
+    let resp = request()
+        .method("GET")
+        .path("/generate/test4")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request().method("GET").path("/v1/brains").reply(&api).await;
+    let list: List = body_as_json(&resp);
+    assert!(list.loading.is_empty());
+    assert!(list.brains.contains_key("test4"));
+    assert!(list.failed.contains_key("bad"));
+}
+
+#[tokio::test]
+async fn loading_strict() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = with_pending(&dir, config::Startup::Strict);
+
+    assert!(db.load_pending().await.is_err());
+    assert!(db.contains("test4").await);
+    assert!(db.failed.lock().await.contains_key("bad"));
+}
+
+fn openapi() -> serde_json::Value {
+    serde_json::from_str(routes::OPENAPI).unwrap()
+}
+
+fn assert_schema<T>(doc: &serde_json::Value, name: &str, item: &T)
+where
+    T: serde::Serialize,
+{
+    let item = serde_json::to_value(item).unwrap();
+    let mut fields = item.as_object().unwrap().keys().collect::<Vec<_>>();
+    fields.sort();
+
+    let properties = &doc["components"]["schemas"][name]["properties"];
+    let mut documented = properties.as_object().unwrap().keys().collect::<Vec<_>>();
+    documented.sort();
+
+    assert_eq!(fields, documented, "schema for {}", name);
+}
+
+#[test]
+fn openapi_matches_types() {
+    use models::{input::*, responses::*};
+    use std::time::Duration;
+
+    let doc = openapi();
+    assert_schema(&doc, "Duration", &Duration::from_secs(1));
+
+    let opts = GenerateOptions {
+        context: Some("foo".into()),
+        min: Some(1),
+        max: Some(2),
+    };
+    assert_schema(&doc, "GenerateOptions", &opts);
+    assert_schema(&doc, "TrainData", &make_input());
+    let new = NewBrain {
+        brain_file: "test1.db".into(),
+        depth: 3,
+    };
+    assert_schema(&doc, "NewBrain", &new);
+    let rename = RenameBrain {
+        name: "test4".into(),
+    };
+    assert_schema(&doc, "RenameBrain", &rename);
+    let copy = CopyBrain {
+        name: "test4".into(),
+        brain_file: "test4.db".into(),
+        depth: Some(2),
+    };
+    assert_schema(&doc, "CopyBrain", &copy);
+    assert_schema(&doc, "DeleteOptions", &DeleteOptions::default());
+    assert_schema(&doc, "GenerateBatchOptions", &GenerateBatchOptions::default());
+    assert_schema(&doc, "TrainBatch", &TrainBatch::default());
+
+    let generated = Generated {
+        name: "test1".into(),
+        data: "foo".into(),
+    };
+    assert_schema(&doc, "Generated", &generated);
+    let trained = Trained {
+        data: "foo".into(),
+        time: Duration::from_secs(1),
+    };
+    assert_schema(&doc, "Trained", &trained);
+    let batch = GeneratedBatch {
+        name: "test1".into(),
+        results: vec![BatchItem::Ok("foo".into())],
+    };
+    assert_schema(&doc, "GeneratedBatch", &batch);
+    let batch = TrainedBatch {
+        results: vec![BatchItem::Ok(trained)],
+        time: Duration::from_secs(1),
+    };
+    assert_schema(&doc, "TrainedBatch", &batch);
+    let saved = Saved {
+        name: "test1".into(),
+        time: Duration::from_secs(1),
+    };
+    assert_schema(&doc, "Saved", &saved);
+    let created = Created {
+        name: "test1".into(),
+        brain_file: "test1.db".into(),
+    };
+    assert_schema(&doc, "Created", &created);
+    let renamed = Renamed {
+        from: "test1".into(),
+        name: "test4".into(),
+        config_updated: true,
+    };
+    assert_schema(&doc, "Renamed", &renamed);
+    let copied = Copied {
+        from: "test1".into(),
+        name: "test4".into(),
+        brain_file: "test4.db".into(),
+        depth: 2,
+    };
+    assert_schema(&doc, "Copied", &copied);
+    let deleted = Deleted {
+        name: "test1".into(),
+        brain_file: "test1.db".into(),
+        file_deleted: false,
+        config_updated: true,
+        errors: vec![Error::CannotDelete {
+            file: "test1.db".into(),
+            reason: "Is a directory".into(),
+        }],
+    };
+    assert_schema(&doc, "Deleted", &deleted);
+    let unloaded = Unloaded {
+        name: "test1".into(),
+    };
+    assert_schema(&doc, "Unloaded", &unloaded);
+    let reloaded = Reloaded {
+        name: "test1".into(),
+        brain_file: "test1.db".into(),
+        time: Duration::from_secs(1),
+    };
+    assert_schema(&doc, "Reloaded", &reloaded);
+    let mut reloaded = ConfigReloaded::default();
+    let err = Error::CannotSave {
+        file: "test1.db".into(),
+        reason: "No space left on device".into(),
+    };
+    reloaded.unsaved.insert("test1".into(), err);
+    assert_schema(&doc, "ConfigReloaded", &reloaded);
+
+    let item = ListItem {
+        name: "test1".into(),
+        brain_file: "test1.db".into(),
+        read_only: false,
+        metadata: Some(Metadata::default()),
+    };
+    assert_schema(&doc, "ListItem", &item);
+    assert_schema(&doc, "Metadata", &Metadata::default());
+    let failed = Failed {
+        name: "test2".into(),
+        brain_file: "test2.db".into(),
+        reason: "foo".into(),
+    };
+    assert_schema(&doc, "Failed", &failed);
+    let list = List {
+        brains: Default::default(),
+        failed: Default::default(),
+        loading: Default::default(),
+        config_path: "brain.toml".into(),
+    };
+    assert_schema(&doc, "List", &list);
+    let health = Health {
+        ready: true,
+        loaded: 1,
+        loading: 0,
+        failed: 0,
+    };
+    assert_schema(&doc, "Health", &health);
+
+    let job = Job {
+        id: 1,
+        brain: "test1".into(),
+        state: JobState::Failed,
+        lines: 1,
+        total: 2,
+        lines_per_sec: 1.0,
+        elapsed: Duration::from_secs(1),
+        reason: Some("foo".into()),
+    };
+    assert_schema(&doc, "Job", &job);
+}
+
+#[test]
+fn openapi_lists_every_error() {
+    let file = || "test1.db".to_string();
+    let name = || "test1".to_string();
+    let reason = || "foo".to_string();
+
+    let errors = vec![
+        Error::ReadOnly,
+        Error::NotEnoughState,
+        Error::CannotRotate {
+            file: file(),
+            reason: reason(),
+        },
+        Error::CannotSave {
+            file: file(),
+            reason: reason(),
+        },
+        Error::AlreadyExists { name: name() },
+        Error::CannotLoad {
+            file: file(),
+            reason: reason(),
+        },
+        Error::CannotDelete {
+            file: file(),
+            reason: reason(),
+        },
+        Error::CannotUpdateConfig {
+            file: file(),
+            reason: reason(),
+        },
+        Error::InvalidConfig {
+            file: file(),
+            reason: reason(),
+        },
+        Error::NotFound { name: name() },
+        Error::JobNotFound { id: 1 },
+        Error::UnknownRoute,
+        Error::InvalidBody { reason: reason() },
+        Error::InvalidQuery { reason: reason() },
+        Error::InvalidHeader { reason: reason() },
+        Error::PayloadTooLarge,
+        Error::MethodNotAllowed,
+        Error::UnsupportedMediaType,
+        Error::Unauthorized,
+        Error::Forbidden { reason: reason() },
+        Error::RateLimited { retry_after: 1.5 },
+        Error::Loading { name: name() },
+        Error::Internal { reason: reason() },
+    ];
+
+    let doc = openapi();
+    let schema = &doc["components"]["schemas"]["Error"]["properties"];
+    let mut documented = schema["error"]["enum"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tag| tag.as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    documented.sort();
+
+    let mut tags = vec![];
+    for error in errors {
+        let error = serde_json::to_value(&error).unwrap();
+        for field in error.as_object().unwrap().keys() {
+            assert!(schema.get(field).is_some(), "undocumented field: {}", field);
+        }
+        tags.push(error["error"].as_str().unwrap().to_string());
+    }
+    tags.sort();
+
+    assert_eq!(tags, documented);
+}
+
+#[tokio::test]
+async fn openapi_paths_are_routed() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, None));
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/openapi.json")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let doc: serde_json::Value = body_as_json(&resp);
+    assert_eq!(doc, openapi());
+
+    for (path, item) in doc["paths"].as_object().unwrap() {
+        let path = path.replace("{name}", "test3").replace("{id}", "1");
+        for method in item.as_object().unwrap().keys() {
+            if method == "parameters" {
+                continue;
+            }
+
+            let resp = request()
+                .method(&method.to_uppercase())
+                .path(&path)
+                .reply(&api)
+                .await;
+            if resp.status().is_success() {
+                continue;
+            }
+
+            let err: Error = body_as_json(&resp);
+            match err {
+                Error::UnknownRoute | Error::MethodNotAllowed => {
+                    panic!("{} {} is not routed: {:?}", method, path, err)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn v1_routes_and_legacy_aliases() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, LOREM_IPSUM));
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("POST")
+        .path("/v1/brains/test_no_file/save")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("PUT")
+        .path("/save/test_no_file")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = request()
+        .method("PUT")
+        .path("/v1/brains/test_no_file/save")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let resp = request()
+        .method("DELETE")
+        .path("/v1/brains/test3")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let err: Error = body_as_json(&resp);
+    matches::assert_matches!(err, Error::NotFound{..});
+}
+
+#[tokio::test]
+async fn cors() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    Arc::get_mut(&mut db).unwrap().settings.cors = Some(config::CorsConfig {
+        origins: vec!["https://example.com".into()],
+        max_age: 60,
+    });
+    let api = routes::api(db);
+
+    for path in &[
+        "/v1/brains/test1/generate",
+        "/v1/brains/test1/train",
+        "/list",
+    ] {
+        let resp = request()
+            .method("OPTIONS")
+            .path(path)
+            .header("origin", "https://Example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT, "{}", path);
+        let headers = resp.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://Example.com"
+        );
+        assert!(headers["access-control-allow-methods"]
+            .to_str()
+            .unwrap()
+            .contains("POST"));
+        assert_eq!(headers["access-control-max-age"], "60");
+    }
+
+    let resp = request()
+        .method("OPTIONS")
+        .path("/v1/brains/test1/generate")
+        .header("origin", "https://example.org")
+        .header("access-control-request-method", "GET")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert!(!resp.headers().contains_key("access-control-allow-origin"));
+
+    // not a preflight
+    let resp = request()
+        .method("OPTIONS")
+        .path("/v1/brains/test1/generate")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .header("origin", "https://example.com")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()["access-control-allow-origin"],
+        "https://example.com"
+    );
+    assert_eq!(resp.headers()["vary"], "origin");
+
+    // errors get the headers too, so browsers can read them
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test4")
+        .header("origin", "https://example.com")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(resp.headers().contains_key("access-control-allow-origin"));
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .header("origin", "https://example.org")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!resp.headers().contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn cors_not_configured() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let api = routes::api(make_db(&dir, LOREM_IPSUM));
+
+    let resp = request()
+        .method("OPTIONS")
+        .path("/v1/brains/test1/generate")
+        .header("origin", "https://example.com")
+        .header("access-control-request-method", "GET")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = request()
+        .method("GET")
+        .path("/v1/brains/test1/generate")
+        .header("origin", "https://example.com")
+        .reply(&api)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!resp.headers().contains_key("access-control-allow-origin"));
+    assert!(!resp.headers().contains_key("vary"));
+}
+
+async fn ws_recv(client: &mut warp::test::WsClient) -> models::ws::Response {
+    let msg = client.recv().await.unwrap();
+    serde_json::from_str(msg.to_str().unwrap()).unwrap()
+}
+
+async fn ws_send(
+    client: &mut warp::test::WsClient,
+    req: models::ws::Request,
+) -> models::ws::Response {
+    client.send_text(serde_json::to_string(&req).unwrap()).await;
+    ws_recv(client).await
+}
+
+#[tokio::test]
+async fn websocket() {
+    use models::ws::{Request, Response};
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, LOREM_IPSUM);
+    let mut client = warp::test::ws()
+        .path("/v1/ws")
+        .handshake(routes::websocket(Arc::clone(&db)))
+        .await
+        .unwrap();
+
+    let opts = models::input::GenerateOptions {
+        context: None,
+        min: None,
+        max: None,
+    };
+    let req = Request::Generate {
+        id: 1,
+        brain: "test1".into(),
+        opts: opts.clone(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(resp, Response::Generated { id: 1, .. });
+
+    let req = Request::Generate {
+        id: 2,
+        brain: "test3".into(),
+        opts,
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(
+        resp,
+        Response::Error {
+            id: Some(2),
+            error: Error::NotFound { .. }
+        }
+    );
+
+    let req = Request::Train {
+        id: 3,
+        brain: "test2".into(),
+        data: make_input(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(
+        resp,
+        Response::Error {
+            id: Some(3),
+            error: Error::ReadOnly
+        }
+    );
+
+    client.send_text("garbage").await;
+    let resp = ws_recv(&mut client).await;
+    matches::assert_matches!(
+        resp,
+        Response::Error {
+            id: None,
+            error: Error::InvalidBody { .. }
+        }
+    );
+}
+
+#[tokio::test]
+async fn websocket_training_events() {
+    use models::ws::{Request, Response};
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let db = make_db(&dir, None);
+    let mut client = warp::test::ws()
+        .path("/v1/ws")
+        .handshake(routes::websocket(Arc::clone(&db)))
+        .await
+        .unwrap();
+
+    let req = Request::Subscribe {
+        id: 1,
+        brain: "test1".into(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(resp, Response::Subscribed { id: 1, .. });
+
+    // training from somewhere else
+    db.train("test1", make_input(), "test").await.unwrap();
+    let resp = ws_recv(&mut client).await;
+    match resp {
+        Response::Training { brain, trained } => {
+            assert_eq!(brain, "test1");
+            assert_eq!(trained.data, LOREM_IPSUM);
+        }
+        resp => panic!("unexpected response: {:?}", resp),
+    }
+
+    // the response and the event can arrive in any order
+    let req = Request::Train {
+        id: 2,
+        brain: "test1".into(),
+        data: make_input(),
+    };
+    client.send_text(serde_json::to_string(&req).unwrap()).await;
+    let mut ids = vec![ws_recv(&mut client).await.id(), ws_recv(&mut client).await.id()];
+    ids.sort();
+    assert_eq!(ids, vec![None, Some(2)]);
+
+    let req = Request::Unsubscribe {
+        id: 3,
+        brain: "test1".into(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(resp, Response::Unsubscribed { id: 3, .. });
+
+    // no event for this one, so the next message is the error
+    db.train("test1", make_input(), "test").await.unwrap();
+    let req = Request::Subscribe {
+        id: 4,
+        brain: "test3".into(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(
+        resp,
+        Response::Error {
+            id: Some(4),
+            error: Error::NotFound { .. }
+        }
+    );
+}
+
+#[tokio::test]
+async fn websocket_auth() {
+    use models::ws::{Request, Response};
+
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    with_tokens(&mut db);
+
+    let client = warp::test::ws()
+        .path("/v1/ws")
+        .handshake(routes::websocket(Arc::clone(&db)))
+        .await;
+    assert!(client.is_err());
+
+    let mut client = warp::test::ws()
+        .path("/v1/ws")
+        .header("authorization", "Bearer reader-token")
+        .handshake(routes::websocket(Arc::clone(&db)))
+        .await
+        .unwrap();
+
+    let opts = models::input::GenerateOptions {
+        context: None,
+        min: None,
+        max: None,
+    };
+    let req = Request::Generate {
+        id: 1,
+        brain: "test1".into(),
+        opts: opts.clone(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(resp, Response::Generated { id: 1, .. });
+
+    let req = Request::Generate {
+        id: 2,
+        brain: "test_no_file".into(),
+        opts,
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(
+        resp,
+        Response::Error {
+            id: Some(2),
+            error: Error::Forbidden { .. }
+        }
+    );
+
+    let req = Request::Train {
+        id: 3,
+        brain: "test1".into(),
+        data: make_input(),
+    };
+    let resp = ws_send(&mut client, req).await;
+    matches::assert_matches!(
+        resp,
+        Response::Error {
+            id: Some(3),
+            error: Error::Forbidden { .. }
+        }
+    );
+}
+
+/// Sends a request on its own connection, and reads the whole response
+async fn http_get<C>(mut conn: C, path: &str) -> String
+where
+    C: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let req = format!(
+        "GET {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+        path
+    );
+    conn.write_all(req.as_bytes()).await.unwrap();
+    let mut resp = String::new();
+    conn.read_to_string(&mut resp).await.unwrap();
+    resp
+}
+
+#[tokio::test]
+async fn serve_remote_addr() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, LOREM_IPSUM);
+    let log = dir.path().join("access.log");
+    Arc::get_mut(&mut db).unwrap().settings.access_log = Some(config::LogConfig {
+        file: log.clone(),
+        max_size: 1024 * 1024,
+        keep: 1,
+    });
+
+    let mut listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { server::serve(db, listener.incoming()).await });
+
+    let conn = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let resp = http_get(conn, "/v1/brains/test1/generate").await;
+    assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+
+    let access = read_log(&log);
+    assert_eq!(access.len(), 1);
+    assert_eq!(access[0]["address"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn run_address_in_use() {
+    let dir = TempDir::new("brain_tests").unwrap();
+    let mut db = make_db(&dir, None);
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let settings = &mut Arc::get_mut(&mut db).unwrap().settings;
+    settings.host = "127.0.0.1".into();
+    settings.port = taken.local_addr().unwrap().port();
+
+    let err = server::Server::new(db).run().await.unwrap_err();
+    assert!(err.to_string().contains("cannot bind"), "{}", err);
+}
+
+#[cfg(unix)]
+mod listener {
+    use super::*;
+    use crate::server::server::Listener;
+
+    use std::os::unix::fs::{FileTypeExt as _, PermissionsExt as _};
+    use std::os::unix::io::{AsRawFd as _, IntoRawFd as _};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    fn unix_socket(path: &Path, mode: Option<&str>) -> config::ServerConfig {
+        config::ServerConfig {
+            unix_socket: Some(config::UnixSocketConfig {
+                path: path.into(),
+                mode: mode.map(Into::into),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn bind_unix() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let path = dir.path().join("brain.sock");
+
+        let listener = Listener::new(&unix_socket(&path, Some("600"))).unwrap();
+        assert!(matches::matches!(listener, Listener::Unix(..)));
+
+        let meta = std::fs::metadata(&path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        UnixStream::connect(&path).unwrap();
+
+        // it was bound somewhere private, and moved here
+        let files = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[tokio::test]
+    async fn bind_unix_stale() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let path = dir.path().join("brain.sock");
+
+        // the socket is left behind once nothing listens on it
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(UnixStream::connect(&path).is_err());
+
+        let _listener = Listener::new(&unix_socket(&path, None)).unwrap();
+        UnixStream::connect(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn bind_unix_in_use() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let path = dir.path().join("brain.sock");
+        let _live = UnixListener::bind(&path).unwrap();
+
+        let err = Listener::new(&unix_socket(&path, None)).err().unwrap();
+        let err = err.to_string();
+        assert!(err.contains("already being listened on"), "{}", err);
+        UnixStream::connect(&path).unwrap();
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[tokio::test]
+    async fn bind_unix_tls() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let path = dir.path().join("brain.sock");
+        let mut settings = unix_socket(&path, None);
+        settings.tls = Some(config::TlsConfig {
+            cert: dir.path().join("cert.pem"),
+            key: dir.path().join("key.pem"),
+        });
+
+        let err = Listener::new(&settings).err().unwrap();
+        let err = err.to_string();
+        assert!(err.contains("without tls support"), "{}", err);
+        assert!(std::fs::metadata(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn inherited_tcp() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+
+        match Listener::from_fd(tcp.into_raw_fd()).unwrap() {
+            Listener::Tcp(listener) => assert_eq!(listener.local_addr().unwrap(), addr),
+            Listener::Unix(..) => panic!("expected a tcp listener"),
+        }
+    }
+
+    #[tokio::test]
+    async fn inherited_unix() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let path = dir.path().join("brain.sock");
+        let unix = UnixListener::bind(&path).unwrap();
+
+        match Listener::from_fd(unix.into_raw_fd()).unwrap() {
+            Listener::Unix(..) => UnixStream::connect(&path).map(drop).unwrap(),
+            Listener::Tcp(..) => panic!("expected a unix listener"),
+        }
+    }
+
+    #[tokio::test]
+    async fn inherited_not_a_socket() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let file = std::fs::File::create(dir.path().join("file")).unwrap();
+
+        let err = Listener::from_fd(file.as_raw_fd()).err().unwrap();
+        let err = err.to_string();
+        assert!(err.contains("cannot use the passed socket"), "{}", err);
+    }
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use super::*;
+    use crate::server::tls::Acceptor;
+
+    use std::net::IpAddr;
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::{client::TlsStream, rustls, webpki, TlsConnector};
+
+    // self-signed certificates for localhost, made with `openssl req -x509
+    // -nodes -days 36500 -subj /CN=localhost -addext subjectAltName=DNS:localhost
+    // -addext basicConstraints=critical,CA:FALSE`
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    /// Copies the `ec` or `rsa` test certificate and key into `dir`
+    fn use_cert(dir: &TempDir, name: &str) -> config::TlsConfig {
+        let config = config::TlsConfig {
+            cert: dir.path().join("cert.pem"),
+            key: dir.path().join("key.pem"),
+        };
+        std::fs::copy(testdata(&format!("{}.pem", name)), &config.cert).unwrap();
+        std::fs::copy(testdata(&format!("{}.key", name)), &config.key).unwrap();
+        config
+    }
+
+    const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+
+    /// Serves the api over tls on a new port
+    async fn serve_tls(db: Arc<BrainManager>, acceptor: Acceptor) -> SocketAddr {
+        let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server::serve(db, acceptor.accept(listener.incoming())).await });
+        addr
+    }
+
+    /// Connects from `from`, trusting only the `name` test certificate
+    async fn connect(
+        addr: SocketAddr,
+        from: IpAddr,
+        name: &str,
+    ) -> std::io::Result<TlsStream<TcpStream>> {
+        use socket2::{Domain, Socket, Type};
+
+        let socket = Socket::new(Domain::ipv4(), Type::stream(), None)?;
+        socket.bind(&SocketAddr::new(from, 0).into())?;
+        socket.connect(&addr.into())?;
+        let stream = socket.into_tcp_stream();
+        stream.set_nonblocking(true)?;
+        let stream = TcpStream::from_std(stream)?;
+
+        let mut config = rustls::ClientConfig::new();
+        let pem = std::fs::read(testdata(&format!("{}.pem", name)))?;
+        config.root_store.add_pem_file(&mut &*pem).unwrap();
+        let domain = webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        TlsConnector::from(Arc::new(config))
+            .connect(domain, stream)
+            .await
+    }
+
+    #[tokio::test]
+    async fn handshake() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let acceptor = Acceptor::new(&use_cert(&dir, "ec")).unwrap();
+        let addr = serve_tls(make_db(&dir, None), acceptor).await;
+
+        let conn = connect(addr, LOCALHOST.into(), "ec").await.unwrap();
+        let resp = http_get(conn, "/v1/healthz").await;
+        assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+
+        // a client that doesn't trust the certificate
+        assert!(connect(addr, LOCALHOST.into(), "rsa").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn handshake_timeout() {
+        use tokio::io::AsyncReadExt as _;
+
+        let dir = TempDir::new("brain_tests").unwrap();
+        let config = use_cert(&dir, "ec");
+        let timeout = Duration::from_millis(200);
+        let acceptor = Acceptor::with_timings(&config, Duration::from_secs(10), timeout).unwrap();
+        let addr = serve_tls(make_db(&dir, None), acceptor).await;
+
+        // never starts its handshake
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+
+        // which doesn't hold up the other connections
+        let conn = connect(addr, LOCALHOST.into(), "ec").await.unwrap();
+        let resp = http_get(conn, "/v1/healthz").await;
+        assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), idle.read(&mut buf));
+        // closed, rather than waiting for a handshake
+        assert_eq!(read.await.unwrap().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn reload_certificate() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let config = use_cert(&dir, "ec");
+        let check_every = Duration::from_millis(50);
+        let acceptor = Acceptor::with_timings(&config, check_every, Duration::from_secs(10));
+        let addr = serve_tls(make_db(&dir, None), acceptor.unwrap()).await;
+
+        let conn = connect(addr, LOCALHOST.into(), "ec").await.unwrap();
+        let resp = http_get(conn, "/v1/healthz").await;
+        assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+
+        use_cert(&dir, "rsa");
+        let mut reloaded = None;
+        for _ in 0..100 {
+            tokio::time::delay_for(check_every).await;
+            if let Ok(conn) = connect(addr, LOCALHOST.into(), "rsa").await {
+                reloaded.replace(conn);
+                break;
+            }
+        }
+        let conn = reloaded.expect("the new certificate wasn't served");
+        let resp = http_get(conn, "/v1/healthz").await;
+        assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+
+        assert!(connect(addr, LOCALHOST.into(), "ec").await.is_err());
+    }
+
+    // only linux routes all of 127.0.0.0/8 to the loopback interface
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn clients_have_their_own_rate_limits() {
+        let dir = TempDir::new("brain_tests").unwrap();
+        let mut db = make_db(&dir, LOREM_IPSUM);
+        let limits = &mut Arc::get_mut(&mut db).unwrap().settings.rate_limits;
+        limits.client.generate.replace(config::Limit {
+            per_second: 0.1,
+            burst: 1,
+        });
+        let acceptor = Acceptor::new(&use_cert(&dir, "ec")).unwrap();
+        let addr = serve_tls(db, acceptor).await;
+
+        let generate = |from: [u8; 4]| async move {
+            let conn = connect(addr, from.into(), "ec").await.unwrap();
+            http_get(conn, "/v1/brains/test1/generate").await
+        };
+        let resp = generate(LOCALHOST).await;
+        assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+        let resp = generate(LOCALHOST).await;
+        assert!(resp.starts_with("HTTP/1.1 429"), "{}", resp);
+
+        let resp = generate([127, 0, 0, 2]).await;
+        assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+    }
+}
@@ -0,0 +1,189 @@
+use super::server::Connection;
+use crate::config::TlsConfig;
+
+use futures::prelude::*;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::{
+    internal::pemfile, sign, ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+// how often the certificate and key are checked for changes
+const CHECK_EVERY: Duration = Duration::from_secs(10);
+
+// connections that take longer than this to handshake are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Does the TLS handshakes for a listener's connections
+pub struct Acceptor {
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+}
+
+impl Acceptor {
+    /// Loads the certificate, and starts watching its files for changes
+    pub fn new(config: &TlsConfig) -> anyhow::Result<Self> {
+        Self::build(config, CHECK_EVERY, HANDSHAKE_TIMEOUT)
+    }
+
+    /// `new`, with shorter waits so the tests don't have to sleep for long
+    #[cfg(test)]
+    pub fn with_timings(
+        config: &TlsConfig,
+        check_every: Duration,
+        handshake_timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        Self::build(config, check_every, handshake_timeout)
+    }
+
+    fn build(
+        config: &TlsConfig,
+        check_every: Duration,
+        handshake_timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        // taken before loading, so a change made while loading is reloaded
+        let modified = mtimes(config);
+        let resolver = Arc::new(Resolver {
+            key: RwLock::new(load(config)?),
+            config: config.clone(),
+        });
+        tokio::spawn(Arc::clone(&resolver).watch(check_every, modified));
+
+        let mut server = ServerConfig::new(NoClientAuth::new());
+        server.cert_resolver = resolver;
+        server.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(server)),
+            handshake_timeout,
+        })
+    }
+
+    /// The connections that finished their handshakes
+    ///
+    /// Handshakes happen concurrently, so a slow client doesn't hold up the
+    /// others. The ones that fail are only logged.
+    pub fn accept<'a, S, C>(self, incoming: S) -> impl Stream<Item = io::Result<TlsStream<C>>> + 'a
+    where
+        S: Stream<Item = io::Result<C>> + Send + 'a,
+        C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let handshakes = incoming
+            .for_each(move |conn| {
+                let (acceptor, tx) = (self.acceptor.clone(), tx.clone());
+                let timeout = self.handshake_timeout;
+                async move {
+                    let conn = match conn {
+                        Ok(conn) => conn,
+                        Err(err) => {
+                            let _ = tx.send(Err(err));
+                            return;
+                        }
+                    };
+                    tokio::spawn(async move {
+                        let handshake = acceptor.accept(conn);
+                        match tokio::time::timeout(timeout, handshake).await {
+                            Ok(Ok(stream)) => {
+                                let _ = tx.send(Ok(stream));
+                            }
+                            Ok(Err(err)) => {
+                                log::debug!(target: "brain", "tls handshake failed: {}", err)
+                            }
+                            Err(..) => log::debug!(target: "brain", "tls handshake timed out"),
+                        }
+                    });
+                }
+            })
+            .into_stream()
+            .filter_map(|_| future::ready(None));
+
+        stream::select(rx, handshakes)
+    }
+}
+
+impl<C: Connection> Connection for TlsStream<C> {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().0.remote_addr()
+    }
+}
+
+/// Hands out the current certificate, which `watch` replaces when it changes
+struct Resolver {
+    config: TlsConfig,
+    key: RwLock<sign::CertifiedKey>,
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _: ClientHello) -> Option<sign::CertifiedKey> {
+        Some(self.key.read().unwrap().clone())
+    }
+}
+
+impl Resolver {
+    async fn watch(
+        self: Arc<Self>,
+        every: Duration,
+        mut modified: Option<(SystemTime, SystemTime)>,
+    ) {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            let current = mtimes(&self.config);
+            if current == modified {
+                continue;
+            }
+            modified = current;
+
+            // the old certificate is kept until both files can be read
+            match load(&self.config) {
+                Ok(key) => {
+                    *self.key.write().unwrap() = key;
+                    log::info!(target: "brain", "reloaded the tls certificate");
+                }
+                Err(err) => {
+                    log::error!(target: "brain", "cannot reload the tls certificate: {}", err)
+                }
+            }
+        }
+    }
+}
+
+// when the certificate and key were last changed
+fn mtimes(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+    Some((modified(&config.cert)?, modified(&config.key)?))
+}
+
+fn load(config: &TlsConfig) -> anyhow::Result<sign::CertifiedKey> {
+    let read = |path: &std::path::Path| {
+        std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("cannot read '{}': {}", path.display(), err))
+    };
+
+    let certs = pemfile::certs(&mut &*read(&config.cert)?).unwrap_or_default();
+    if certs.is_empty() {
+        anyhow::bail!("'{}' has no certificates", config.cert.display())
+    }
+
+    let pem = read(&config.key)?;
+    let key = pemfile::pkcs8_private_keys(&mut &*pem)
+        .ok()
+        .filter(|keys| !keys.is_empty())
+        .or_else(|| pemfile::rsa_private_keys(&mut &*pem).ok())
+        .and_then(|keys| keys.into_iter().next())
+        .ok_or_else(|| anyhow::anyhow!("'{}' has no private key", config.key.display()))?;
+    let key = sign::any_supported_type(&key)
+        .map_err(|_| anyhow::anyhow!("'{}' isn't a supported private key", config.key.display()))?;
+
+    Ok(sign::CertifiedKey::new(certs, Arc::new(key)))
+}
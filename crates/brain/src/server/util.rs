@@ -1,12 +1,15 @@
-use super::models::Error;
-use super::server::{BrainDb, Topics};
+use super::models::{input, Error};
+use crate::auth::{Auth, Grant};
+use crate::config::Scope;
+use crate::{BrainDb, BrainManager};
 
-use std::path::PathBuf;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use serde::{de::DeserializeOwned, Serialize};
 use warp::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     reject::{self, Reject},
     reply, Filter, Rejection, Reply,
 };
@@ -14,14 +17,19 @@ use warp::{
 type JsonResult = Result<reply::WithStatus<reply::Json>, Rejection>;
 
 #[derive(Debug)]
-struct BadRequest {
+struct ApiError {
     error: Error,
 }
 
-impl Reject for BadRequest {}
+impl Reject for ApiError {}
 
 pub fn error(error: Error) -> JsonResult {
-    Err(reject::custom(BadRequest { error }))
+    Err(rejection(error))
+}
+
+/// Rejects with the error, for handlers that don't reply with JSON
+pub fn rejection(error: Error) -> Rejection {
+    reject::custom(ApiError { error })
 }
 
 pub fn okay<T>(item: T) -> JsonResult
@@ -31,48 +39,298 @@ where
     Ok(reply::with_status(reply::json(&item), StatusCode::OK))
 }
 
-pub fn json_body<T>() -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+pub fn reply<T>(result: Result<T, Error>) -> JsonResult
+where
+    T: Serialize,
+{
+    match result {
+        Ok(item) => okay(item),
+        Err(err) => error(err),
+    }
+}
+
+/// Replies with `202 Accepted`, for work that carries on in the background
+pub fn accepted<T>(result: Result<T, Error>) -> JsonResult
+where
+    T: Serialize,
+{
+    match result {
+        Ok(item) => Ok(reply::with_status(reply::json(&item), StatusCode::ACCEPTED)),
+        Err(err) => error(err),
+    }
+}
+
+pub fn json_body<T>(limit: u64) -> impl Filter<Extract = (T,), Error = Rejection> + Clone
 where
     T: Send + DeserializeOwned,
 {
-    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    warp::body::content_length_limit(limit).and(warp::body::json())
+}
+
+/// A batch of lines to train with
+///
+/// JSON bodies are a `TrainBatch`, and plain text bodies have an item per line.
+pub fn batch_body(
+    limit: u64,
+) -> impl Filter<Extract = (input::TrainBatch,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(limit)
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::body::bytes())
+        .and_then(
+            |content_type: Option<String>, body: bytes::Bytes| async move {
+                parse_batch(content_type.as_deref().unwrap_or_default(), &body).map_err(rejection)
+            },
+        )
+}
+
+fn parse_batch(content_type: &str, body: &[u8]) -> Result<input::TrainBatch, Error> {
+    let invalid = |reason: String| Error::InvalidBody { reason };
+    let mime = content_type.split(';').next().unwrap_or_default();
+    match mime.trim().to_ascii_lowercase().as_str() {
+        // like `json_body`, a missing content type is taken to be json
+        "" | "application/json" => {
+            serde_json::from_slice(body).map_err(|err| invalid(err.to_string()))
+        }
+        "text/plain" => {
+            let body = std::str::from_utf8(body).map_err(|err| invalid(err.to_string()))?;
+            let data = body
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(ToString::to_string)
+                .collect();
+            Ok(input::TrainBatch { data })
+        }
+        _ => Err(Error::UnsupportedMediaType),
+    }
+}
+
+/// Whether an upload is gzipped, from its `content-type` and `content-encoding`
+///
+/// Uploads are plain text otherwise.
+pub fn is_gzip(content_type: Option<&str>, encoding: Option<&str>) -> Result<bool, Error> {
+    let mime = content_type
+        .and_then(|ty| ty.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let gzip = match mime.as_str() {
+        "application/gzip" | "application/x-gzip" => true,
+        "" | "text/plain" | "application/octet-stream" => false,
+        _ => return Err(Error::UnsupportedMediaType),
+    };
+
+    match encoding
+        .map(|enc| enc.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        None | Some("identity") => Ok(gzip),
+        Some("gzip") | Some("x-gzip") => Ok(true),
+        Some(..) => Err(Error::UnsupportedMediaType),
+    }
 }
 
+fn error_reply(error: &Error) -> reply::Response {
+    let status = StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::BAD_REQUEST);
+    let mut resp = reply::with_status(reply::json(error), status).into_response();
+    let headers = resp.headers_mut();
+    match error {
+        Error::Unauthorized => {
+            let challenge = HeaderValue::from_static("Bearer");
+            headers.insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        Error::RateLimited { retry_after } => {
+            // in whole seconds, so it is rounded up
+            let secs = retry_after.ceil().max(1.0) as u64;
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        _ => {}
+    }
+    resp
+}
+
+/// Turns our errors into JSON replies
+///
+/// Other rejections are passed along so the next route can be tried.
 pub async fn recover(err: Rejection) -> Result<impl Reply, Rejection> {
-    if let Some(BadRequest { error }) = err.find() {
-        return Ok(reply::with_status(
-            reply::json(&error),
-            StatusCode::BAD_REQUEST,
-        ));
+    if let Some(ApiError { error }) = err.find() {
+        return Ok(error_reply(error));
     }
 
     Err(err)
 }
 
-pub async fn filter(topics: Arc<Topics>, name: String) -> Result<BrainDb, Rejection> {
-    topics
-        .brains
-        .lock()
-        .await
-        .get(&name)
-        .map(Arc::clone)
-        .ok_or_else(reject::not_found)
+/// Turns every rejection left after trying all of the routes into a JSON reply
+pub async fn recover_all(err: Rejection) -> Result<impl Reply, Infallible> {
+    let error = if let Some(ApiError { error }) = err.find() {
+        error.clone()
+    } else if err.is_not_found() {
+        Error::UnknownRoute
+    } else if let Some(err) = err.find::<warp::body::BodyDeserializeError>() {
+        Error::InvalidBody {
+            reason: err.to_string(),
+        }
+    } else if err.find::<reject::InvalidQuery>().is_some() {
+        Error::InvalidQuery {
+            reason: "invalid query string".into(),
+        }
+    } else if let Some(err) = err.find::<reject::MissingHeader>() {
+        Error::InvalidHeader {
+            reason: err.to_string(),
+        }
+    } else if let Some(err) = err.find::<reject::InvalidHeader>() {
+        Error::InvalidHeader {
+            reason: err.to_string(),
+        }
+    } else if let Some(err) = err.find::<warp::ws::MissingConnectionUpgrade>() {
+        Error::InvalidHeader {
+            reason: err.to_string(),
+        }
+    } else if err.find::<reject::PayloadTooLarge>().is_some() {
+        Error::PayloadTooLarge
+    } else if err.find::<reject::LengthRequired>().is_some() {
+        Error::InvalidBody {
+            reason: "a content-length header is required".into(),
+        }
+    } else if err.find::<reject::UnsupportedMediaType>().is_some() {
+        Error::UnsupportedMediaType
+    } else if err.find::<reject::MethodNotAllowed>().is_some() {
+        Error::MethodNotAllowed
+    } else {
+        log::error!(target: "brain", "unhandled rejection: {:?}", err);
+        Error::Internal {
+            reason: format!("{:?}", err),
+        }
+    };
+
+    Ok(error_reply(&error))
+}
+
+/// Who made a request, and what their token grants
+#[derive(Clone)]
+pub struct Caller {
+    pub grant: Grant,
+    scope: Option<Scope>,
+    manager: Arc<BrainManager>,
+}
+
+/// The address of a connection's peer, which the server puts in the
+/// extensions of the connection's requests
+#[derive(Clone, Copy, Debug)]
+pub struct RemoteAddr(pub Option<SocketAddr>);
+
+/// Extracts the address the request came from
+///
+/// warp only knows it for the connections it accepts itself, and the server
+/// accepts them instead, so it is taken from the extensions.
+pub fn remote() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone {
+    let peer = warp::ext::get::<RemoteAddr>()
+        .map(|RemoteAddr(addr)| addr)
+        .or(warp::any().map(|| None))
+        .unify();
+    warp::addr::remote()
+        .and(peer)
+        .map(|addr: Option<SocketAddr>, peer: Option<SocketAddr>| addr.or(peer))
+}
+
+/// Requires a bearer token with `scope`, extracting the caller
+///
+/// Any known token is enough when `scope` is `None`. Nothing is required when
+/// no tokens are configured. The caller's rate limit for `scope` is taken from
+/// here too.
+pub fn authorize(
+    manager: &Arc<BrainManager>,
+    scope: Option<Scope>,
+) -> impl Filter<Extract = (Caller,), Error = Rejection> + Clone {
+    let auth = Arc::new(Auth::new(&manager.settings().tokens));
+    let manager = Arc::clone(manager);
+    warp::header::optional::<String>("authorization")
+        .and(remote())
+        .and_then(move |header: Option<String>, addr: Option<SocketAddr>| {
+            let (auth, manager) = (Arc::clone(&auth), Arc::clone(&manager));
+            async move {
+                let grant = auth.grant(header.as_deref()).map_err(rejection)?;
+                let grant = grant.with_address(addr.map(|addr| addr.ip()));
+                if let Some(scope) = scope {
+                    grant.scope(scope).map_err(rejection)?;
+                    manager
+                        .limit_client(scope, &grant.client())
+                        .map_err(rejection)?;
+                }
+                Ok::<_, Rejection>(Caller {
+                    grant,
+                    scope,
+                    manager,
+                })
+            }
+        })
+}
+
+/// Extracts who sent the request, for the audit logs
+///
+/// This is the name of the token, or the address without tokens. It goes
+/// after `authorize`, which has already checked the token.
+pub fn source(
+    manager: &Arc<BrainManager>,
+) -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    let auth = Arc::new(Auth::new(&manager.settings().tokens));
+    warp::header::optional::<String>("authorization")
+        .and(remote())
+        .map(move |header: Option<String>, addr: Option<SocketAddr>| {
+            auth.grant(header.as_deref())
+                .map(|grant| grant.with_address(addr.map(|addr| addr.ip())).client())
+                .unwrap_or_else(|_| "unknown".into())
+        })
+}
+
+/// Passes the name along if the caller can use that brain, and it isn't over
+/// its rate limit
+pub async fn allowed(name: String, caller: Caller) -> Result<String, Rejection> {
+    caller.grant.brain(&name).map_err(rejection)?;
+    if let Some(scope) = caller.scope {
+        caller
+            .manager
+            .limit_brain(scope, &name)
+            .map_err(rejection)?;
+    }
+    Ok(name)
+}
+
+/// Like `allowed`, for routes whose body names another brain, which the
+/// caller must be allowed to use too
+pub async fn allowed_both(name: String, caller: Caller, other: &str) -> Result<String, Rejection> {
+    caller.grant.brain(other).map_err(rejection)?;
+    allowed(name, caller).await
+}
+
+pub async fn filter(manager: Arc<BrainManager>, name: String) -> Result<BrainDb, Rejection> {
+    match manager.get(&name).await {
+        Some(brain) => Ok(brain),
+        None => Err(reject::custom(ApiError {
+            error: manager.missing(&name).await,
+        })),
+    }
 }
 
 pub async fn expect_unique(
-    topics: Arc<Topics>,
+    manager: Arc<BrainManager>,
     name: String,
-) -> Result<(Arc<Topics>, String), Rejection> {
-    if topics.brains.lock().await.contains_key(&name) {
-        return Err(reject::custom(BadRequest {
+) -> Result<(Arc<BrainManager>, String), Rejection> {
+    if manager.contains(&name).await || manager.is_loading(&name).await {
+        return Err(reject::custom(ApiError {
             error: Error::AlreadyExists { name },
         }));
     }
-    Ok((topics, name))
+    Ok((manager, name))
 }
 
-pub async fn rotate(input: &PathBuf) -> std::io::Result<()> {
-    let mut new = input.to_owned();
-    new.set_extension("bak");
-    tokio::fs::rename(&input, new).await
+pub async fn expect_existing(
+    manager: Arc<BrainManager>,
+    name: String,
+) -> Result<(Arc<BrainManager>, String), Rejection> {
+    if !manager.contains(&name).await {
+        return Err(reject::custom(ApiError {
+            error: manager.missing(&name).await,
+        }));
+    }
+    Ok((manager, name))
 }
@@ -0,0 +1,343 @@
+use crate::config::{Config, GenerateConfig, LogConfig, Scope};
+use hashbrown::HashMap;
+use types::is_valid_name;
+
+/// A problem found in a config file, with its location when it can be found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub line: Option<usize>,
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}: {}", line, self.path, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+struct Issues<'a> {
+    source: &'a str,
+    list: Vec<Issue>,
+}
+
+impl<'a> Issues<'a> {
+    fn push(&mut self, table: &str, key: Option<&str>, message: impl ToString) {
+        let path = match key {
+            Some(key) => format!("{}.{}", table, key),
+            None => table.to_string(),
+        };
+        self.list.push(Issue {
+            line: locate(self.source, table, key),
+            path,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// Checks the parts of the config that deserializing can't
+///
+/// `source` is the text the config was parsed from, and is only used to find
+/// line numbers for the issues.
+pub fn validate(config: &Config, source: &str) -> Vec<Issue> {
+    let mut issues = Issues {
+        source,
+        list: vec![],
+    };
+
+    let server = &config.server;
+    if let Err(err) = server.address() {
+        let message = format!("invalid address '{}': {}", server.host, err);
+        issues.push("server", Some("host"), message);
+    }
+    if server.body_limit == 0 {
+        issues.push("server", Some("body_limit"), "must be greater than zero");
+    }
+    if server.batch_limit == 0 {
+        issues.push("server", Some("batch_limit"), "must be greater than zero");
+    }
+    if server.batch_body_limit == 0 {
+        issues.push("server", Some("batch_body_limit"), "must be greater than zero");
+    }
+    if server.defaults.max == 0 {
+        issues.push("server.defaults", Some("max"), "must be greater than zero");
+    }
+    if server.defaults.min > server.defaults.max {
+        let message = format!("is greater than max ({})", server.defaults.max);
+        issues.push("server.defaults", Some("min"), message);
+    }
+    let defaults = &server.defaults;
+    check_limits(
+        &mut issues,
+        "server.defaults",
+        defaults.max_words,
+        defaults.hard_max_words,
+    );
+
+    let mut tokens = server.tokens.iter().collect::<Vec<_>>();
+    tokens.sort_by_key(|(name, _)| *name);
+
+    let mut seen = HashMap::<_, &str>::new();
+    for (name, token) in tokens {
+        let table = format!("server.tokens.{}", name);
+        if token.token.trim().is_empty() {
+            issues.push(&table, Some("token"), "must not be empty");
+        } else if let Some(other) = seen.insert(token.token.as_str(), name) {
+            let message = format!("is the same as the token for '{}'", other);
+            issues.push(&table, Some("token"), message);
+        }
+
+        let brains = token.brains.iter().flatten();
+        for brain in brains.filter(|brain| !is_valid_name(brain)) {
+            let message = format!("'{}' isn't a valid brain name", brain);
+            issues.push(&table, Some("brains"), message);
+        }
+    }
+
+    let limits = &server.rate_limits;
+    for (table, limits) in &[("client", &limits.client), ("brain", &limits.brain)] {
+        let table = format!("server.rate_limits.{}", table);
+        for scope in &[Scope::Generate, Scope::Train, Scope::Save, Scope::Admin] {
+            let limit = match limits.get(*scope) {
+                Some(limit) => limit,
+                None => continue,
+            };
+            if !(limit.per_second.is_finite() && limit.per_second > 0.0) {
+                let message = "per_second must be greater than zero";
+                issues.push(&table, Some(scope.as_str()), message);
+            }
+            if limit.burst == 0 {
+                let message = "burst must be greater than zero";
+                issues.push(&table, Some(scope.as_str()), message);
+            }
+        }
+    }
+
+    if let Some(log) = &server.access_log {
+        check_log(&mut issues, "server.access_log", log);
+    }
+
+    if let Some(socket) = &server.unix_socket {
+        if socket.path.as_os_str().is_empty() {
+            issues.push("server.unix_socket", Some("path"), "must not be empty");
+        }
+        if let Err(message) = socket.mode() {
+            issues.push("server.unix_socket", Some("mode"), message);
+        }
+    }
+
+    if let Some(tls) = &server.tls {
+        if !cfg!(feature = "tls") {
+            issues.push("server.tls", None, "the server was built without tls support");
+        }
+        if tls.cert.as_os_str().is_empty() {
+            issues.push("server.tls", Some("cert"), "must not be empty");
+        }
+        if tls.key.as_os_str().is_empty() {
+            issues.push("server.tls", Some("key"), "must not be empty");
+        }
+    }
+
+    if let Some(cors) = &server.cors {
+        if cors.origins.is_empty() {
+            issues.push("server.cors", Some("origins"), "must not be empty");
+        }
+        for origin in cors.origins.iter().filter(|origin| !is_origin(origin)) {
+            let message = format!(
+                "'{}' must be '*', or a scheme and host like \"https://example.com\"",
+                origin
+            );
+            issues.push("server.cors", Some("origins"), message);
+        }
+    }
+
+    let mut names = config.brains.keys().collect::<Vec<_>>();
+    names.sort();
+
+    // a file with two writers would be rotated by both
+    let mut logs = HashMap::new();
+    if let Some(log) = &server.access_log {
+        logs.insert(&log.file, "server.access_log".to_string());
+    }
+
+    let mut files = HashMap::<_, Vec<_>>::new();
+    for name in names {
+        let brain = &config.brains[name];
+        let table = format!("brains.{}", name);
+
+        if !is_valid_name(name) {
+            issues.push(
+                &table,
+                None,
+                "names may only contain letters, digits, '-' and '_'",
+            );
+        }
+
+        if let Some(audit) = &brain.audit {
+            let audit_table = format!("{}.audit", table);
+            check_log(&mut issues, &audit_table, &audit.log);
+            match logs.get(&audit.log.file) {
+                _ if audit.log.file.as_os_str().is_empty() => {}
+                Some(other) => {
+                    let message = format!(
+                        "'{}' is already the log file of {}",
+                        audit.log.file.display(),
+                        other
+                    );
+                    issues.push(&audit_table, Some("file"), message);
+                }
+                None => {
+                    logs.insert(&audit.log.file, audit_table);
+                }
+            }
+        }
+
+        if let Some(generate) = &brain.generate {
+            check_generate(&mut issues, &format!("{}.generate", table), generate);
+        }
+
+        if brain.brain_file.as_os_str().is_empty() {
+            issues.push(&table, Some("brain_file"), "must not be empty");
+            continue;
+        }
+
+        files
+            .entry(&brain.brain_file)
+            .or_default()
+            .push((name, brain.read_only));
+    }
+
+    let mut shared = files
+        .into_iter()
+        .filter(|(_, brains)| brains.len() > 1 && brains.iter().any(|(_, ro)| !ro))
+        .collect::<Vec<_>>();
+    shared.sort();
+
+    for (file, brains) in shared {
+        let names = brains
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        for name in &names {
+            let message = format!(
+                "'{}' is shared by {} and at least one of them is writable",
+                file.display(),
+                names.join(", ")
+            );
+            issues.push(&format!("brains.{}", name), Some("brain_file"), message);
+        }
+    }
+
+    issues.list
+}
+
+/// The issues `validate` finds with one brain, such as an invalid name or a
+/// brain file another brain uses
+pub fn validate_brain(config: &Config, source: &str, name: &str) -> Vec<Issue> {
+    let table = format!("brains.{}", name);
+    let prefix = format!("{}.", table);
+    let mut issues = validate(config, source);
+    issues.retain(|issue| issue.path == table || issue.path.starts_with(&prefix));
+    issues
+}
+
+// browsers send the scheme, host and port, without a path
+fn is_origin(origin: &str) -> bool {
+    if origin == "*" {
+        return true;
+    }
+    let host = match origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+    {
+        Some(host) => host,
+        None => return false,
+    };
+    !host.is_empty() && !host.contains('/')
+}
+
+fn check_generate(issues: &mut Issues<'_>, table: &str, generate: &GenerateConfig) {
+    if generate.max == Some(0) {
+        issues.push(table, Some("max"), "must be greater than zero");
+    }
+    if let (Some(min), Some(max)) = (generate.min, generate.max) {
+        if min > max {
+            issues.push(table, Some("min"), format!("is greater than max ({})", max));
+        }
+    }
+    if generate.batch_limit == Some(0) {
+        issues.push(table, Some("batch_limit"), "must be greater than zero");
+    }
+    check_limits(issues, table, generate.max_words, generate.hard_max_words);
+}
+
+fn check_limits(
+    issues: &mut Issues<'_>,
+    table: &str,
+    max_words: Option<usize>,
+    hard_max_words: Option<usize>,
+) {
+    if max_words == Some(0) {
+        issues.push(table, Some("max_words"), "must be greater than zero");
+    }
+    if hard_max_words == Some(0) {
+        issues.push(table, Some("hard_max_words"), "must be greater than zero");
+    }
+    if let (Some(soft), Some(hard)) = (max_words, hard_max_words) {
+        if soft > hard {
+            let message = format!("is greater than hard_max_words ({})", hard);
+            issues.push(table, Some("max_words"), message);
+        }
+    }
+}
+
+fn check_log(issues: &mut Issues<'_>, table: &str, log: &LogConfig) {
+    if log.file.as_os_str().is_empty() {
+        issues.push(table, Some("file"), "must not be empty");
+    }
+    if log.max_size == 0 {
+        issues.push(table, Some("max_size"), "must be greater than zero");
+    }
+}
+
+// finds the line of a [table] header, or of a key inside of it
+fn locate(source: &str, table: &str, key: Option<&str>) -> Option<usize> {
+    fn header(line: &str) -> Option<String> {
+        let line = line.trim();
+        if !line.starts_with('[') || line.starts_with("[[") {
+            return None;
+        }
+        let end = line.find(']')?;
+        let name = line[1..end]
+            .split('.')
+            .map(|part| part.trim().trim_matches(|c| c == '"' || c == '\''))
+            .collect::<Vec<_>>()
+            .join(".");
+        Some(name)
+    }
+
+    let mut lines = source.lines().enumerate();
+    let (start, _) = lines
+        .by_ref()
+        .find(|(_, line)| header(line).as_deref() == Some(table))?;
+
+    let key = match key {
+        Some(key) => key,
+        None => return Some(start + 1),
+    };
+
+    for (n, line) in lines {
+        if header(line).is_some() {
+            break;
+        }
+        let line = line.trim_start();
+        if line.starts_with(key) && line[key.len()..].trim_start().starts_with('=') {
+            return Some(n + 1);
+        }
+    }
+
+    Some(start + 1)
+}
@@ -0,0 +1,224 @@
+use crate::requests::*;
+use crate::Result;
+use types::{input, responses};
+
+/// The words of a generated sentence, in the order they were picked
+pub type WordStream = futures::stream::BoxStream<'static, Result<String>>;
+
+/// A backend that can serve brain requests
+///
+/// The request builders are backend-agnostic, so the same code can run against
+/// the HTTP [`Client`](crate::Client) or an in-process backend.
+#[async_trait::async_trait]
+pub trait BrainApi: Send + Sync {
+    async fn send_generate(
+        &self,
+        brain: &str,
+        opts: input::GenerateOptions,
+    ) -> Result<responses::Generated>;
+
+    async fn send_generate_stream(
+        &self,
+        brain: &str,
+        opts: input::GenerateOptions,
+    ) -> Result<WordStream>;
+
+    async fn send_generate_batch(
+        &self,
+        brain: &str,
+        opts: input::GenerateBatchOptions,
+    ) -> Result<responses::GeneratedBatch>;
+
+    async fn send_train(&self, brain: &str, data: input::TrainData) -> Result<responses::Trained>;
+
+    async fn send_train_batch(
+        &self,
+        brain: &str,
+        data: input::TrainBatch,
+    ) -> Result<responses::TrainedBatch>;
+
+    async fn send_new_brain(
+        &self,
+        brain: &str,
+        input: input::NewBrain,
+    ) -> Result<responses::Created>;
+
+    async fn send_rename(
+        &self,
+        brain: &str,
+        input: input::RenameBrain,
+    ) -> Result<responses::Renamed>;
+
+    async fn send_copy(&self, brain: &str, input: input::CopyBrain) -> Result<responses::Copied>;
+
+    async fn send_save(&self, brain: &str) -> Result<responses::Saved>;
+
+    async fn send_list(&self) -> Result<responses::List>;
+
+    async fn send_info(&self, brain: &str) -> Result<responses::ListItem>;
+
+    async fn send_delete(
+        &self,
+        brain: &str,
+        opts: input::DeleteOptions,
+    ) -> Result<responses::Deleted>;
+
+    async fn send_reload(&self, brain: &str) -> Result<responses::Reloaded>;
+
+    async fn send_unload(&self, brain: &str) -> Result<responses::Unloaded>;
+
+    async fn send_reload_config(&self) -> Result<responses::ConfigReloaded>;
+
+    async fn send_upload(&self, brain: &str, data: Vec<u8>, gzip: bool) -> Result<responses::Job>;
+
+    async fn send_job(&self, id: u64) -> Result<responses::Job>;
+
+    async fn send_cancel_job(&self, id: u64) -> Result<responses::Job>;
+}
+
+impl<'a> dyn BrainApi + 'a {
+    pub fn generate(&self, brain: impl ToString) -> GenerateRequest<'_> {
+        GenerateRequest {
+            api: self,
+            brain: brain.to_string(),
+            context: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Generates `count` sentences in one request
+    pub fn generate_batch(&self, brain: impl ToString, count: usize) -> GenerateBatchRequest<'_> {
+        GenerateBatchRequest {
+            api: self,
+            brain: brain.to_string(),
+            count,
+            context: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn train(&self, brain: impl ToString, data: impl ToString) -> TrainRequest<'_> {
+        TrainRequest {
+            api: self,
+            brain: brain.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    /// Trains with every line in one request
+    pub fn train_batch<I>(&self, brain: impl ToString, lines: I) -> TrainBatchRequest<'_>
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        TrainBatchRequest {
+            api: self,
+            brain: brain.to_string(),
+            data: lines.into_iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    pub fn new_brain(
+        &self,
+        brain: impl ToString,
+        brain_file: impl ToString,
+    ) -> NewBrainRequest<'_> {
+        NewBrainRequest {
+            api: self,
+            brain: brain.to_string(),
+            brain_file: brain_file.to_string(),
+            depth: None,
+        }
+    }
+
+    /// Renames `brain` to `name`
+    pub fn rename(&self, brain: impl ToString, name: impl ToString) -> RenameRequest<'_> {
+        RenameRequest {
+            api: self,
+            brain: brain.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Copies `brain` to a new brain called `name`, saved to `brain_file`
+    pub fn copy(
+        &self,
+        brain: impl ToString,
+        name: impl ToString,
+        brain_file: impl ToString,
+    ) -> CopyRequest<'_> {
+        CopyRequest {
+            api: self,
+            brain: brain.to_string(),
+            name: name.to_string(),
+            brain_file: brain_file.to_string(),
+            depth: None,
+        }
+    }
+
+    pub fn save(&self, brain: impl ToString) -> SaveRequest<'_> {
+        SaveRequest {
+            api: self,
+            brain: brain.to_string(),
+        }
+    }
+
+    pub fn list(&self) -> ListRequest<'_> {
+        ListRequest { api: self }
+    }
+
+    /// Describes the brain, with the metadata from its brain file
+    pub fn info(&self, brain: impl ToString) -> InfoRequest<'_> {
+        InfoRequest {
+            api: self,
+            brain: brain.to_string(),
+        }
+    }
+
+    pub fn delete(&self, brain: impl ToString) -> DeleteRequest<'_> {
+        DeleteRequest {
+            api: self,
+            brain: brain.to_string(),
+            delete_file: None,
+            keep_config: None,
+        }
+    }
+
+    pub fn reload(&self, brain: impl ToString) -> ReloadRequest<'_> {
+        ReloadRequest {
+            api: self,
+            brain: brain.to_string(),
+        }
+    }
+
+    pub fn unload(&self, brain: impl ToString) -> UnloadRequest<'_> {
+        UnloadRequest {
+            api: self,
+            brain: brain.to_string(),
+        }
+    }
+
+    pub fn reload_config(&self) -> ReloadConfigRequest<'_> {
+        ReloadConfigRequest { api: self }
+    }
+
+    /// Trains `brain` with `data` in a background job on the server
+    pub fn upload(&self, brain: impl ToString, data: impl Into<Vec<u8>>) -> UploadRequest<'_> {
+        UploadRequest {
+            api: self,
+            brain: brain.to_string(),
+            data: data.into(),
+            gzip: false,
+        }
+    }
+
+    pub fn job(&self, id: u64) -> JobRequest<'_> {
+        JobRequest { api: self, id }
+    }
+
+    pub fn cancel_job(&self, id: u64) -> CancelJobRequest<'_> {
+        CancelJobRequest { api: self, id }
+    }
+}
@@ -1,10 +1,82 @@
+use std::time::Duration;
+
+// the longest the server is believed about how long to wait, in seconds
+const MAX_WAIT: f64 = 60.0 * 60.0;
+
 #[derive(Debug)]
 pub enum Error {
     NoBrainProvided,
     NoDataProvided,
     NoBrainFileProvided,
+    NotFound { name: String },
+    UnknownRoute,
+    InvalidBody { reason: String },
+    InvalidQuery { reason: String },
+    InvalidHeader { reason: String },
+    PayloadTooLarge,
+    MethodNotAllowed,
+    UnsupportedMediaType,
+    RateLimited { retry_after: Duration },
     Server { err: types::Error },
+    Unexpected { status: u16, body: String },
+    WebSocket { reason: String },
     Client { err: reqwest::Error },
+    Unix { reason: String },
+}
+
+impl Error {
+    /// The error the server responded with, if any
+    pub fn server_error(&self) -> Option<types::Error> {
+        let err = match self {
+            Error::NotFound { name } => types::Error::NotFound { name: name.clone() },
+            Error::UnknownRoute => types::Error::UnknownRoute,
+            Error::InvalidBody { reason } => types::Error::InvalidBody {
+                reason: reason.clone(),
+            },
+            Error::InvalidQuery { reason } => types::Error::InvalidQuery {
+                reason: reason.clone(),
+            },
+            Error::InvalidHeader { reason } => types::Error::InvalidHeader {
+                reason: reason.clone(),
+            },
+            Error::PayloadTooLarge => types::Error::PayloadTooLarge,
+            Error::MethodNotAllowed => types::Error::MethodNotAllowed,
+            Error::UnsupportedMediaType => types::Error::UnsupportedMediaType,
+            Error::RateLimited { retry_after } => types::Error::RateLimited {
+                retry_after: retry_after.as_secs_f64(),
+            },
+            Error::Server { err } => err.clone(),
+            _ => return None,
+        };
+        Some(err)
+    }
+}
+
+impl From<types::Error> for Error {
+    fn from(err: types::Error) -> Self {
+        match err {
+            types::Error::NotFound { name } => Error::NotFound { name },
+            types::Error::UnknownRoute => Error::UnknownRoute,
+            types::Error::InvalidBody { reason } => Error::InvalidBody { reason },
+            types::Error::InvalidQuery { reason } => Error::InvalidQuery { reason },
+            types::Error::InvalidHeader { reason } => Error::InvalidHeader { reason },
+            types::Error::PayloadTooLarge => Error::PayloadTooLarge,
+            types::Error::MethodNotAllowed => Error::MethodNotAllowed,
+            types::Error::UnsupportedMediaType => Error::UnsupportedMediaType,
+            types::Error::RateLimited { retry_after } => {
+                // a negative or invalid wait is no wait at all
+                let secs = if retry_after > 0.0 {
+                    retry_after.min(MAX_WAIT)
+                } else {
+                    0.0
+                };
+                Error::RateLimited {
+                    retry_after: Duration::from_secs_f64(secs),
+                }
+            }
+            err => Error::Server { err },
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -13,9 +85,25 @@ impl std::fmt::Display for Error {
             Error::NoBrainProvided => f.write_str("No brain provided"),
             Error::NoDataProvided => f.write_str("No data provided"),
             Error::NoBrainFileProvided => f.write_str("No brain file provided"),
+            Error::NotFound { name } => write!(f, "brain not found: {}", name),
+            Error::UnknownRoute => f.write_str("unknown route"),
+            Error::InvalidBody { reason } => write!(f, "invalid body: {}", reason),
+            Error::InvalidQuery { reason } => write!(f, "invalid query: {}", reason),
+            Error::InvalidHeader { reason } => write!(f, "invalid header: {}", reason),
+            Error::PayloadTooLarge => f.write_str("payload too large"),
+            Error::MethodNotAllowed => f.write_str("method not allowed"),
+            Error::UnsupportedMediaType => f.write_str("unsupported media type"),
+            Error::RateLimited { retry_after } => {
+                write!(f, "rate limited, retry after {:.2?}", retry_after)
+            }
             // TODO
             Error::Server { err } => write!(f, "server error: {:?}", err),
+            Error::Unexpected { status, body } => {
+                write!(f, "unexpected response ({}): {}", status, body)
+            }
+            Error::WebSocket { reason } => write!(f, "websocket error: {}", reason),
             Error::Client { err } => write!(f, "client error: {}", err),
+            Error::Unix { reason } => write!(f, "unix socket error: {}", reason),
         }
     }
 }
@@ -24,12 +112,7 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::Client { err } => Some(err),
-
-            // TODO
-            Error::Server { .. } => None,
-            Error::NoBrainProvided => None,
-            Error::NoDataProvided => None,
-            Error::NoBrainFileProvided => None,
+            _ => None,
         }
     }
 }
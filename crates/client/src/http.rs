@@ -0,0 +1,298 @@
+use crate::{BrainApi, Client, Error, Result, WordStream};
+use types::{input, responses};
+
+use futures::{Stream, StreamExt as _};
+use reqwest::Method;
+
+#[async_trait::async_trait]
+impl BrainApi for Client {
+    async fn send_generate(
+        &self,
+        brain: &str,
+        opts: input::GenerateOptions,
+    ) -> Result<responses::Generated> {
+        let req = self
+            .request(
+                Method::GET,
+                &format!("{}/v1/brains/{}/generate", self.host, brain),
+            )
+            .query(&opts);
+        self.send_json(req).await
+    }
+
+    async fn send_generate_stream(
+        &self,
+        brain: &str,
+        opts: input::GenerateOptions,
+    ) -> Result<WordStream> {
+        let req = self
+            .request(
+                Method::GET,
+                &format!("{}/v1/brains/{}/generate/stream", self.host, brain),
+            )
+            .query(&opts);
+        let resp = self.send(req).await?;
+        Ok(words(resp).boxed())
+    }
+
+    async fn send_generate_batch(
+        &self,
+        brain: &str,
+        opts: input::GenerateBatchOptions,
+    ) -> Result<responses::GeneratedBatch> {
+        let req = self
+            .request(
+                Method::GET,
+                &format!("{}/v1/brains/{}/generate/batch", self.host, brain),
+            )
+            .query(&opts);
+        self.send_json(req).await
+    }
+
+    async fn send_train(&self, brain: &str, data: input::TrainData) -> Result<responses::Trained> {
+        let req = self
+            .request(
+                Method::POST,
+                &format!("{}/v1/brains/{}/train", self.host, brain),
+            )
+            .json(&data);
+        self.send_json(req).await
+    }
+
+    async fn send_train_batch(
+        &self,
+        brain: &str,
+        data: input::TrainBatch,
+    ) -> Result<responses::TrainedBatch> {
+        let req = self
+            .request(
+                Method::POST,
+                &format!("{}/v1/brains/{}/train/batch", self.host, brain),
+            )
+            .json(&data);
+        self.send_json(req).await
+    }
+
+    async fn send_new_brain(
+        &self,
+        brain: &str,
+        input: input::NewBrain,
+    ) -> Result<responses::Created> {
+        let req = self
+            .request(Method::POST, &format!("{}/v1/brains/{}", self.host, brain))
+            .json(&input);
+        self.send_json(req).await
+    }
+
+    async fn send_rename(
+        &self,
+        brain: &str,
+        input: input::RenameBrain,
+    ) -> Result<responses::Renamed> {
+        let req = self
+            .request(
+                Method::POST,
+                &format!("{}/v1/brains/{}/rename", self.host, brain),
+            )
+            .json(&input);
+        self.send_json(req).await
+    }
+
+    async fn send_copy(&self, brain: &str, input: input::CopyBrain) -> Result<responses::Copied> {
+        let req = self
+            .request(
+                Method::POST,
+                &format!("{}/v1/brains/{}/copy", self.host, brain),
+            )
+            .json(&input);
+        self.send_json(req).await
+    }
+
+    async fn send_save(&self, brain: &str) -> Result<responses::Saved> {
+        let req = self.request(
+            Method::POST,
+            &format!("{}/v1/brains/{}/save", self.host, brain),
+        );
+        self.send_json(req).await
+    }
+
+    async fn send_list(&self) -> Result<responses::List> {
+        let url = format!("{}/v1/brains", self.host);
+        self.send_json(self.request(Method::GET, &url)).await
+    }
+
+    async fn send_info(&self, brain: &str) -> Result<responses::ListItem> {
+        let url = format!("{}/v1/brains/{}", self.host, brain);
+        self.send_json(self.request(Method::GET, &url)).await
+    }
+
+    async fn send_delete(
+        &self,
+        brain: &str,
+        opts: input::DeleteOptions,
+    ) -> Result<responses::Deleted> {
+        let req = self
+            .request(
+                Method::DELETE,
+                &format!("{}/v1/brains/{}", self.host, brain),
+            )
+            .query(&opts);
+        self.send_json(req).await
+    }
+
+    async fn send_reload(&self, brain: &str) -> Result<responses::Reloaded> {
+        let req = self.request(
+            Method::POST,
+            &format!("{}/v1/brains/{}/reload", self.host, brain),
+        );
+        self.send_json(req).await
+    }
+
+    async fn send_unload(&self, brain: &str) -> Result<responses::Unloaded> {
+        let req = self.request(
+            Method::POST,
+            &format!("{}/v1/brains/{}/unload", self.host, brain),
+        );
+        self.send_json(req).await
+    }
+
+    async fn send_reload_config(&self) -> Result<responses::ConfigReloaded> {
+        let url = format!("{}/v1/config/reload", self.host);
+        self.send_json(self.request(Method::POST, &url)).await
+    }
+
+    async fn send_upload(&self, brain: &str, data: Vec<u8>, gzip: bool) -> Result<responses::Job> {
+        let content_type = if gzip {
+            "application/gzip"
+        } else {
+            "text/plain"
+        };
+        let req = self
+            .request(
+                Method::POST,
+                &format!("{}/v1/brains/{}/upload", self.host, brain),
+            )
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(data);
+        self.send_json(req).await
+    }
+
+    async fn send_job(&self, id: u64) -> Result<responses::Job> {
+        let url = format!("{}/v1/jobs/{}", self.host, id);
+        self.send_json(self.request(Method::GET, &url)).await
+    }
+
+    async fn send_cancel_job(&self, id: u64) -> Result<responses::Job> {
+        let url = format!("{}/v1/jobs/{}", self.host, id);
+        self.send_json(self.request(Method::DELETE, &url)).await
+    }
+}
+
+impl Client {
+    // every request is built here, so they all carry the token
+    fn request(&self, method: Method, url: &str) -> reqwest::RequestBuilder {
+        let req = self.client.request(method, url);
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    /// Sends the request, retrying it when it is rate limited if the client
+    /// was asked to
+    async fn send(&self, mut req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let mut retries = 0;
+        loop {
+            let retry = if retries < self.retries {
+                req.try_clone()
+            } else {
+                None
+            };
+
+            match (check_status(self.execute(req).await).await, retry) {
+                (Err(Error::RateLimited { retry_after }), Some(next)) => {
+                    tokio::time::delay_for(retry_after).await;
+                    retries += 1;
+                    req = next;
+                }
+                (resp, _) => return resp,
+            }
+        }
+    }
+
+    async fn execute(&self, req: reqwest::RequestBuilder) -> Response {
+        #[cfg(feature = "unix")]
+        {
+            if let Some(client) = &self.unix {
+                return crate::unix::send(client, req).await;
+            }
+        }
+        req.send().await.map_err(|err| Error::Client { err })
+    }
+
+    async fn send_json<T>(&self, req: reqwest::RequestBuilder) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let resp = self.send(req).await?;
+        resp.json().await.map_err(|err| Error::Client { err })
+    }
+}
+
+type Response = Result<reqwest::Response>;
+
+async fn check_status(resp: Response) -> Result<reqwest::Response> {
+    let resp = resp?;
+    if !resp.status().is_success() {
+        let status = resp.status().as_u16();
+        let body = resp.text().await.map_err(|err| Error::Client { err })?;
+        let error = match serde_json::from_str::<types::Error>(&body) {
+            Ok(err) => err.into(),
+            Err(..) => Error::Unexpected { status, body },
+        };
+        return Err(error);
+    }
+    Ok(resp)
+}
+
+/// Reads the words out of the server-sent events, until the `done` event
+fn words(resp: reqwest::Response) -> impl Stream<Item = Result<String>> {
+    futures::stream::unfold(Some((resp, vec![])), |state| async move {
+        let (mut resp, mut buf) = state?;
+        loop {
+            if let Some(end) = buf.windows(2).position(|w| w == b"\n\n") {
+                let event = buf.drain(..end + 2).collect::<Vec<_>>();
+                let (name, data) = parse_event(&String::from_utf8_lossy(&event));
+                match name.as_str() {
+                    "token" => return Some((Ok(data), Some((resp, buf)))),
+                    "done" => return None,
+                    _ => continue,
+                }
+            }
+
+            match resp.chunk().await {
+                Ok(Some(chunk)) => buf.extend_from_slice(&chunk),
+                Ok(None) => return None,
+                Err(err) => return Some((Err(Error::Client { err }), None)),
+            }
+        }
+    })
+}
+
+// returns the event name and its data
+fn parse_event(event: &str) -> (String, String) {
+    let (mut name, mut data) = (String::new(), vec![]);
+    for line in event.lines() {
+        let (field, value) = match line.find(':') {
+            Some(pos) => (&line[..pos], &line[pos + 1..]),
+            None => (line, ""),
+        };
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => name = value.to_string(),
+            "data" => data.push(value),
+            _ => {}
+        }
+    }
+    (name, data.join("\n"))
+}
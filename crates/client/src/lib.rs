@@ -9,10 +9,39 @@ pub use types;
 pub mod requests;
 use requests::*;
 
+mod api;
+pub use api::{BrainApi, WordStream};
+
+mod http;
+
+#[cfg(feature = "local")]
+mod local;
+#[cfg(feature = "local")]
+pub use local::LocalClient;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "ws")]
+pub mod ws;
+
+#[cfg(feature = "unix")]
+mod unix;
+
+// makes the reqwest client's builder, before the roots are added
+type Builder = std::sync::Arc<dyn Fn() -> reqwest::ClientBuilder + Send + Sync>;
+
 #[derive(Clone)]
 pub struct Client {
     host: String,
     client: reqwest::Client,
+    builder: Builder,
+    token: Option<String>,
+    retries: usize,
+    #[cfg(feature = "unix")]
+    unix: Option<unix::UnixClient>,
+    #[cfg(feature = "tls")]
+    roots: Vec<reqwest::Certificate>,
 }
 
 impl Client {
@@ -20,27 +49,106 @@ impl Client {
         Self {
             host: host.to_string(),
             client: reqwest::Client::new(),
+            builder: std::sync::Arc::new(reqwest::Client::builder),
+            token: None,
+            retries: 0,
+            #[cfg(feature = "unix")]
+            unix: None,
+            #[cfg(feature = "tls")]
+            roots: vec![],
         }
     }
 
-    pub fn generate<'a>(&'a self, brain: impl ToString) -> GenerateRequest<'a> {
-        GenerateRequest {
-            url: &self.host,
-            client: &self.client,
-            brain: brain.to_string(),
-            context: None,
-            min: None,
-            max: None,
+    /// Sends the requests with a client from `builder`, for timeouts or
+    /// proxies
+    ///
+    /// `builder` is called again whenever the client has to be rebuilt, like
+    /// when a root certificate is added, so none of its settings are lost.
+    pub fn with_builder<F>(host: impl ToString, builder: F) -> Result<Self>
+    where
+        F: Fn() -> reqwest::ClientBuilder + Send + Sync + 'static,
+    {
+        let mut client = Self {
+            builder: std::sync::Arc::new(builder),
+            ..Self::new(host)
+        };
+        client.client = client.build()?;
+        Ok(client)
+    }
+
+    /// Connects to a server listening on the unix domain socket at `path`
+    ///
+    /// Websockets can't be used over it.
+    #[cfg(feature = "unix")]
+    pub fn unix(path: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            unix: Some(unix::client(path.into())),
+            ..Self::new("http://localhost")
         }
     }
 
+    /// Sends `token` as a bearer token with every request
+    ///
+    /// Servers with tokens configured reject requests without one.
+    pub fn token(mut self, token: impl ToString) -> Self {
+        self.token.replace(token.to_string());
+        self
+    }
+
+    /// Trusts a PEM encoded root certificate, for servers with self-signed
+    /// certificates
+    ///
+    /// The usual root certificates are trusted too.
+    #[cfg(feature = "tls")]
+    pub fn root_certificate(mut self, pem: &[u8]) -> Result<Self> {
+        let cert = reqwest::Certificate::from_pem(pem).map_err(|err| Error::Client { err })?;
+        self.roots.push(cert);
+        self.client = self.build()?;
+        Ok(self)
+    }
+
+    fn build(&self) -> Result<reqwest::Client> {
+        let builder = (self.builder)();
+        #[cfg(feature = "tls")]
+        let builder = self
+            .roots
+            .iter()
+            .cloned()
+            .fold(builder, reqwest::ClientBuilder::add_root_certificate);
+        builder.build().map_err(|err| Error::Client { err })
+    }
+
+    /// Retries requests that were rate limited, up to `retries` times
+    ///
+    /// Each retry waits for as long as the server asked. Without this, the
+    /// `RateLimited` error is returned instead.
+    pub fn retry_rate_limited(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn generate<'a>(&'a self, brain: impl ToString) -> GenerateRequest<'a> {
+        <dyn BrainApi>::generate(self, brain)
+    }
+
+    pub fn generate_batch<'a>(
+        &'a self,
+        brain: impl ToString,
+        count: usize,
+    ) -> GenerateBatchRequest<'a> {
+        <dyn BrainApi>::generate_batch(self, brain, count)
+    }
+
     pub fn train<'a>(&'a self, brain: impl ToString, data: impl ToString) -> TrainRequest<'a> {
-        TrainRequest {
-            url: &self.host,
-            client: &self.client,
-            brain: brain.to_string(),
-            data: data.to_string(),
-        }
+        <dyn BrainApi>::train(self, brain, data)
+    }
+
+    pub fn train_batch<'a, I>(&'a self, brain: impl ToString, lines: I) -> TrainBatchRequest<'a>
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        <dyn BrainApi>::train_batch(self, brain, lines)
     }
 
     pub fn new_brain<'a>(
@@ -48,28 +156,64 @@ impl Client {
         brain: impl ToString,
         brain_file: impl ToString,
     ) -> NewBrainRequest<'a> {
-        NewBrainRequest {
-            url: &self.host,
-            client: &self.client,
-            brain: brain.to_string(),
-            brain_file: brain_file.to_string(),
-            depth: None,
-        }
+        <dyn BrainApi>::new_brain(self, brain, brain_file)
+    }
+
+    pub fn rename<'a>(&'a self, brain: impl ToString, name: impl ToString) -> RenameRequest<'a> {
+        <dyn BrainApi>::rename(self, brain, name)
+    }
+
+    pub fn copy<'a>(
+        &'a self,
+        brain: impl ToString,
+        name: impl ToString,
+        brain_file: impl ToString,
+    ) -> CopyRequest<'a> {
+        <dyn BrainApi>::copy(self, brain, name, brain_file)
     }
 
     pub fn save<'a>(&'a self, brain: impl ToString) -> SaveRequest<'a> {
-        SaveRequest {
-            url: &self.host,
-            client: &self.client,
-            brain: brain.to_string(),
-        }
+        <dyn BrainApi>::save(self, brain)
     }
 
     pub fn list<'a>(&'a self) -> ListRequest<'a> {
-        ListRequest {
-            url: &self.host,
-            client: &self.client,
-        }
+        <dyn BrainApi>::list(self)
+    }
+
+    pub fn info<'a>(&'a self, brain: impl ToString) -> InfoRequest<'a> {
+        <dyn BrainApi>::info(self, brain)
+    }
+
+    pub fn delete<'a>(&'a self, brain: impl ToString) -> DeleteRequest<'a> {
+        <dyn BrainApi>::delete(self, brain)
+    }
+
+    pub fn reload<'a>(&'a self, brain: impl ToString) -> ReloadRequest<'a> {
+        <dyn BrainApi>::reload(self, brain)
+    }
+
+    pub fn unload<'a>(&'a self, brain: impl ToString) -> UnloadRequest<'a> {
+        <dyn BrainApi>::unload(self, brain)
+    }
+
+    pub fn reload_config<'a>(&'a self) -> ReloadConfigRequest<'a> {
+        <dyn BrainApi>::reload_config(self)
+    }
+
+    pub fn upload<'a>(
+        &'a self,
+        brain: impl ToString,
+        data: impl Into<Vec<u8>>,
+    ) -> UploadRequest<'a> {
+        <dyn BrainApi>::upload(self, brain, data)
+    }
+
+    pub fn job<'a>(&'a self, id: u64) -> JobRequest<'a> {
+        <dyn BrainApi>::job(self, id)
+    }
+
+    pub fn cancel_job<'a>(&'a self, id: u64) -> CancelJobRequest<'a> {
+        <dyn BrainApi>::cancel_job(self, id)
     }
 }
 
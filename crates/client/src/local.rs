@@ -0,0 +1,800 @@
+use crate::requests::*;
+use crate::{BrainApi, Error, Result, WordStream};
+use types::generate::{GenerateConfig, GenerateDefaults, Generation, OptionsSource};
+use types::{input, responses};
+
+use futures::StreamExt as _;
+use hashbrown::HashMap;
+use markov::Markov;
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// the server's default
+const BATCH_LIMIT: usize = 100;
+
+struct Options {
+    brain_file: PathBuf,
+    read_only: bool,
+    generate: GenerateConfig,
+}
+
+struct Entry {
+    options: Options,
+    // unloaded brains keep their entry so they can be reloaded from the brain file
+    markov: Option<Markov>,
+}
+
+/// An in-process backend that serves brains from memory, without an HTTP server
+///
+/// It mirrors the server's behavior, including its errors, so it can be swapped
+/// in for the [`Client`](crate::Client) in tests. Like the server, it uses
+/// the default generation settings unless it is given others, and it saves
+/// brains to their brain files, keeping the old file as a `.bak`.
+pub struct LocalClient {
+    brains: Mutex<HashMap<String, Entry>>,
+    // uploads are trained right away, so every job is finished
+    jobs: Mutex<Vec<responses::Job>>,
+    defaults: GenerateDefaults,
+    batch_limit: usize,
+}
+
+impl Default for LocalClient {
+    fn default() -> Self {
+        Self {
+            brains: Mutex::default(),
+            jobs: Mutex::default(),
+            defaults: GenerateDefaults::default(),
+            batch_limit: BATCH_LIMIT,
+        }
+    }
+}
+
+impl LocalClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generates with these defaults and limits, like `server.defaults`
+    pub fn with_defaults(mut self, defaults: GenerateDefaults) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Allows batches of up to `limit` sentences, like `server.batch_limit`
+    pub fn with_batch_limit(mut self, limit: usize) -> Self {
+        self.batch_limit = limit;
+        self
+    }
+
+    /// Gives a brain that was already added its own defaults and limits, like
+    /// a brain's `generate` table
+    pub fn with_generate(self, brain: &str, config: GenerateConfig) -> Self {
+        if let Some(entry) = self.brains.lock().unwrap().get_mut(brain) {
+            entry.options.generate = config;
+        }
+        self
+    }
+
+    pub fn with_brain(
+        self,
+        name: impl ToString,
+        brain_file: impl Into<PathBuf>,
+        markov: Markov,
+    ) -> Self {
+        self.insert(name, brain_file, false, markov)
+    }
+
+    pub fn with_read_only_brain(
+        self,
+        name: impl ToString,
+        brain_file: impl Into<PathBuf>,
+        markov: Markov,
+    ) -> Self {
+        self.insert(name, brain_file, true, markov)
+    }
+
+    fn insert(
+        self,
+        name: impl ToString,
+        brain_file: impl Into<PathBuf>,
+        read_only: bool,
+        markov: Markov,
+    ) -> Self {
+        let entry = Entry {
+            options: Options {
+                brain_file: brain_file.into(),
+                read_only,
+                generate: GenerateConfig::default(),
+            },
+            markov: Some(markov),
+        };
+        self.brains.lock().unwrap().insert(name.to_string(), entry);
+        self
+    }
+
+    pub fn generate<'a>(&'a self, brain: impl ToString) -> GenerateRequest<'a> {
+        <dyn BrainApi>::generate(self, brain)
+    }
+
+    pub fn generate_batch<'a>(
+        &'a self,
+        brain: impl ToString,
+        count: usize,
+    ) -> GenerateBatchRequest<'a> {
+        <dyn BrainApi>::generate_batch(self, brain, count)
+    }
+
+    pub fn train<'a>(&'a self, brain: impl ToString, data: impl ToString) -> TrainRequest<'a> {
+        <dyn BrainApi>::train(self, brain, data)
+    }
+
+    pub fn train_batch<'a, I>(&'a self, brain: impl ToString, lines: I) -> TrainBatchRequest<'a>
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        <dyn BrainApi>::train_batch(self, brain, lines)
+    }
+
+    pub fn new_brain<'a>(
+        &'a self,
+        brain: impl ToString,
+        brain_file: impl ToString,
+    ) -> NewBrainRequest<'a> {
+        <dyn BrainApi>::new_brain(self, brain, brain_file)
+    }
+
+    pub fn rename<'a>(&'a self, brain: impl ToString, name: impl ToString) -> RenameRequest<'a> {
+        <dyn BrainApi>::rename(self, brain, name)
+    }
+
+    pub fn copy<'a>(
+        &'a self,
+        brain: impl ToString,
+        name: impl ToString,
+        brain_file: impl ToString,
+    ) -> CopyRequest<'a> {
+        <dyn BrainApi>::copy(self, brain, name, brain_file)
+    }
+
+    pub fn save<'a>(&'a self, brain: impl ToString) -> SaveRequest<'a> {
+        <dyn BrainApi>::save(self, brain)
+    }
+
+    pub fn list<'a>(&'a self) -> ListRequest<'a> {
+        <dyn BrainApi>::list(self)
+    }
+
+    pub fn info<'a>(&'a self, brain: impl ToString) -> InfoRequest<'a> {
+        <dyn BrainApi>::info(self, brain)
+    }
+
+    pub fn delete<'a>(&'a self, brain: impl ToString) -> DeleteRequest<'a> {
+        <dyn BrainApi>::delete(self, brain)
+    }
+
+    pub fn reload<'a>(&'a self, brain: impl ToString) -> ReloadRequest<'a> {
+        <dyn BrainApi>::reload(self, brain)
+    }
+
+    pub fn unload<'a>(&'a self, brain: impl ToString) -> UnloadRequest<'a> {
+        <dyn BrainApi>::unload(self, brain)
+    }
+
+    pub fn reload_config<'a>(&'a self) -> ReloadConfigRequest<'a> {
+        <dyn BrainApi>::reload_config(self)
+    }
+
+    pub fn upload<'a>(
+        &'a self,
+        brain: impl ToString,
+        data: impl Into<Vec<u8>>,
+    ) -> UploadRequest<'a> {
+        <dyn BrainApi>::upload(self, brain, data)
+    }
+
+    pub fn job<'a>(&'a self, id: u64) -> JobRequest<'a> {
+        <dyn BrainApi>::job(self, id)
+    }
+
+    pub fn cancel_job<'a>(&'a self, id: u64) -> CancelJobRequest<'a> {
+        <dyn BrainApi>::cancel_job(self, id)
+    }
+
+    // the http client sends generation options in a query
+    fn options(&self, options: &Options, opts: &input::GenerateOptions) -> Result<Generation> {
+        let source = OptionsSource::Query;
+        options.generate.options(opts, &self.defaults, source).map_err(server)
+    }
+
+    fn with_loaded<T>(
+        &self,
+        brain: &str,
+        func: impl FnOnce(&Options, &mut Markov) -> Result<T>,
+    ) -> Result<T> {
+        let mut brains = self.brains.lock().unwrap();
+        match brains.get_mut(brain) {
+            Some(Entry {
+                options,
+                markov: Some(markov),
+            }) => func(options, markov),
+            _ => Err(not_found(brain)),
+        }
+    }
+
+    async fn save_brain(&self, brain: &str) -> Result<responses::Saved> {
+        let (brain_file, markov, last_saved) = self.with_loaded(brain, |options, markov| {
+            let last_saved = markov.mark_saved();
+            Ok((options.brain_file.clone(), markov.clone(), last_saved))
+        })?;
+
+        match save_markov(markov, &brain_file).await {
+            Ok(time) => Ok(responses::Saved {
+                name: brain_file.to_string_lossy().to_string(),
+                time,
+            }),
+            Err(err) => {
+                // it wasn't saved after all
+                let mut brains = self.brains.lock().unwrap();
+                if let Some(Entry {
+                    markov: Some(markov),
+                    ..
+                }) = brains.get_mut(brain)
+                {
+                    markov.meta.last_saved = last_saved;
+                    markov.dirty = true;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    // adds a brain that was just saved, unless another one took its name or
+    // file in the meantime
+    fn add(&self, name: &str, options: Options, markov: Markov) -> Result<()> {
+        let mut brains = self.brains.lock().unwrap();
+        expect_unused(&brains, name)?;
+        expect_unused_file(&brains, &options.brain_file)?;
+        let markov = Some(markov);
+        brains.insert(name.to_string(), Entry { options, markov });
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl BrainApi for LocalClient {
+    async fn send_generate(
+        &self,
+        brain: &str,
+        opts: input::GenerateOptions,
+    ) -> Result<responses::Generated> {
+        use rand::prelude::*;
+        self.with_loaded(brain, |options, markov| {
+            let generation = self.options(options, &opts)?;
+            let data = markov.generate(
+                &mut thread_rng(),
+                generation.min,
+                generation.max,
+                generation.context.as_deref(),
+            );
+            let data = data.ok_or_else(|| server(types::Error::NotEnoughState))?;
+            Ok(responses::Generated {
+                name: brain.to_string(),
+                data,
+            })
+        })
+    }
+
+    async fn send_generate_stream(
+        &self,
+        brain: &str,
+        opts: input::GenerateOptions,
+    ) -> Result<WordStream> {
+        use rand::prelude::*;
+        let words = self.with_loaded(brain, |options, markov| {
+            let generation = self.options(options, &opts)?;
+            if markov.starts.is_empty() {
+                return Err(server(types::Error::NotEnoughState));
+            }
+            let words = markov
+                .walk(
+                    &mut thread_rng(),
+                    generation.min,
+                    generation.max,
+                    generation.context.as_deref(),
+                )
+                .map(Ok)
+                .collect::<Vec<_>>();
+            Ok(words)
+        })?;
+        Ok(futures::stream::iter(words).boxed())
+    }
+
+    async fn send_generate_batch(
+        &self,
+        brain: &str,
+        opts: input::GenerateBatchOptions,
+    ) -> Result<responses::GeneratedBatch> {
+        use rand::prelude::*;
+        self.with_loaded(brain, |options, markov| {
+            options.generate.check_batch(opts.count, self.batch_limit)?;
+            let generation = self.options(options, &opts.options())?;
+
+            let mut rng = thread_rng();
+            let results = (0..opts.count)
+                .map(|_| {
+                    markov
+                        .generate(
+                            &mut rng,
+                            generation.min,
+                            generation.max,
+                            generation.context.as_deref(),
+                        )
+                        .ok_or(types::Error::NotEnoughState)
+                })
+                .map(responses::BatchItem::from)
+                .collect();
+            Ok(responses::GeneratedBatch {
+                name: brain.to_string(),
+                results,
+            })
+        })
+    }
+
+    async fn send_train(&self, brain: &str, data: input::TrainData) -> Result<responses::Trained> {
+        self.with_loaded(brain, |options, markov| {
+            if options.read_only {
+                return Err(server(types::Error::ReadOnly));
+            }
+
+            let now = Instant::now();
+            markov.train_text(&data.data);
+            Ok(responses::Trained {
+                data: data.data,
+                time: now.elapsed(),
+            })
+        })
+    }
+
+    async fn send_train_batch(
+        &self,
+        brain: &str,
+        data: input::TrainBatch,
+    ) -> Result<responses::TrainedBatch> {
+        self.with_loaded(brain, |options, markov| {
+            if options.read_only {
+                return Err(server(types::Error::ReadOnly));
+            }
+
+            let now = Instant::now();
+            let results = data
+                .data
+                .into_iter()
+                .map(|data| {
+                    if data.trim().is_empty() {
+                        return Err(types::Error::InvalidBody {
+                            reason: "the line is empty".into(),
+                        });
+                    }
+                    let start = Instant::now();
+                    markov.train_text(&data);
+                    Ok(responses::Trained {
+                        data,
+                        time: start.elapsed(),
+                    })
+                })
+                .map(responses::BatchItem::from)
+                .collect();
+            Ok(responses::TrainedBatch {
+                results,
+                time: now.elapsed(),
+            })
+        })
+    }
+
+    async fn send_new_brain(
+        &self,
+        brain: &str,
+        input: input::NewBrain,
+    ) -> Result<responses::Created> {
+        // the name is part of the path
+        if !types::is_valid_name(brain) {
+            return Err(server(types::Error::InvalidQuery {
+                reason: format!("'{}' isn't a valid brain name", brain),
+            }));
+        }
+        let options = Options {
+            brain_file: input.brain_file.clone().into(),
+            read_only: false,
+            generate: GenerateConfig::default(),
+        };
+        {
+            let brains = self.brains.lock().unwrap();
+            expect_unused(&brains, brain)?;
+            expect_unused_file(&brains, &options.brain_file)?;
+        }
+
+        let mut markov = Markov::new(input.depth, brain);
+        markov.mark_saved();
+        save_markov(markov.clone(), &options.brain_file).await?;
+        self.add(brain, options, markov)?;
+
+        Ok(responses::Created {
+            name: brain.to_string(),
+            brain_file: input.brain_file,
+        })
+    }
+
+    async fn send_rename(
+        &self,
+        brain: &str,
+        input: input::RenameBrain,
+    ) -> Result<responses::Renamed> {
+        let mut brains = self.brains.lock().unwrap();
+        match brains.get(brain) {
+            Some(entry) if entry.markov.is_some() => {}
+            _ => return Err(not_found(brain)),
+        }
+        expect_unused(&brains, &input.name)?;
+
+        let mut entry = brains.remove(brain).unwrap();
+        if let Some(markov) = &mut entry.markov {
+            markov.name = input.name.clone();
+            markov.dirty = true;
+        }
+        brains.insert(input.name.clone(), entry);
+
+        Ok(responses::Renamed {
+            from: brain.to_string(),
+            name: input.name,
+            // there is no config file
+            config_updated: false,
+        })
+    }
+
+    async fn send_copy(&self, brain: &str, input: input::CopyBrain) -> Result<responses::Copied> {
+        let (options, mut copy) = {
+            let brains = self.brains.lock().unwrap();
+            let (options, markov) = match brains.get(brain) {
+                Some(Entry {
+                    options,
+                    markov: Some(markov),
+                }) => (options, markov),
+                _ => return Err(not_found(brain)),
+            };
+            let options = Options {
+                brain_file: input.brain_file.clone().into(),
+                read_only: false,
+                // like the server, the copy generates like the original
+                generate: options.generate.clone(),
+            };
+
+            let copy = match input.depth {
+                Some(depth) => markov.with_depth(depth).ok_or_else(|| {
+                    server(types::Error::InvalidBody {
+                        reason: format!("depth must be from 1 to {}", markov.depth),
+                    })
+                })?,
+                None => markov.clone(),
+            };
+            expect_unused(&brains, &input.name)?;
+            expect_unused_file(&brains, &options.brain_file)?;
+            (options, copy)
+        };
+
+        copy.name = input.name.clone();
+        copy.meta.source = Some(format!("copied from {}", brain));
+        copy.mark_saved();
+        let depth = copy.depth;
+        save_markov(copy.clone(), &options.brain_file).await?;
+        self.add(&input.name, options, copy)?;
+
+        Ok(responses::Copied {
+            from: brain.to_string(),
+            name: input.name,
+            brain_file: input.brain_file,
+            depth,
+        })
+    }
+
+    async fn send_save(&self, brain: &str) -> Result<responses::Saved> {
+        self.save_brain(brain).await
+    }
+
+    async fn send_list(&self) -> Result<responses::List> {
+        let items = self
+            .brains
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(name, entry)| {
+                let markov = entry.markov.as_ref()?;
+                Some(describe(name, &entry.options, markov))
+            })
+            .collect::<Vec<_>>();
+        let brains = blocking(move || {
+            let items = items.into_iter().map(with_file_size);
+            items.map(|item| (item.name.clone(), item)).collect()
+        })
+        .await;
+
+        Ok(responses::List {
+            brains,
+            failed: Default::default(),
+            loading: Default::default(),
+            config_path: PathBuf::new(),
+        })
+    }
+
+    async fn send_info(&self, brain: &str) -> Result<responses::ListItem> {
+        let item = self.with_loaded(brain, |options, markov| {
+            Ok(describe(brain, options, markov))
+        })?;
+        Ok(blocking(move || with_file_size(item)).await)
+    }
+
+    async fn send_delete(
+        &self,
+        brain: &str,
+        opts: input::DeleteOptions,
+    ) -> Result<responses::Deleted> {
+        let Entry { options, .. } = {
+            let mut brains = self.brains.lock().unwrap();
+            match brains.get(brain) {
+                Some(entry) if entry.markov.is_some() => brains.remove(brain).unwrap(),
+                _ => return Err(not_found(brain)),
+            }
+        };
+
+        let mut errors = vec![];
+        let mut file_deleted = false;
+        if opts.delete_file.unwrap_or(false) {
+            let brain_file = options.brain_file.clone();
+            match blocking(move || std::fs::remove_file(brain_file)).await {
+                Ok(..) => file_deleted = true,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => errors.push(types::Error::CannotDelete {
+                    file: options.brain_file.to_string_lossy().to_string(),
+                    reason: err.to_string(),
+                }),
+            }
+        }
+
+        Ok(responses::Deleted {
+            name: brain.to_string(),
+            brain_file: options.brain_file,
+            file_deleted,
+            // there is no config file
+            config_updated: false,
+            errors,
+        })
+    }
+
+    async fn send_reload(&self, brain: &str) -> Result<responses::Reloaded> {
+        let brain_file = match self.brains.lock().unwrap().get(brain) {
+            Some(entry) => entry.options.brain_file.clone(),
+            None => return Err(not_found(brain)),
+        };
+
+        let now = Instant::now();
+        let file = brain_file.clone();
+        let markov = blocking(move || markov::load(file)).await.map_err(|err| {
+            server(types::Error::CannotLoad {
+                file: brain_file.to_string_lossy().to_string(),
+                reason: err.to_string(),
+            })
+        })?;
+
+        let mut brains = self.brains.lock().unwrap();
+        let entry = brains.get_mut(brain).ok_or_else(|| not_found(brain))?;
+        entry.markov.replace(markov);
+
+        Ok(responses::Reloaded {
+            name: brain.to_string(),
+            brain_file,
+            time: now.elapsed(),
+        })
+    }
+
+    async fn send_unload(&self, brain: &str) -> Result<responses::Unloaded> {
+        let dirty = self.with_loaded(brain, |options, markov| {
+            Ok(!options.read_only && markov.dirty)
+        })?;
+        // like the server, save unsaved training rather than losing it
+        if dirty {
+            self.save_brain(brain).await?;
+        }
+
+        let mut brains = self.brains.lock().unwrap();
+        let entry = brains.get_mut(brain).ok_or_else(|| not_found(brain))?;
+        entry.markov = None;
+        Ok(responses::Unloaded {
+            name: brain.to_string(),
+        })
+    }
+
+    async fn send_reload_config(&self) -> Result<responses::ConfigReloaded> {
+        // there is no config file, so nothing can change
+        Ok(responses::ConfigReloaded::default())
+    }
+
+    async fn send_upload(&self, brain: &str, data: Vec<u8>, gzip: bool) -> Result<responses::Job> {
+        let data = if gzip {
+            let mut decoded = vec![];
+            flate2::read::GzDecoder::new(&*data)
+                .read_to_end(&mut decoded)
+                .map_err(|err| {
+                    server(types::Error::InvalidBody {
+                        reason: err.to_string(),
+                    })
+                })?;
+            decoded
+        } else {
+            data
+        };
+
+        let now = Instant::now();
+        let (lines, total, reason) = self.with_loaded(brain, |options, markov| {
+            if options.read_only {
+                return Err(server(types::Error::ReadOnly));
+            }
+
+            let mut text = data.split(|&b| b == b'\n').collect::<Vec<_>>();
+            if let Some([]) = text.last() {
+                text.pop();
+            }
+
+            // like the server, training stops at the first line that isn't utf-8
+            let (total, mut lines, mut reason) = (text.len(), 0, None);
+            for line in text {
+                match std::str::from_utf8(line) {
+                    Ok(line) => {
+                        markov.train_text(line.trim_end_matches('\r'));
+                        lines += 1;
+                    }
+                    Err(err) => {
+                        reason.replace(err.to_string());
+                        break;
+                    }
+                }
+            }
+            Ok((lines, total, reason))
+        })?;
+
+        let elapsed = now.elapsed();
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = responses::Job {
+            id: jobs.len() as u64 + 1,
+            brain: brain.to_string(),
+            state: match reason {
+                Some(..) => responses::JobState::Failed,
+                None => responses::JobState::Done,
+            },
+            lines,
+            total,
+            lines_per_sec: lines as f64 / elapsed.as_secs_f64(),
+            elapsed,
+            reason,
+        };
+        jobs.push(job.clone());
+        Ok(job)
+    }
+
+    async fn send_job(&self, id: u64) -> Result<responses::Job> {
+        let jobs = self.jobs.lock().unwrap();
+        let index = (id as usize).checked_sub(1);
+        index
+            .and_then(|index| jobs.get(index))
+            .cloned()
+            .ok_or_else(|| server(types::Error::JobNotFound { id }))
+    }
+
+    async fn send_cancel_job(&self, id: u64) -> Result<responses::Job> {
+        // the jobs are already finished, so there is nothing to cancel
+        self.send_job(id).await
+    }
+}
+
+fn describe(name: &str, options: &Options, markov: &Markov) -> responses::ListItem {
+    let meta = &markov.meta;
+    let metadata = responses::Metadata {
+        depth: markov.depth,
+        created: meta.created,
+        last_trained: meta.last_trained,
+        last_saved: meta.last_saved,
+        lines: meta.lines,
+        source: meta.source.clone(),
+        // filled in by with_file_size, without holding the lock
+        file_size: None,
+        memory_size: markov.memory_size() as u64,
+        dirty: markov.dirty,
+    };
+    responses::ListItem {
+        name: name.to_string(),
+        brain_file: options.brain_file.clone(),
+        read_only: options.read_only,
+        metadata: Some(metadata),
+    }
+}
+
+fn with_file_size(mut item: responses::ListItem) -> responses::ListItem {
+    if let Some(metadata) = &mut item.metadata {
+        let file = std::fs::metadata(&item.brain_file).ok();
+        metadata.file_size = file.map(|file| file.len());
+    }
+    item
+}
+
+// like the server, a new name has to be valid and can't be taken by another
+// brain, even an unloaded one
+fn expect_unused(brains: &HashMap<String, Entry>, name: &str) -> Result<()> {
+    if !types::is_valid_name(name) {
+        return Err(server(types::Error::InvalidBody {
+            reason: format!("'{}' isn't a valid brain name", name),
+        }));
+    }
+    if brains.contains_key(name) {
+        return Err(server(types::Error::AlreadyExists { name: name.into() }));
+    }
+    Ok(())
+}
+
+fn expect_unused_file(brains: &HashMap<String, Entry>, path: &Path) -> Result<()> {
+    match brains
+        .iter()
+        .find(|(_, entry)| entry.options.brain_file == path)
+    {
+        Some((other, _)) => Err(server(types::Error::InvalidBody {
+            reason: format!("'{}' is the brain file of '{}'", path.display(), other),
+        })),
+        None => Ok(()),
+    }
+}
+
+// like the server, keeps the old file as a .bak
+async fn save_markov(markov: Markov, path: &Path) -> Result<Duration> {
+    let path = path.to_owned();
+    let file = path.to_string_lossy().to_string();
+    blocking(move || {
+        if path.exists() {
+            let backup = path.with_extension("bak");
+            if let Err(err) = std::fs::rename(&path, backup) {
+                return Err(types::Error::CannotRotate {
+                    file,
+                    reason: err.to_string(),
+                });
+            }
+        }
+
+        let now = Instant::now();
+        match markov::save(&markov, &path) {
+            Ok(..) => Ok(now.elapsed()),
+            Err(err) => Err(types::Error::CannotSave {
+                file,
+                reason: err.to_string(),
+            }),
+        }
+    })
+    .await
+    .map_err(server)
+}
+
+// file i/o runs on the blocking threads, not the async ones
+async fn blocking<T, F>(func: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    // unwrap is for the task, not the value
+    tokio::task::spawn_blocking(func).await.unwrap()
+}
+
+fn server(err: types::Error) -> Error {
+    err.into()
+}
+
+fn not_found(brain: &str) -> Error {
+    Error::NotFound { name: brain.into() }
+}
@@ -0,0 +1,12 @@
+use super::*;
+
+pub struct CancelJobRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) id: u64,
+}
+
+impl<'a> CancelJobRequest<'a> {
+    pub async fn send(self) -> Result<responses::Job> {
+        self.api.send_cancel_job(self.id).await
+    }
+}
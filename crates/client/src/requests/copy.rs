@@ -0,0 +1,26 @@
+use super::*;
+
+pub struct CopyRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+    pub(crate) name: String,
+    pub(crate) brain_file: String,
+    pub(crate) depth: Option<usize>,
+}
+
+impl<'a> CopyRequest<'a> {
+    /// Re-derives the copy at a lower depth
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth.replace(depth);
+        self
+    }
+
+    pub async fn send(self) -> Result<responses::Copied> {
+        let input = input::CopyBrain {
+            name: self.name,
+            brain_file: self.brain_file,
+            depth: self.depth,
+        };
+        self.api.send_copy(&self.brain, input).await
+    }
+}
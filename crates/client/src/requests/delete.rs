@@ -0,0 +1,28 @@
+use super::*;
+
+pub struct DeleteRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+    pub(crate) delete_file: Option<bool>,
+    pub(crate) keep_config: Option<bool>,
+}
+
+impl<'a> DeleteRequest<'a> {
+    pub fn delete_file(mut self, delete_file: bool) -> Self {
+        self.delete_file.replace(delete_file);
+        self
+    }
+
+    pub fn keep_config(mut self, keep_config: bool) -> Self {
+        self.keep_config.replace(keep_config);
+        self
+    }
+
+    pub async fn send(self) -> Result<responses::Deleted> {
+        let opts = input::DeleteOptions {
+            delete_file: self.delete_file,
+            keep_config: self.keep_config,
+        };
+        self.api.send_delete(&self.brain, opts).await
+    }
+}
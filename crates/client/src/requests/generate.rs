@@ -1,8 +1,7 @@
 use super::*;
 
 pub struct GenerateRequest<'a> {
-    pub(crate) url: &'a str,
-    pub(crate) client: &'a reqwest::Client,
+    pub(crate) api: &'a dyn BrainApi,
     pub(crate) brain: String,
     pub(crate) context: Option<String>,
     pub(crate) min: Option<usize>,
@@ -26,16 +25,22 @@ impl<'a> GenerateRequest<'a> {
     }
 
     pub async fn send(self) -> Result<responses::Generated> {
-        let resp = self
-            .client
-            .get(&format!("{}/generate/{}", self.url, self.brain))
-            .query(&input::GenerateOptions {
-                context: self.context,
-                min: self.min,
-                max: self.max,
-            })
-            .send()
-            .await;
-        check_response(resp).await
+        let (api, brain, opts) = self.into_parts();
+        api.send_generate(&brain, opts).await
+    }
+
+    /// Generates the sentence a word at a time, as the brain picks them
+    pub async fn stream(self) -> Result<WordStream> {
+        let (api, brain, opts) = self.into_parts();
+        api.send_generate_stream(&brain, opts).await
+    }
+
+    fn into_parts(self) -> (&'a dyn BrainApi, String, input::GenerateOptions) {
+        let opts = input::GenerateOptions {
+            context: self.context,
+            min: self.min,
+            max: self.max,
+        };
+        (self.api, self.brain, opts)
     }
 }
@@ -0,0 +1,37 @@
+use super::*;
+
+pub struct GenerateBatchRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+    pub(crate) count: usize,
+    pub(crate) context: Option<String>,
+    pub(crate) min: Option<usize>,
+    pub(crate) max: Option<usize>,
+}
+
+impl<'a> GenerateBatchRequest<'a> {
+    pub fn context(mut self, context: impl ToString) -> Self {
+        self.context.replace(context.to_string());
+        self
+    }
+
+    pub fn min(mut self, min: usize) -> Self {
+        self.min.replace(min);
+        self
+    }
+
+    pub fn max(mut self, max: usize) -> Self {
+        self.max.replace(max);
+        self
+    }
+
+    pub async fn send(self) -> Result<responses::GeneratedBatch> {
+        let opts = input::GenerateBatchOptions {
+            count: self.count,
+            context: self.context,
+            min: self.min,
+            max: self.max,
+        };
+        self.api.send_generate_batch(&self.brain, opts).await
+    }
+}
@@ -0,0 +1,12 @@
+use super::*;
+
+pub struct InfoRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+}
+
+impl<'a> InfoRequest<'a> {
+    pub async fn send(self) -> Result<responses::ListItem> {
+        self.api.send_info(&self.brain).await
+    }
+}
@@ -0,0 +1,12 @@
+use super::*;
+
+pub struct JobRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) id: u64,
+}
+
+impl<'a> JobRequest<'a> {
+    pub async fn send(self) -> Result<responses::Job> {
+        self.api.send_job(self.id).await
+    }
+}
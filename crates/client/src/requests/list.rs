@@ -1,13 +1,10 @@
 use super::*;
 pub struct ListRequest<'a> {
-    pub(crate) url: &'a str,
-    pub(crate) client: &'a reqwest::Client,
+    pub(crate) api: &'a dyn BrainApi,
 }
 
 impl<'a> ListRequest<'a> {
     pub async fn send(self) -> Result<responses::List> {
-        let url = format!("{}/list", self.url);
-        let resp = self.client.get(&url).send().await;
-        check_response(resp).await
+        self.api.send_list().await
     }
 }
@@ -1,36 +1,53 @@
-use crate::{Error, Result};
+use crate::{BrainApi, Result, WordStream};
 use types::{input, responses};
 
 mod generate;
 pub use generate::GenerateRequest;
 
+mod generate_batch;
+pub use generate_batch::GenerateBatchRequest;
+
 mod list;
 pub use list::ListRequest;
 
+mod info;
+pub use info::InfoRequest;
+
 mod save;
 pub use save::SaveRequest;
 
 mod train;
 pub use train::TrainRequest;
 
+mod train_batch;
+pub use train_batch::TrainBatchRequest;
+
 mod new_brain;
 pub use new_brain::NewBrainRequest;
 
-type Response = std::result::Result<reqwest::Response, reqwest::Error>;
-
-async fn check_response<T>(resp: Response) -> Result<T>
-where
-    T: serde::de::DeserializeOwned,
-{
-    let resp = resp.map_err(|err| Error::Client { err })?;
-    if !resp.status().is_success() {
-        let error = Error::Server {
-            err: resp
-                .json::<types::Error>()
-                .await
-                .map_err(|err| Error::Client { err })?,
-        };
-        return Err(error);
-    }
-    resp.json().await.map_err(|err| Error::Client { err })
-}
+mod rename;
+pub use rename::RenameRequest;
+
+mod copy;
+pub use copy::CopyRequest;
+
+mod delete;
+pub use delete::DeleteRequest;
+
+mod reload;
+pub use reload::ReloadRequest;
+
+mod unload;
+pub use unload::UnloadRequest;
+
+mod reload_config;
+pub use reload_config::ReloadConfigRequest;
+
+mod upload;
+pub use upload::UploadRequest;
+
+mod job;
+pub use job::JobRequest;
+
+mod cancel_job;
+pub use cancel_job::CancelJobRequest;
@@ -1,8 +1,7 @@
 use super::*;
 
 pub struct NewBrainRequest<'a> {
-    pub(crate) url: &'a str,
-    pub(crate) client: &'a  reqwest::Client,
+    pub(crate) api: &'a dyn BrainApi,
     pub(crate) brain: String,
     pub(crate) brain_file: String,
     pub(crate) depth: Option<usize>,
@@ -15,16 +14,10 @@ impl<'a> NewBrainRequest<'a> {
     }
 
     pub async fn send(self) -> Result<responses::Created> {
-        let resp = self
-            .client
-            .post(&format!("{}/new/{}", self.url, self.brain))
-            .json(&input::NewBrain {
-                brain_file: self.brain_file,
-                depth: self.depth.unwrap_or(5),
-            })
-            .send()
-            .await;
-
-        check_response(resp).await
+        let input = input::NewBrain {
+            brain_file: self.brain_file,
+            depth: self.depth.unwrap_or(5),
+        };
+        self.api.send_new_brain(&self.brain, input).await
     }
 }
@@ -0,0 +1,12 @@
+use super::*;
+
+pub struct ReloadRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+}
+
+impl<'a> ReloadRequest<'a> {
+    pub async fn send(self) -> Result<responses::Reloaded> {
+        self.api.send_reload(&self.brain).await
+    }
+}
@@ -0,0 +1,11 @@
+use super::*;
+
+pub struct ReloadConfigRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+}
+
+impl<'a> ReloadConfigRequest<'a> {
+    pub async fn send(self) -> Result<responses::ConfigReloaded> {
+        self.api.send_reload_config().await
+    }
+}
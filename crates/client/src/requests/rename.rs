@@ -0,0 +1,14 @@
+use super::*;
+
+pub struct RenameRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+    pub(crate) name: String,
+}
+
+impl<'a> RenameRequest<'a> {
+    pub async fn send(self) -> Result<responses::Renamed> {
+        let input = input::RenameBrain { name: self.name };
+        self.api.send_rename(&self.brain, input).await
+    }
+}
@@ -0,0 +1,19 @@
+use super::*;
+
+pub struct TrainBatchRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+    pub(crate) data: Vec<String>,
+}
+
+impl<'a> TrainBatchRequest<'a> {
+    pub fn line(mut self, data: impl ToString) -> Self {
+        self.data.push(data.to_string());
+        self
+    }
+
+    pub async fn send(self) -> Result<responses::TrainedBatch> {
+        let data = input::TrainBatch { data: self.data };
+        self.api.send_train_batch(&self.brain, data).await
+    }
+}
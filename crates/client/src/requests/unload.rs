@@ -0,0 +1,12 @@
+use super::*;
+
+pub struct UnloadRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+}
+
+impl<'a> UnloadRequest<'a> {
+    pub async fn send(self) -> Result<responses::Unloaded> {
+        self.api.send_unload(&self.brain).await
+    }
+}
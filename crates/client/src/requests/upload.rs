@@ -0,0 +1,23 @@
+use super::*;
+
+pub struct UploadRequest<'a> {
+    pub(crate) api: &'a dyn BrainApi,
+    pub(crate) brain: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) gzip: bool,
+}
+
+impl<'a> UploadRequest<'a> {
+    /// The data is gzip compressed
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Starts the training job, returning once the upload has been received
+    pub async fn send(self) -> Result<responses::Job> {
+        self.api
+            .send_upload(&self.brain, self.data, self.gzip)
+            .await
+    }
+}
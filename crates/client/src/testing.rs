@@ -0,0 +1,427 @@
+//! A fake brain server for testing code that uses the [`Client`](crate::Client)
+//!
+//! The server is backed by a [`LocalClient`](crate::LocalClient), so seeded
+//! brains behave like they would on a real server. Responses and errors can be
+//! scripted per route, and every request is recorded for assertions.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use client::testing::{FakeServer, Route};
+//! use client::LocalClient;
+//!
+//! let server = FakeServer::start(LocalClient::new()).await;
+//! server.inject(Route::Train, client::types::Error::ReadOnly);
+//!
+//! let client = server.client();
+//! assert!(client.train("foo", "hello").send().await.is_err());
+//! server.assert_requested(Route::Train, 1);
+//! # }
+//! ```
+use crate::{BrainApi, Client, LocalClient, Result, WordStream};
+use types::{input, responses};
+
+use futures::StreamExt as _;
+use hashbrown::HashMap;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// The routes the brain server provides
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Route {
+    Generate,
+    GenerateStream,
+    GenerateBatch,
+    Train,
+    TrainBatch,
+    NewBrain,
+    Rename,
+    Copy,
+    Save,
+    List,
+    Info,
+    Delete,
+    Reload,
+    Unload,
+    ReloadConfig,
+    Upload,
+    Job,
+    CancelJob,
+}
+
+/// A request the fake server received
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded {
+    pub route: Route,
+    /// The brain in the path, or the id for the job routes
+    pub brain: Option<String>,
+    pub query: Option<String>,
+    pub body: String,
+}
+
+struct Scripted {
+    status: StatusCode,
+    body: String,
+}
+
+struct State {
+    api: LocalClient,
+    scripted: Mutex<HashMap<Route, VecDeque<Scripted>>>,
+    requests: Mutex<Vec<Recorded>>,
+}
+
+/// A local HTTP server that acts like the brain server
+///
+/// The server shuts down when this is dropped.
+pub struct FakeServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    _shutdown: tokio::sync::oneshot::Sender<()>,
+}
+
+impl FakeServer {
+    /// Starts the server on a random local port, serving the brains in `api`
+    pub async fn start(api: LocalClient) -> Self {
+        let state = Arc::new(State {
+            api,
+            scripted: Default::default(),
+            requests: Default::default(),
+        });
+
+        let service = {
+            let state = Arc::clone(&state);
+            make_service_fn(move |_| {
+                let state = Arc::clone(&state);
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&state), req)))
+                }
+            })
+        };
+
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let addr = server.local_addr();
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = rx.await;
+        }));
+
+        Self {
+            addr,
+            state,
+            _shutdown: tx,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Creates a client connected to this server
+    pub fn client(&self) -> Client {
+        Client::new(self.url())
+    }
+
+    /// Responds to the next request for `route` with `item`, instead of the brains
+    pub fn script<T>(&self, route: Route, item: &T)
+    where
+        T: Serialize,
+    {
+        self.push(route, StatusCode::OK, serde_json::to_string(item).unwrap())
+    }
+
+    /// Fails the next request for `route` with `error`
+    pub fn inject(&self, route: Route, err: types::Error) {
+        self.push(
+            route,
+            status_for(&err),
+            serde_json::to_string(&err).unwrap(),
+        )
+    }
+
+    /// Every request received so far, in order
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Every request received so far for `route`
+    pub fn requests_for(&self, route: Route) -> Vec<Recorded> {
+        self.requests()
+            .into_iter()
+            .filter(|req| req.route == route)
+            .collect()
+    }
+
+    /// Asserts that `route` was requested exactly `times` times
+    pub fn assert_requested(&self, route: Route, times: usize) {
+        let count = self.requests_for(route).len();
+        assert_eq!(
+            count, times,
+            "expected {:?} to be requested {} time(s), got {}",
+            route, times, count
+        );
+    }
+
+    /// Asserts that `route` was requested for `brain` at least once
+    pub fn assert_requested_for(&self, route: Route, brain: &str) {
+        let found = self
+            .requests_for(route)
+            .iter()
+            .any(|req| req.brain.as_deref() == Some(brain));
+        assert!(
+            found,
+            "expected {:?} to be requested for '{}'",
+            route, brain
+        );
+    }
+
+    /// Asserts that no scripted responses or injected errors are left
+    pub fn assert_script_consumed(&self) {
+        let scripted = self.state.scripted.lock().unwrap();
+        let left = scripted
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(route, _)| *route)
+            .collect::<Vec<_>>();
+        assert!(left.is_empty(), "scripted responses left for: {:?}", left);
+    }
+
+    fn push(&self, route: Route, status: StatusCode, body: String) {
+        self.state
+            .scripted
+            .lock()
+            .unwrap()
+            .entry(route)
+            .or_default()
+            .push_back(Scripted { status, body });
+    }
+}
+
+impl std::fmt::Debug for FakeServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeServer")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+/// The status code the server uses for `error`
+pub fn status_for(error: &types::Error) -> StatusCode {
+    StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::BAD_REQUEST)
+}
+
+async fn handle(
+    state: Arc<State>,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let raw = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let body = String::from_utf8_lossy(&raw).to_string();
+
+    let segments = parts
+        .uri
+        .path()
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+    let (route, brain) = match (&parts.method, segments.as_slice()) {
+        (&Method::GET, ["v1", "brains"]) => (Route::List, None),
+        (&Method::GET, ["v1", "brains", brain]) => (Route::Info, Some(brain)),
+        (&Method::POST, ["v1", "brains", brain]) => (Route::NewBrain, Some(brain)),
+        (&Method::DELETE, ["v1", "brains", brain]) => (Route::Delete, Some(brain)),
+        (&Method::GET, ["v1", "brains", brain, "generate"]) => (Route::Generate, Some(brain)),
+        (&Method::GET, ["v1", "brains", brain, "generate", "stream"]) => {
+            (Route::GenerateStream, Some(brain))
+        }
+        (&Method::GET, ["v1", "brains", brain, "generate", "batch"]) => {
+            (Route::GenerateBatch, Some(brain))
+        }
+        (&Method::POST, ["v1", "brains", brain, "train"]) => (Route::Train, Some(brain)),
+        (&Method::POST, ["v1", "brains", brain, "train", "batch"]) => {
+            (Route::TrainBatch, Some(brain))
+        }
+        (&Method::POST, ["v1", "brains", brain, "save"]) => (Route::Save, Some(brain)),
+        (&Method::POST, ["v1", "brains", brain, "reload"]) => (Route::Reload, Some(brain)),
+        (&Method::POST, ["v1", "brains", brain, "unload"]) => (Route::Unload, Some(brain)),
+        (&Method::POST, ["v1", "brains", brain, "rename"]) => (Route::Rename, Some(brain)),
+        (&Method::POST, ["v1", "brains", brain, "copy"]) => (Route::Copy, Some(brain)),
+        (&Method::POST, ["v1", "config", "reload"]) => (Route::ReloadConfig, None),
+        (&Method::POST, ["v1", "brains", brain, "upload"]) => (Route::Upload, Some(brain)),
+        (&Method::GET, ["v1", "jobs", id]) => (Route::Job, Some(id)),
+        (&Method::DELETE, ["v1", "jobs", id]) => (Route::CancelJob, Some(id)),
+        // the legacy routes
+        (&Method::GET, ["generate", brain]) => (Route::Generate, Some(brain)),
+        (&Method::GET, ["generate", brain, "stream"]) => (Route::GenerateStream, Some(brain)),
+        (&Method::GET, ["generate", brain, "batch"]) => (Route::GenerateBatch, Some(brain)),
+        (&Method::POST, ["train", brain]) => (Route::Train, Some(brain)),
+        (&Method::POST, ["train", brain, "batch"]) => (Route::TrainBatch, Some(brain)),
+        (&Method::POST, ["new", brain]) => (Route::NewBrain, Some(brain)),
+        (&Method::PUT, ["save", brain]) => (Route::Save, Some(brain)),
+        (&Method::GET, ["list"]) => (Route::List, None),
+        (&Method::GET, ["brain", brain]) => (Route::Info, Some(brain)),
+        (&Method::DELETE, ["brain", brain]) => (Route::Delete, Some(brain)),
+        (&Method::POST, ["reload", brain]) => (Route::Reload, Some(brain)),
+        (&Method::POST, ["unload", brain]) => (Route::Unload, Some(brain)),
+        (&Method::POST, ["reload-config"]) => (Route::ReloadConfig, None),
+        (&Method::POST, ["upload", brain]) => (Route::Upload, Some(brain)),
+        (&Method::GET, ["jobs", id]) => (Route::Job, Some(id)),
+        (&Method::DELETE, ["jobs", id]) => (Route::CancelJob, Some(id)),
+        _ => return Ok(error(&types::Error::UnknownRoute)),
+    };
+
+    let recorded = Recorded {
+        route,
+        brain: brain.map(|brain| brain.to_string()),
+        query: parts.uri.query().map(ToString::to_string),
+        body,
+    };
+    state.requests.lock().unwrap().push(recorded.clone());
+
+    let scripted = state
+        .scripted
+        .lock()
+        .unwrap()
+        .get_mut(&route)
+        .and_then(VecDeque::pop_front);
+    if let Some(Scripted { status, body }) = scripted {
+        return Ok(reply(status, body));
+    }
+
+    let api = &state.api;
+    let brain = recorded.brain.as_deref().unwrap_or_default();
+    let query = recorded.query.as_deref().unwrap_or_default();
+    let body = &recorded.body;
+
+    let resp = match route {
+        Route::Generate => match parse_query(query) {
+            Ok(opts) => respond(api.send_generate(brain, opts).await),
+            Err(err) => error(&err),
+        },
+        Route::GenerateStream => match parse_query(query) {
+            Ok(opts) => match api.send_generate_stream(brain, opts).await {
+                Ok(words) => events(words).await,
+                Err(err) => respond::<()>(Err(err)),
+            },
+            Err(err) => error(&err),
+        },
+        Route::GenerateBatch => match parse_query(query) {
+            Ok(opts) => respond(api.send_generate_batch(brain, opts).await),
+            Err(err) => error(&err),
+        },
+        Route::Train => match parse_body::<input::TrainData>(body) {
+            Ok(data) => respond(api.send_train(brain, data).await),
+            Err(err) => error(&err),
+        },
+        Route::TrainBatch => match parse_body::<input::TrainBatch>(body) {
+            Ok(data) => respond(api.send_train_batch(brain, data).await),
+            Err(err) => error(&err),
+        },
+        Route::NewBrain => match parse_body::<input::NewBrain>(body) {
+            Ok(input) => respond(api.send_new_brain(brain, input).await),
+            Err(err) => error(&err),
+        },
+        Route::Rename => match parse_body::<input::RenameBrain>(body) {
+            Ok(input) => respond(api.send_rename(brain, input).await),
+            Err(err) => error(&err),
+        },
+        Route::Copy => match parse_body::<input::CopyBrain>(body) {
+            Ok(input) => respond(api.send_copy(brain, input).await),
+            Err(err) => error(&err),
+        },
+        Route::Save => respond(api.send_save(brain).await),
+        Route::List => respond(api.send_list().await),
+        Route::Info => respond(api.send_info(brain).await),
+        Route::Delete => match parse_query(query) {
+            Ok(opts) => respond(api.send_delete(brain, opts).await),
+            Err(err) => error(&err),
+        },
+        Route::Reload => respond(api.send_reload(brain).await),
+        Route::Unload => respond(api.send_unload(brain).await),
+        Route::ReloadConfig => respond::<responses::ConfigReloaded>(api.send_reload_config().await),
+        Route::Upload => {
+            let header = |name| parts.headers.get(name).and_then(|v| v.to_str().ok());
+            let gzip = header("content-type") == Some("application/gzip")
+                || header("content-encoding") == Some("gzip");
+            match api.send_upload(brain, raw.to_vec(), gzip).await {
+                Ok(job) => reply(StatusCode::ACCEPTED, serde_json::to_string(&job).unwrap()),
+                Err(err) => respond::<()>(Err(err)),
+            }
+        }
+        Route::Job | Route::CancelJob => match brain.parse() {
+            Ok(id) if route == Route::Job => respond(api.send_job(id).await),
+            Ok(id) => respond(api.send_cancel_job(id).await),
+            Err(..) => error(&types::Error::UnknownRoute),
+        },
+    };
+    Ok(resp)
+}
+
+fn parse_query<T>(query: &str) -> std::result::Result<T, types::Error>
+where
+    T: DeserializeOwned,
+{
+    serde_urlencoded::from_str(query).map_err(|err| types::Error::InvalidQuery {
+        reason: err.to_string(),
+    })
+}
+
+fn parse_body<T>(body: &str) -> std::result::Result<T, types::Error>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(body).map_err(|err| types::Error::InvalidBody {
+        reason: err.to_string(),
+    })
+}
+
+fn respond<T>(result: Result<T>) -> Response<Body>
+where
+    T: Serialize,
+{
+    match result {
+        Ok(item) => reply(StatusCode::OK, serde_json::to_string(&item).unwrap()),
+        Err(err) => match err.server_error() {
+            Some(err) => error(&err),
+            None => error(&types::Error::Internal {
+                reason: err.to_string(),
+            }),
+        },
+    }
+}
+
+// the words as server-sent events, like the server sends them
+async fn events(mut words: WordStream) -> Response<Body> {
+    let mut body = String::new();
+    while let Some(Ok(word)) = words.next().await {
+        body.push_str(&format!("event:token\ndata:{}\n\n", word));
+    }
+    body.push_str("event:done\ndata:\n\n");
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/event-stream")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn error(err: &types::Error) -> Response<Body> {
+    reply(status_for(err), serde_json::to_string(err).unwrap())
+}
+
+fn reply(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
@@ -37,7 +37,7 @@ async fn generate() {
         server.expect(
             Expectation::matching(all_of![
                 request::method("GET"), //
-                request::path("/generate/foo"),
+                request::path("/v1/brains/foo/generate"),
                 request::query(url_decoded(eq(k)))
             ])
             .respond_with(json_encoded(&generated)),
@@ -48,6 +48,34 @@ async fn generate() {
     }
 }
 
+#[tokio::test]
+async fn generate_stream() {
+    use futures::TryStreamExt as _;
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("GET"), //
+            request::path("/v1/brains/foo/generate/stream"),
+            request::query(url_decoded(eq(vec![KV::new("max", "2")])))
+        ])
+        .respond_with(
+            status_code(200)
+                .insert_header("content-type", "text/event-stream")
+                .body(
+                    "event:token\ndata:hello\n\nevent: token\ndata: world\n\nevent:done\ndata:\n\n",
+                ),
+        ),
+    );
+
+    let url = format!("http://{}", server.addr());
+    let client = Client::new(&url);
+    let words = client.generate("foo").max(2).stream().await.unwrap();
+    let words = words.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(words, vec!["hello", "world"]);
+}
+
 #[tokio::test]
 async fn train() {
     use httptest::{mappers::*, responders::*, Expectation, Server};
@@ -62,7 +90,7 @@ async fn train() {
     server.expect(
         Expectation::matching(all_of![
             request::method("POST"), //
-            request::path("/train/foo"),
+            request::path("/v1/brains/foo/train"),
             request::body(
                 serde_json::to_string(&types::input::TrainData { data: data.clone() }).unwrap()
             )
@@ -80,6 +108,116 @@ async fn train() {
     assert_eq!(resp, trained);
 }
 
+#[tokio::test]
+async fn batches() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+    use types::responses::{BatchItem, GeneratedBatch, Trained, TrainedBatch};
+
+    let generated = GeneratedBatch {
+        name: "foo".into(),
+        results: vec![
+            BatchItem::Ok("hello world".into()),
+            BatchItem::Error(types::Error::NotEnoughState),
+        ],
+    };
+    let trained = TrainedBatch {
+        results: vec![BatchItem::Ok(Trained {
+            data: "hello".into(),
+            time: std::time::Duration::from_millis(1),
+        })],
+        time: std::time::Duration::from_millis(1),
+    };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("GET"), //
+            request::path("/v1/brains/foo/generate/batch"),
+            request::query(url_decoded(eq(vec![
+                KV::new("count", "2"),
+                KV::new("max", "10")
+            ])))
+        ])
+        .respond_with(json_encoded(&generated)),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/brains/foo/train/batch"),
+            request::body(r#"{"data":["hello","world"]}"#)
+        ])
+        .respond_with(json_encoded(&trained)),
+    );
+
+    let client = Client::new(format!("http://{}", server.addr()));
+    let resp = client
+        .generate_batch("foo", 2)
+        .max(10)
+        .send()
+        .await
+        .unwrap();
+    let mut results = resp.results.into_iter().map(BatchItem::into_result);
+    assert_eq!(results.next().unwrap().unwrap(), "hello world");
+    assert!(results.next().unwrap().is_err());
+
+    let resp = client
+        .train_batch("foo", vec!["hello"])
+        .line("world")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.results.len(), 1);
+}
+
+#[tokio::test]
+async fn upload() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+    use types::responses::{Job, JobState};
+
+    let job = Job {
+        id: 1,
+        brain: "foo".into(),
+        state: JobState::Running,
+        lines: 0,
+        total: 2,
+        lines_per_sec: 0.0,
+        elapsed: std::time::Duration::from_millis(1),
+        reason: None,
+    };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/brains/foo/upload"),
+            request::headers(contains(("content-type", "application/gzip"))),
+        ])
+        .respond_with(status_code(202).body(serde_json::to_string(&job).unwrap())),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("DELETE"), //
+            request::path("/v1/jobs/1"),
+        ])
+        .respond_with(json_encoded(&Job {
+            state: JobState::Cancelled,
+            ..job.clone()
+        })),
+    );
+
+    let client = Client::new(format!("http://{}", server.addr()));
+    let resp = client
+        .upload("foo", vec![0x1f, 0x8b])
+        .gzip(true)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.id, 1);
+
+    let resp = client.cancel_job(1).send().await.unwrap();
+    assert_eq!(resp.state, JobState::Cancelled);
+}
+
 #[tokio::test]
 async fn new_brain() {
     use httptest::{mappers::*, responders::*, Expectation, Server};
@@ -93,7 +231,7 @@ async fn new_brain() {
     server.expect(
         Expectation::matching(all_of![
             request::method("POST"), //
-            request::path("/new/foo"),
+            request::path("/v1/brains/foo"),
             request::body(
                 serde_json::to_string(&types::input::NewBrain {
                     brain_file: "foo_brain".into(),
@@ -127,8 +265,8 @@ async fn save() {
     let server = Server::run();
     server.expect(
         Expectation::matching(all_of![
-            request::method("PUT"), //
-            request::path("/save/foo"),
+            request::method("POST"), //
+            request::path("/v1/brains/foo/save"),
         ])
         .respond_with(json_encoded(&save_response)),
     );
@@ -150,8 +288,8 @@ async fn save_error() {
     let server = Server::run();
     server.expect(
         Expectation::matching(all_of![
-            request::method("PUT"), //
-            request::path("/save/foo"),
+            request::method("POST"), //
+            request::path("/v1/brains/foo/save"),
         ])
         .respond_with(
             status_code(400).body(
@@ -178,6 +316,153 @@ async fn save_error() {
     )
 }
 
+#[tokio::test]
+async fn typed_errors() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/brains/foo/train"),
+        ])
+        .respond_with(
+            status_code(404).body(
+                serde_json::to_string(&types::Error::NotFound { name: "foo".into() }).unwrap(),
+            ),
+        ),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/brains/foo"),
+        ])
+        .respond_with(
+            status_code(413).body(serde_json::to_string(&types::Error::PayloadTooLarge).unwrap()),
+        ),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("GET"), //
+            request::path("/v1/brains"),
+        ])
+        .respond_with(status_code(502).body("bad gateway")),
+    );
+
+    let client = Client::new(format!("http://{}", server.addr()));
+
+    let err = client.train("foo", "bar").send().await.unwrap_err();
+    matches::assert_matches!(err, Error::NotFound { ref name } if name == "foo");
+
+    let err = client.new_brain("foo", "foo.db").send().await.unwrap_err();
+    matches::assert_matches!(err, Error::PayloadTooLarge);
+
+    let err = client.list().send().await.unwrap_err();
+    matches::assert_matches!(err, Error::Unexpected { status: 502, .. });
+}
+
+#[test]
+fn invalid_errors_round_trip() {
+    let reason = || "bad".to_string();
+    let errors = vec![
+        types::Error::InvalidBody { reason: reason() },
+        types::Error::InvalidQuery { reason: reason() },
+        types::Error::InvalidHeader { reason: reason() },
+    ];
+    for expected in errors {
+        let err = Error::from(expected.clone()).server_error().unwrap();
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn token() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let generated = types::responses::Generated {
+        name: "foo".into(),
+        data: "hello world".into(),
+    };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("GET"), //
+            request::path("/v1/brains/foo/generate"),
+            request::headers(contains(("authorization", "Bearer secret"))),
+        ])
+        .respond_with(json_encoded(&generated)),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("GET"), //
+            request::path("/v1/brains/foo/generate"),
+            request::headers(not(contains(key("authorization")))),
+        ])
+        .respond_with(
+            status_code(401).body(serde_json::to_string(&types::Error::Unauthorized).unwrap()),
+        ),
+    );
+
+    let url = format!("http://{}", server.addr());
+    let resp = Client::new(&url)
+        .token("secret")
+        .generate("foo")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp, generated);
+
+    let err = Client::new(&url).generate("foo").send().await.unwrap_err();
+    matches::assert_matches!(
+        err,
+        Error::Server {
+            err: types::Error::Unauthorized
+        }
+    );
+}
+
+#[tokio::test]
+async fn rate_limited() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let generated = types::responses::Generated {
+        name: "foo".into(),
+        data: "hello world".into(),
+    };
+    let limited = serde_json::to_string(&types::Error::RateLimited { retry_after: 0.01 }).unwrap();
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("GET"), //
+            request::path("/v1/brains/foo/generate"),
+        ])
+        .times(4)
+        .respond_with(cycle![
+            status_code(429).body(limited),
+            json_encoded(&generated),
+        ]),
+    );
+
+    let url = format!("http://{}", server.addr());
+    let client = Client::new(&url);
+    let err = client.generate("foo").send().await.unwrap_err();
+    matches::assert_matches!(
+        err,
+        Error::RateLimited { retry_after } if retry_after.as_millis() == 10
+    );
+    let resp = client.generate("foo").send().await.unwrap();
+    assert_eq!(resp, generated);
+
+    let client = Client::new(&url).retry_rate_limited(1);
+    let resp = client.generate("foo").send().await.unwrap();
+    assert_eq!(resp, generated);
+}
+
 #[tokio::test]
 async fn list_ok() {
     use httptest::{mappers::*, responders::*, Expectation, Server};
@@ -191,6 +476,13 @@ async fn list_ok() {
                     name: "foo".into(),
                     brain_file: "foo_brain.db".into(),
                     read_only: true,
+                    metadata: Some(types::responses::Metadata {
+                        depth: 5,
+                        created: Some(1_600_000_000),
+                        lines: 42,
+                        file_size: Some(1024),
+                        ..Default::default()
+                    }),
                 },
             );
             map.insert(
@@ -199,10 +491,13 @@ async fn list_ok() {
                     name: "bar_brain".into(),
                     brain_file: "bar.db".into(),
                     read_only: false,
+                    metadata: None,
                 },
             );
             map
         },
+        failed: Default::default(),
+        loading: Default::default(),
         config_path: "local_config.toml".into(),
     };
 
@@ -210,7 +505,7 @@ async fn list_ok() {
     server.expect(
         Expectation::matching(all_of![
             request::method("GET"), //
-            request::path("/list")
+            request::path("/v1/brains")
         ])
         .respond_with(json_encoded(&list_response)),
     );
@@ -224,3 +519,852 @@ async fn list_ok() {
 
     assert_eq!(resp, list_response);
 }
+
+#[tokio::test]
+async fn delete() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let deleted = types::responses::Deleted {
+        name: "foo".into(),
+        brain_file: "foo.db".into(),
+        file_deleted: true,
+        config_updated: true,
+        errors: vec![],
+    };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("DELETE"), //
+            request::path("/v1/brains/foo"),
+            request::query(url_decoded(eq(vec![KV::new("delete_file", "true")])))
+        ])
+        .respond_with(json_encoded(&deleted)),
+    );
+
+    let addr = server.addr();
+    let resp = Client::new(format!("http://{}", addr))
+        .delete("foo")
+        .delete_file(true)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp, deleted);
+}
+
+#[tokio::test]
+async fn reload() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let reloaded = types::responses::Reloaded {
+        name: "foo".into(),
+        brain_file: "foo.db".into(),
+        time: std::time::Duration::from_millis(42),
+    };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/brains/foo/reload"),
+        ])
+        .respond_with(json_encoded(&reloaded)),
+    );
+
+    let addr = server.addr();
+    let resp = Client::new(format!("http://{}", addr))
+        .reload("foo")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp, reloaded);
+}
+
+#[tokio::test]
+async fn unload() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let unloaded = types::responses::Unloaded { name: "foo".into() };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/brains/foo/unload"),
+        ])
+        .respond_with(json_encoded(&unloaded)),
+    );
+
+    let addr = server.addr();
+    let resp = Client::new(format!("http://{}", addr))
+        .unload("foo")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp, unloaded);
+}
+
+#[tokio::test]
+async fn reload_config() {
+    use httptest::{mappers::*, responders::*, Expectation, Server};
+
+    let reloaded = types::responses::ConfigReloaded {
+        added: vec!["foo".into()],
+        removed: vec!["bar".into()],
+        ..Default::default()
+    };
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method("POST"), //
+            request::path("/v1/config/reload"),
+        ])
+        .respond_with(json_encoded(&reloaded)),
+    );
+
+    let addr = server.addr();
+    let resp = Client::new(format!("http://{}", addr))
+        .reload_config()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp, reloaded);
+}
+
+#[cfg(feature = "local")]
+mod local {
+    use super::*;
+    use matches::assert_matches;
+    use tempdir::TempDir;
+
+    const LOREM_IPSUM: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. \
+                               Donec ornare mi vitae fermentum aliquet.";
+
+    fn make_client(dir: &TempDir) -> LocalClient {
+        let mut markov = markov::Markov::new(3, "foo");
+        markov.train_text(LOREM_IPSUM);
+        let bar = markov::Markov::new(3, "bar");
+        LocalClient::new()
+            .with_brain("foo", file(dir, "foo.db"), markov)
+            .with_read_only_brain("bar", file(dir, "bar.db"), bar)
+    }
+
+    fn file(dir: &TempDir, name: &str) -> String {
+        dir.path().join(name).to_string_lossy().to_string()
+    }
+
+    // the same builder code runs against any backend
+    async fn generate_from(api: &dyn BrainApi) -> Result<types::responses::Generated> {
+        api.generate("foo").min(1).max(10).send().await
+    }
+
+    #[tokio::test]
+    async fn generate() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let resp = generate_from(&client).await.unwrap();
+        assert_eq!(resp.name, "foo");
+
+        let err = client.generate("bar").send().await.unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::NotEnoughState
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_defaults() {
+        use futures::TryStreamExt as _;
+        use types::generate::{GenerateConfig, GenerateDefaults};
+
+        let defaults = GenerateDefaults {
+            max_words: Some(2),
+            hard_max_words: Some(50),
+            ..Default::default()
+        };
+        let config = GenerateConfig {
+            batch_limit: Some(4),
+            ..Default::default()
+        };
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir)
+            .with_defaults(defaults)
+            .with_batch_limit(3)
+            .with_generate("foo", config);
+
+        let words = client.generate("foo").max(40).stream().await.unwrap();
+        let words = words.try_collect::<Vec<_>>().await.unwrap();
+        assert!(!words.is_empty() && words.len() <= 2);
+
+        let err = client.generate("foo").max(60).send().await.unwrap_err();
+        assert_matches!(err, Error::InvalidQuery { .. });
+
+        // the brain's own limit, which its copies keep
+        client.generate_batch("foo", 4).send().await.unwrap();
+        let err = client.generate_batch("foo", 5).send().await.unwrap_err();
+        assert_matches!(err, Error::InvalidQuery { .. });
+        client
+            .copy("foo", "baz", file(&dir, "baz.db"))
+            .send()
+            .await
+            .unwrap();
+        client.generate_batch("baz", 4).send().await.unwrap();
+
+        // the client's limit
+        client
+            .new_brain("quux", file(&dir, "quux.db"))
+            .send()
+            .await
+            .unwrap();
+        let err = client.generate_batch("quux", 4).send().await.unwrap_err();
+        assert_matches!(err, Error::InvalidQuery { .. });
+    }
+
+    #[tokio::test]
+    async fn generate_stream() {
+        use futures::TryStreamExt as _;
+
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let words = client.generate("foo").max(10).stream().await.unwrap();
+        let words = words.try_collect::<Vec<_>>().await.unwrap();
+        assert!(!words.is_empty() && words.len() <= 10);
+        assert!(words.iter().all(|word| LOREM_IPSUM.contains(word.as_str())));
+
+        let err = client.generate("bar").stream().await.err().unwrap();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::NotEnoughState
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn train() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let resp = client.train("foo", "hello world").send().await.unwrap();
+        assert_eq!(resp.data, "hello world");
+
+        let err = client.train("bar", "hello world").send().await.unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::ReadOnly
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn batches() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let resp = client
+            .generate_batch("foo", 3)
+            .max(10)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.results.len(), 3);
+        assert!(resp
+            .results
+            .into_iter()
+            .all(|item| item.into_result().is_ok()));
+
+        let err = client.generate_batch("foo", 0).send().await.unwrap_err();
+        assert_matches!(err, Error::InvalidQuery { .. });
+
+        let resp = client
+            .train_batch("foo", vec!["hello world", ""])
+            .send()
+            .await
+            .unwrap();
+        let mut results = resp.results.into_iter().map(|item| item.into_result());
+        assert_eq!(results.next().unwrap().unwrap().data, "hello world");
+        assert_matches!(
+            results.next().unwrap(),
+            Err(types::Error::InvalidBody { .. })
+        );
+
+        let err = client
+            .train_batch("bar", vec!["hello world"])
+            .send()
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::ReadOnly
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn upload() {
+        use std::io::Write as _;
+        use types::responses::JobState;
+
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let job = client
+            .upload("foo", "hello world\nfoo bar baz\n")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(job.state, JobState::Done);
+        assert_eq!((job.lines, job.total), (2, 2));
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+        encoder.write_all(b"hello\nworld").unwrap();
+        let data = encoder.finish().unwrap();
+        let job = client.upload("foo", data).gzip(true).send().await.unwrap();
+        assert_eq!((job.lines, job.total), (2, 2));
+
+        let resp = client.job(job.id).send().await.unwrap();
+        assert_eq!(resp.id, job.id);
+
+        let err = client.job(1234).send().await.unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::JobNotFound { id: 1234 }
+            }
+        );
+
+        let err = client.upload("bar", "hello").send().await.unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::ReadOnly
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn new_brain() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let resp = client
+            .new_brain("baz", file(&dir, "baz.db"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.name, "baz");
+
+        let err = client
+            .new_brain("foo", file(&dir, "foo.db"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::AlreadyExists { .. }
+            }
+        );
+
+        let list = client.list().send().await.unwrap();
+        assert!(list.brains.contains_key("baz"));
+
+        // like the server, the new brain is saved, and saving keeps a backup
+        assert!(dir.path().join("baz.db").exists());
+        client.save("baz").send().await.unwrap();
+        assert!(dir.path().join("baz.bak").exists());
+
+        let err = client
+            .new_brain("not valid", file(&dir, "qux.db"))
+            .send()
+            .await;
+        assert_matches!(err, Err(Error::InvalidQuery { .. }));
+        let err = client.new_brain("qux", file(&dir, "foo.db")).send().await;
+        assert_matches!(err, Err(Error::InvalidBody { .. }));
+        assert!(!dir.path().join("qux.db").exists());
+    }
+
+    #[tokio::test]
+    async fn info() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let item = client.info("foo").send().await.unwrap();
+        assert_eq!(
+            item.brain_file,
+            std::path::PathBuf::from(file(&dir, "foo.db"))
+        );
+        let metadata = item.metadata.unwrap();
+        assert_eq!(metadata.depth, 3);
+        assert_eq!(metadata.lines, 1);
+        assert_eq!(metadata.file_size, None);
+        assert!(metadata.dirty);
+
+        let list = client.list().send().await.unwrap();
+        assert_eq!(list.brains["foo"].metadata, Some(metadata));
+
+        let err = client.info("baz").send().await.unwrap_err();
+        assert_matches!(err, Error::NotFound { .. });
+    }
+
+    #[tokio::test]
+    async fn rename_and_copy() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let resp = client.rename("foo", "baz").send().await.unwrap();
+        assert_eq!((resp.from.as_str(), resp.name.as_str()), ("foo", "baz"));
+        assert_matches!(client.info("foo").send().await, Err(Error::NotFound { .. }));
+
+        let resp = client
+            .copy("baz", "qux", file(&dir, "qux.db"))
+            .depth(2)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.depth, 2);
+        let metadata = client.info("qux").send().await.unwrap().metadata.unwrap();
+        assert_eq!(metadata.depth, 2);
+        assert_eq!(metadata.source.as_deref(), Some("copied from baz"));
+        client.generate("qux").send().await.unwrap();
+
+        let err = client
+            .copy("baz", "bar", file(&dir, "bar2.db"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::AlreadyExists { .. }
+            }
+        );
+        let err = client
+            .copy("baz", "quux", file(&dir, "quux.db"))
+            .depth(4)
+            .send()
+            .await;
+        assert_matches!(err, Err(Error::InvalidBody { .. }));
+        let err = client.rename("baz", "not valid").send().await;
+        assert_matches!(err, Err(Error::InvalidBody { .. }));
+    }
+
+    #[tokio::test]
+    async fn unknown_brain() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        let err = client.save("baz").send().await.unwrap_err();
+        assert_matches!(err, Error::NotFound { .. });
+    }
+
+    #[tokio::test]
+    async fn unload_and_delete() {
+        let dir = TempDir::new("client_tests").unwrap();
+        let client = make_client(&dir);
+        client.unload("foo").send().await.unwrap();
+
+        let list = client.list().send().await.unwrap();
+        assert!(!list.brains.contains_key("foo"));
+        assert!(list.brains.contains_key("bar"));
+
+        let err = client.generate("foo").send().await.unwrap_err();
+        assert_matches!(err, Error::NotFound { .. });
+        assert!(dir.path().join("foo.db").exists());
+
+        // an unloaded brain keeps its name
+        let err = client.new_brain("foo", file(&dir, "foo2.db")).send().await;
+        assert_matches!(
+            err,
+            Err(Error::Server {
+                err: types::Error::AlreadyExists { .. }
+            })
+        );
+
+        let resp = client.delete("bar").send().await.unwrap();
+        assert!(!resp.file_deleted);
+        assert!(client.list().send().await.unwrap().brains.is_empty());
+    }
+}
+
+#[cfg(feature = "testing")]
+mod fake_server {
+    use super::*;
+    use crate::testing::{FakeServer, Route};
+    use matches::assert_matches;
+
+    async fn make_server() -> FakeServer {
+        let mut markov = markov::Markov::new(3, "foo");
+        markov.train_text("Lorem ipsum dolor sit amet, consectetur adipiscing elit.");
+        FakeServer::start(LocalClient::new().with_brain("foo", "foo.db", markov)).await
+    }
+
+    #[tokio::test]
+    async fn seeded() {
+        let server = make_server().await;
+        let client = server.client();
+
+        let resp = client.generate("foo").min(1).send().await.unwrap();
+        assert_eq!(resp.name, "foo");
+
+        client.train("foo", "hello world").send().await.unwrap();
+        let list = client.list().send().await.unwrap();
+        assert!(list.brains.contains_key("foo"));
+
+        let err = client.train("bar", "hello world").send().await.unwrap_err();
+        assert_matches!(err, Error::NotFound { .. });
+
+        server.assert_requested(Route::Train, 2);
+        server.assert_requested_for(Route::Generate, "foo");
+
+        let requests = server.requests_for(Route::Generate);
+        assert_eq!(requests[0].query.as_deref(), Some("min=1"));
+    }
+
+    #[tokio::test]
+    async fn streamed() {
+        use futures::TryStreamExt as _;
+
+        let server = make_server().await;
+        let client = server.client();
+
+        let words = client.generate("foo").min(1).stream().await.unwrap();
+        let words = words.try_collect::<Vec<_>>().await.unwrap();
+        assert!(!words.is_empty());
+        server.assert_requested_for(Route::GenerateStream, "foo");
+
+        let err = client.generate("bar").stream().await.err().unwrap();
+        assert_matches!(err, Error::NotFound { .. });
+    }
+
+    #[tokio::test]
+    async fn batched() {
+        let server = make_server().await;
+        let client = server.client();
+
+        let resp = client.generate_batch("foo", 2).min(1).send().await.unwrap();
+        assert_eq!(resp.results.len(), 2);
+        server.assert_requested_for(Route::GenerateBatch, "foo");
+
+        let resp = client
+            .train_batch("foo", vec!["hello", "world"])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.results.len(), 2);
+        server.assert_requested(Route::TrainBatch, 1);
+    }
+
+    #[tokio::test]
+    async fn scripted() {
+        let server = make_server().await;
+        let generated = types::responses::Generated {
+            name: "foo".into(),
+            data: "scripted".into(),
+        };
+        server.script(Route::Generate, &generated);
+
+        let client = server.client();
+        let resp = client.generate("foo").send().await.unwrap();
+        assert_eq!(resp, generated);
+        server.assert_script_consumed();
+
+        // falls back to the brains once the script is consumed
+        let resp = client.generate("foo").send().await.unwrap();
+        assert_ne!(resp.data, "scripted");
+    }
+
+    #[tokio::test]
+    async fn injected() {
+        let server = make_server().await;
+        server.inject(
+            Route::Save,
+            types::Error::CannotSave {
+                file: "foo.db".into(),
+                reason: "disk full".into(),
+            },
+        );
+
+        let client = server.client();
+        let err = client.save("foo").send().await.unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::CannotSave { .. }
+            }
+        );
+        server.assert_script_consumed();
+    }
+}
+
+#[cfg(feature = "ws")]
+mod websocket {
+    use super::*;
+    use crate::ws::Connection;
+    use futures::{SinkExt as _, StreamExt as _};
+    use matches::assert_matches;
+    use tokio_tungstenite::tungstenite::Message;
+    use types::ws::{Request, Response};
+
+    fn text(resp: &Response) -> Message {
+        Message::Text(serde_json::to_string(resp).unwrap())
+    }
+
+    // answers generate requests in pairs, in reverse order
+    async fn serve() -> String {
+        let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let mut held = vec![];
+
+            while let Some(Ok(Message::Text(msg))) = socket.next().await {
+                match serde_json::from_str(&msg).unwrap() {
+                    Request::Generate { id, brain, .. } => {
+                        let generated = types::responses::Generated {
+                            name: brain,
+                            data: "hello world".into(),
+                        };
+                        held.push(Response::Generated { id, generated });
+                        if held.len() == 2 {
+                            for resp in held.drain(..).rev() {
+                                socket.send(text(&resp)).await.unwrap();
+                            }
+                        }
+                    }
+                    Request::Train { id, .. } => {
+                        let error = types::Error::ReadOnly;
+                        let resp = Response::Error {
+                            id: Some(id),
+                            error,
+                        };
+                        socket.send(text(&resp)).await.unwrap();
+                    }
+                    Request::Subscribe { id, brain } => {
+                        let resp = Response::Subscribed {
+                            id,
+                            brain: brain.clone(),
+                        };
+                        socket.send(text(&resp)).await.unwrap();
+
+                        let trained = types::responses::Trained {
+                            data: "hello".into(),
+                            time: std::time::Duration::from_millis(1),
+                        };
+                        let resp = Response::Training { brain, trained };
+                        socket.send(text(&resp)).await.unwrap();
+                    }
+                    Request::Unsubscribe { id, brain } => {
+                        let resp = Response::Unsubscribed { id, brain };
+                        socket.send(text(&resp)).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn correlated() {
+        let conn = Connection::connect(serve().await).await.unwrap();
+
+        let (foo, bar) = tokio::join!(
+            conn.generate("foo", Default::default()),
+            conn.generate("bar", Default::default())
+        );
+        assert_eq!(foo.unwrap().name, "foo");
+        assert_eq!(bar.unwrap().name, "bar");
+
+        let err = conn.train("foo", "hello").await.unwrap_err();
+        assert_matches!(
+            err,
+            Error::Server {
+                err: types::Error::ReadOnly
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribed() {
+        let conn = Connection::connect(serve().await).await.unwrap();
+
+        let mut training = conn.subscribe("foo").await.unwrap();
+        let trained = training.next().await.unwrap();
+        assert_eq!(trained.data, "hello");
+
+        conn.unsubscribe("foo").await.unwrap();
+        assert!(training.next().await.is_none());
+    }
+}
+
+#[cfg(feature = "unix")]
+mod unix_socket {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response};
+    use matches::assert_matches;
+    use std::convert::Infallible;
+    use std::path::PathBuf;
+
+    async fn handle(req: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
+        let token = req.headers().get("authorization");
+        let authorized = token.and_then(|token| token.to_str().ok()) == Some("Bearer secret");
+        let path = req.uri().path().to_string();
+        let query = req.uri().query().unwrap_or_default().to_string();
+
+        let (status, body) = match path.as_str() {
+            "/v1/brains/foo/generate" if authorized && query == "min=5" => {
+                let generated = types::responses::Generated {
+                    name: "foo".into(),
+                    data: "hello world".into(),
+                };
+                (200, serde_json::to_vec(&generated).unwrap())
+            }
+            "/v1/brains/foo/train" if authorized => {
+                let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                let data: types::input::TrainData = serde_json::from_slice(&body).unwrap();
+                let trained = types::responses::Trained {
+                    data: data.data,
+                    time: std::time::Duration::from_millis(1),
+                };
+                (200, serde_json::to_vec(&trained).unwrap())
+            }
+            _ => {
+                let err = types::Error::NotFound { name: path };
+                (404, serde_json::to_vec(&err).unwrap())
+            }
+        };
+
+        let mut resp = Response::new(Body::from(body));
+        *resp.status_mut() = hyper::StatusCode::from_u16(status).unwrap();
+        Ok(resp)
+    }
+
+    async fn serve(name: &str) -> PathBuf {
+        let file = format!("brain-client-{}-{}.sock", name, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+
+        let mut listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let incoming = hyper::server::accept::from_stream(listener.incoming());
+            let service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+            hyper::Server::builder(incoming).serve(service).await
+        });
+
+        path
+    }
+
+    #[tokio::test]
+    async fn requests() {
+        let path = serve("requests").await;
+        let client = Client::unix(&path).token("secret");
+
+        let generated = client.generate("foo").min(5_usize).send().await.unwrap();
+        assert_eq!(generated.data, "hello world");
+
+        let trained = client.train("foo", "hello there").send().await.unwrap();
+        assert_eq!(trained.data, "hello there");
+
+        let err = client.info("bar").send().await.unwrap_err();
+        assert_matches!(err, Error::NotFound { ref name } if name == "/v1/brains/bar");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn not_listening() {
+        let path = std::env::temp_dir().join("brain-client-missing.sock");
+        let err = Client::unix(path).list().send().await.unwrap_err();
+        assert_matches!(err, Error::Unix { .. });
+    }
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use super::*;
+    use futures::{future, StreamExt as _, TryStreamExt as _};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response};
+    use matches::assert_matches;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_rustls::rustls::{internal::pemfile, NoClientAuth, ServerConfig};
+
+    // the server's self-signed test certificate for localhost
+    const CERT: &[u8] = include_bytes!("../../brain/testdata/ec.pem");
+    const KEY: &[u8] = include_bytes!("../../brain/testdata/ec.key");
+
+    async fn handle(req: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
+        if req.uri().path() == "/v1/brains/slow/generate" {
+            tokio::time::delay_for(Duration::from_secs(5)).await;
+        }
+        let generated = types::responses::Generated {
+            name: "foo".into(),
+            data: "hello world".into(),
+        };
+        Ok(Response::new(Body::from(
+            serde_json::to_vec(&generated).unwrap(),
+        )))
+    }
+
+    /// Serves https on a new port, returning its url
+    async fn serve() -> String {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        let certs = pemfile::certs(&mut &*CERT).unwrap();
+        let key = pemfile::pkcs8_private_keys(&mut &*KEY).unwrap().remove(0);
+        config.set_single_cert(certs, key).unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let incoming = listener
+                .incoming()
+                .and_then(move |conn| acceptor.accept(conn))
+                // clients that don't trust the certificate fail their handshakes
+                .filter(|conn| future::ready(conn.is_ok()));
+            let incoming = hyper::server::accept::from_stream(incoming);
+            let service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+            hyper::Server::builder(incoming).serve(service).await
+        });
+
+        format!("https://localhost:{}", port)
+    }
+
+    #[tokio::test]
+    async fn root_certificate() {
+        let url = serve().await;
+
+        let client = Client::new(&url).root_certificate(CERT).unwrap();
+        let generated = client.generate("foo").send().await.unwrap();
+        assert_eq!(generated.data, "hello world");
+
+        let err = Client::new(&url).generate("foo").send().await.unwrap_err();
+        assert_matches!(err, Error::Client { .. });
+    }
+
+    #[tokio::test]
+    async fn root_certificate_keeps_builder() {
+        let url = serve().await;
+
+        let builder = || reqwest::Client::builder().timeout(Duration::from_millis(200));
+        let client = Client::with_builder(&url, builder)
+            .unwrap()
+            .root_certificate(CERT)
+            .unwrap();
+        let generated = client.generate("foo").send().await.unwrap();
+        assert_eq!(generated.data, "hello world");
+
+        let err = client.generate("slow").send().await.unwrap_err();
+        assert_matches!(err, Error::Client { ref err } if err.is_timeout());
+    }
+}
@@ -0,0 +1,109 @@
+//! Sends the [`Client`](crate::Client)'s requests over a unix domain socket
+//!
+//! reqwest can't connect to unix sockets, so requests are still built with it,
+//! then sent with hyper, and the responses are handed back as reqwest's.
+use crate::{Error, Result};
+
+use futures::future::{BoxFuture, FutureExt as _};
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::UnixStream;
+
+pub type UnixClient = hyper::Client<UnixConnector>;
+
+pub fn client(path: PathBuf) -> UnixClient {
+    hyper::Client::builder().build(UnixConnector {
+        path: Arc::new(path),
+    })
+}
+
+pub async fn send(client: &UnixClient, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    let req = req.build().map_err(|err| Error::Client { err })?;
+
+    // the host is only used for the host header, the socket is always used
+    let uri = req
+        .url()
+        .as_str()
+        .parse::<hyper::Uri>()
+        .map_err(|err| Error::Unix {
+            reason: format!("invalid url: {}", err),
+        })?;
+    // every body the client sends is in memory
+    let body = req
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| hyper::Body::from(bytes.to_vec()))
+        .unwrap_or_else(hyper::Body::empty);
+
+    let mut request = hyper::Request::new(body);
+    *request.method_mut() = req.method().clone();
+    *request.uri_mut() = uri;
+    *request.headers_mut() = req.headers().clone();
+
+    let resp = client.request(request).await.map_err(|err| Error::Unix {
+        reason: err.to_string(),
+    })?;
+    Ok(resp.map(reqwest::Body::wrap_stream).into())
+}
+
+#[derive(Clone)]
+pub struct UnixConnector {
+    path: Arc<PathBuf>,
+}
+
+impl Service<hyper::Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<UnixConnection>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: hyper::Uri) -> Self::Future {
+        let path = Arc::clone(&self.path);
+        async move { UnixStream::connect(&*path).await.map(UnixConnection) }.boxed()
+    }
+}
+
+pub struct UnixConnection(UnixStream);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,253 @@
+//! A long-lived websocket connection to the brain server
+//!
+//! Requests are tagged with an id, so many of them can be in flight at once on
+//! the same connection.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), client::Error> {
+//! use client::ws::Connection;
+//! use futures::StreamExt as _;
+//!
+//! let conn = Connection::connect("ws://localhost:9090/v1/ws").await?;
+//! let generated = conn.generate("foo", Default::default()).await?;
+//!
+//! let mut training = conn.subscribe("foo").await?;
+//! while let Some(trained) = training.next().await {
+//!     println!("foo learned: {}", trained.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::{Error, Result};
+use types::{input, responses, ws};
+
+use futures::{SinkExt as _, Stream, StreamExt as _};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+type Pending = HashMap<u64, oneshot::Sender<ws::Response>>;
+type Subscribers = HashMap<String, Vec<mpsc::UnboundedSender<responses::Trained>>>;
+
+#[derive(Default)]
+struct Shared {
+    // None once the connection is closed
+    pending: Mutex<Option<Pending>>,
+    subscribers: Mutex<Subscribers>,
+}
+
+/// A websocket connection to the brain server
+///
+/// The connection is closed when this is dropped.
+pub struct Connection {
+    next_id: AtomicU64,
+    outgoing: mpsc::UnboundedSender<ws::Request>,
+    shared: Arc<Shared>,
+}
+
+impl Connection {
+    /// Connects to the websocket at `url`, e.g. `ws://localhost:9090/v1/ws`
+    pub async fn connect(url: impl AsRef<str>) -> Result<Self> {
+        let (socket, _) = tokio_tungstenite::connect_async(url.as_ref())
+            .await
+            .map_err(|err| Error::WebSocket {
+                reason: err.to_string(),
+            })?;
+        let (mut sink, mut incoming) = socket.split();
+
+        let (outgoing, mut requests) = mpsc::unbounded_channel::<ws::Request>();
+        tokio::spawn(async move {
+            while let Some(req) = requests.recv().await {
+                let text = serde_json::to_string(&req).expect("serialize request");
+                if sink.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(Some(HashMap::new())),
+            subscribers: Default::default(),
+        });
+
+        let reader = Arc::clone(&shared);
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = incoming.next().await {
+                let text = match msg {
+                    Message::Text(text) => text,
+                    Message::Close(..) => break,
+                    _ => continue,
+                };
+                if let Ok(resp) = serde_json::from_str(&text) {
+                    reader.dispatch(resp);
+                }
+            }
+
+            // wakes up everyone still waiting
+            reader.pending.lock().unwrap().take();
+            reader.subscribers.lock().unwrap().clear();
+        });
+
+        Ok(Self {
+            next_id: AtomicU64::new(1),
+            outgoing,
+            shared,
+        })
+    }
+
+    pub async fn generate(
+        &self,
+        brain: impl ToString,
+        opts: input::GenerateOptions,
+    ) -> Result<responses::Generated> {
+        let brain = brain.to_string();
+        match self
+            .request(|id| ws::Request::Generate { id, brain, opts })
+            .await?
+        {
+            ws::Response::Generated { generated, .. } => Ok(generated),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    pub async fn train(
+        &self,
+        brain: impl ToString,
+        data: impl ToString,
+    ) -> Result<responses::Trained> {
+        let brain = brain.to_string();
+        let data = input::TrainData {
+            data: data.to_string(),
+        };
+        match self
+            .request(|id| ws::Request::Train { id, brain, data })
+            .await?
+        {
+            ws::Response::Trained { trained, .. } => Ok(trained),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Receives whatever `brain` is trained with, by any client
+    pub async fn subscribe(&self, brain: impl ToString) -> Result<Subscription> {
+        let brain = brain.to_string();
+
+        // registered first, so events right after the reply aren't missed
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap()
+            .entry(brain.clone())
+            .or_default()
+            .push(tx);
+
+        let name = brain.clone();
+        match self
+            .request(|id| ws::Request::Subscribe { id, brain })
+            .await
+        {
+            Ok(ws::Response::Subscribed { .. }) => Ok(Subscription { rx }),
+            Ok(resp) => Err(unexpected(resp)),
+            Err(err) => {
+                self.shared.subscribers.lock().unwrap().remove(&name);
+                Err(err)
+            }
+        }
+    }
+
+    /// Stops every subscription to `brain` on this connection
+    pub async fn unsubscribe(&self, brain: impl ToString) -> Result<()> {
+        let brain = brain.to_string();
+        self.shared.subscribers.lock().unwrap().remove(&brain);
+        match self
+            .request(|id| ws::Request::Unsubscribe { id, brain })
+            .await?
+        {
+            ws::Response::Unsubscribed { .. } => Ok(()),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    async fn request(&self, make: impl FnOnce(u64) -> ws::Request) -> Result<ws::Response> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        match self.shared.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => return Err(disconnected()),
+        };
+
+        if self.outgoing.send(make(id)).is_err() {
+            return Err(disconnected());
+        }
+
+        match rx.await {
+            Ok(ws::Response::Error { error, .. }) => Err(error.into()),
+            Ok(resp) => Ok(resp),
+            Err(..) => Err(disconnected()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection").finish()
+    }
+}
+
+impl Shared {
+    fn dispatch(&self, resp: ws::Response) {
+        if let ws::Response::Training { brain, trained } = resp {
+            if let Some(subscribers) = self.subscribers.lock().unwrap().get_mut(&brain) {
+                subscribers.retain(|tx| tx.send(trained.clone()).is_ok());
+            }
+            return;
+        }
+
+        // errors without an id are for requests that couldn't be read
+        let id = match resp.id() {
+            Some(id) => id,
+            None => return,
+        };
+
+        let tx = match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.remove(&id),
+            None => None,
+        };
+        if let Some(tx) = tx {
+            let _ = tx.send(resp);
+        }
+    }
+}
+
+/// The training events for a brain
+///
+/// Ends when the connection is closed, or the brain is unsubscribed from.
+pub struct Subscription {
+    rx: mpsc::UnboundedReceiver<responses::Trained>,
+}
+
+impl Stream for Subscription {
+    type Item = responses::Trained;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+fn disconnected() -> Error {
+    Error::WebSocket {
+        reason: "the connection was closed".into(),
+    }
+}
+
+fn unexpected(resp: ws::Response) -> Error {
+    Error::WebSocket {
+        reason: format!("unexpected response: {:?}", resp),
+    }
+}
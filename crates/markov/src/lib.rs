@@ -1,6 +1,7 @@
 use hashbrown::{HashMap, HashSet};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::io::{Read as _, Write as _};
 use std::path::Path;
 
 mod error;
@@ -11,6 +12,12 @@ pub use self::markov::Markov;
 
 mod linkset;
 
+mod metadata;
+pub use metadata::Metadata;
+
+mod walk;
+pub use walk::Walk;
+
 pub mod types {
     #[doc(inline)]
     pub use super::linkset::{Link, LinkSet, Token};
@@ -18,22 +25,51 @@ pub mod types {
 
 use types::*;
 
+// brain files with metadata start with this, the last byte is the version.
+// older files start with the chain's length instead
+const HEADER: &[u8; 8] = b"MARKOV\x00\x01";
+
+/// Loads a brain file, along with its metadata
+///
+/// Files from before the metadata was stored load with empty metadata.
 pub fn load(input: impl AsRef<Path>) -> Result<Markov, Error> {
     let input = input.as_ref();
     log::debug!(target: "brain", "loading from file: '{}'", input.display());
     let reader = std::fs::File::open(input)?;
-    let reader = snap::read::FrameDecoder::new(reader);
-    let markov: Markov = bincode::deserialize_from(reader).map_err(Error::Deserialize)?;
+    let mut reader = snap::read::FrameDecoder::new(reader);
+
+    let mut header = Vec::with_capacity(HEADER.len());
+    reader
+        .by_ref()
+        .take(HEADER.len() as u64)
+        .read_to_end(&mut header)?;
+
+    let markov = if header == HEADER {
+        let meta = bincode::deserialize_from(&mut reader).map_err(Error::Deserialize)?;
+        let mut markov: Markov = bincode::deserialize_from(reader).map_err(Error::Deserialize)?;
+        markov.meta = meta;
+        markov
+    } else {
+        log::debug!(target: "brain", "'{}' has no metadata", input.display());
+        let reader = std::io::Cursor::new(header).chain(reader);
+        bincode::deserialize_from(reader).map_err(Error::Deserialize)?
+    };
     log::trace!(target: "brain", "done deserializing data, got: {}", markov.name);
     Ok(markov)
 }
 
+/// Saves the brain and its metadata
+///
+/// The metadata is saved as it is, so `last_saved` should be set beforehand.
 pub fn save(markov: &Markov, output: impl AsRef<Path>) -> Result<(), Error> {
     let output = output.as_ref();
     log::debug!(target: "brain", "saving '{}' to file: {}", markov.name, output.display());
     let writer = std::fs::File::create(output)?;
-    let writer = snap::write::FrameEncoder::new(writer);
-    bincode::serialize_into(writer, &markov).map_err(Error::Serialize)?;
+    let mut writer = snap::write::FrameEncoder::new(writer);
+    writer.write_all(HEADER)?;
+    bincode::serialize_into(&mut writer, &markov.meta).map_err(Error::Serialize)?;
+    bincode::serialize_into(&mut writer, &markov).map_err(Error::Serialize)?;
+    writer.flush()?;
     log::trace!(target: "brain", "done serializing data");
     Ok(())
 }
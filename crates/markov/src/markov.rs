@@ -6,6 +6,12 @@ pub struct Markov {
     pub starts: HashSet<Vec<u8>>,
     pub depth: usize,
     pub name: String,
+    /// Saved ahead of the chain, so files without it can still be loaded
+    #[serde(skip)]
+    pub meta: Metadata,
+    /// Whether it was trained since it was loaded or saved
+    #[serde(skip)]
+    pub dirty: bool,
 }
 
 impl Markov {
@@ -15,6 +21,11 @@ impl Markov {
             name: name.to_string(),
             chain: Default::default(),
             starts: Default::default(),
+            meta: Metadata {
+                created: Some(metadata::now()),
+                ..Metadata::default()
+            },
+            dirty: false,
         }
     }
 
@@ -26,82 +37,27 @@ impl Markov {
         max: usize,
         query: Option<&str>,
     ) -> Option<String> {
-        #[inline(always)]
-        fn context(words: &[Vec<u8>], depth: usize) -> &[Vec<u8>] {
-            &words[words.len().saturating_sub(depth)..]
-        };
-
-        let chances = [rng.gen_range(0.10, 0.40), rng.gen_range(0.10, 0.40)];
-        let mut desired = rng.gen_range(1, 3);
-        let mut last = false;
-
-        log::trace!(target: "brain", "min: {}, max: {}, query: {:?}", min, max, query);
-
-        let mut words: Vec<Vec<u8>> = vec![];
-        let mut count = 0;
-        loop {
-            match query {
-                Some(query) if rng.gen_bool(chances[0]) && desired > 0 && !last => {
-                    words.push(query.as_bytes().to_vec());
-                    desired -= 1;
-                    last = true;
-                }
-                _ => {
-                    let start = self.starts.iter().choose(rng)?.clone();
-                    words.push(start);
-                    last = false;
-                }
-            }
-
-            if words.len() >= max {
-                log::trace!(target: "brain", "exceeding max, words: {}, max: {}", words.len(), max);
-                break;
-            }
-
-            while let Token::Word(word) = self.next_word(rng, context(words.as_slice(), self.depth))
-            {
-                if let Some(query) = query {
-                    if rng.gen_bool(chances[1]) && desired > 0 && !last {
-                        words.push(query.as_bytes().to_vec());
-                        desired -= 1;
-                    }
-                }
-
-                words.push(word.clone());
-                last = false;
-                if words.len() >= max {
-                    log::trace!(target: "brain", "exceeding max, inner: words: {}, max: {}", words.len(), max);
-                    break;
-                }
-            }
-
-            if words.len() >= min {
-                log::trace!(target: "brain", "exceeding min, words: {}, min: {}", words.len(), min);
-                break;
-            }
-
-            if count == words.len() {
-                log::trace!(target: "brain", "no progress, words: {}, count: {}", words.len(), count);
-                break;
-            }
-            count = words.len();
+        let mut walk = self.walk(rng, min, max, query);
+        let words = walk.by_ref().collect::<Vec<_>>();
+        if walk.is_stuck() {
+            return None;
         }
+        Some(words.join(" "))
+    }
 
-        words
-            .iter()
-            .map(|s| s.as_ref())
-            .flat_map(std::str::from_utf8)
-            .fold(String::new(), |mut acc, str| {
-                if !acc.is_empty() {
-                    acc.push_str(" ")
-                }
-                acc.push_str(str);
-                acc
-            })
-            .into()
+    /// Walks the chain, yielding each word as soon as it is chosen
+    pub fn walk<'a, R: ?Sized + Rng>(
+        &'a self,
+        rng: &'a mut R,
+        min: usize,
+        max: usize,
+        query: Option<&'a str>,
+    ) -> Walk<'a, R> {
+        Walk::new(self, rng, min, max, query)
     }
 
     pub fn train_text(&mut self, text: &str) {
+        let mut trained = false;
         for set in text
             .split_terminator(|c| ".?!\n".contains(c))
             .map(|s| {
@@ -113,10 +69,76 @@ impl Markov {
             })
             .filter(|s| !s.is_empty())
         {
-            self.train_words(set)
+            self.train_words(set);
+            trained = true;
+        }
+
+        if trained {
+            self.meta.lines += text.lines().count() as u64;
+            self.meta.last_trained = Some(metadata::now());
+            self.dirty = true;
         }
     }
 
+    /// Marks it as saved just now, returning when it was saved before
+    ///
+    /// This is done before saving, so lines trained while it is being written
+    /// still count as unsaved.
+    pub fn mark_saved(&mut self) -> Option<u64> {
+        self.dirty = false;
+        self.meta.last_saved.replace(metadata::now())
+    }
+
+    /// A copy at a lower depth, as if it had been trained at that depth
+    ///
+    /// The chain has contexts of every width up to its depth, so the wider ones
+    /// are dropped. `None` when the depth is zero or higher than its own.
+    pub fn with_depth(&self, depth: usize) -> Option<Self> {
+        if depth == 0 || depth > self.depth {
+            return None;
+        }
+
+        let chain = self
+            .chain
+            .iter()
+            .filter(|(context, _)| context.len() <= depth)
+            .map(|(context, links)| (context.clone(), links.clone()))
+            .collect();
+
+        Some(Markov {
+            chain,
+            starts: self.starts.clone(),
+            depth,
+            name: self.name.clone(),
+            meta: self.meta.clone(),
+            dirty: true,
+        })
+    }
+
+    /// A rough estimate of how much memory the chain takes, in bytes
+    pub fn memory_size(&self) -> usize {
+        use std::mem::size_of;
+        let word = |word: &Vec<u8>| size_of::<Vec<u8>>() + word.capacity();
+        let link = |link: &Link| {
+            size_of::<Link>()
+                + match &link.token {
+                    Token::Word(word) => word.capacity(),
+                    Token::End => 0,
+                }
+        };
+
+        let chain = self
+            .chain
+            .iter()
+            .map(|(context, links)| {
+                size_of::<(Vec<Vec<u8>>, LinkSet)>()
+                    + context.iter().map(word).sum::<usize>()
+                    + links.iter().map(link).sum::<usize>()
+            })
+            .sum::<usize>();
+        chain + self.starts.iter().map(word).sum::<usize>()
+    }
+
     fn train_words(&mut self, words: Vec<Vec<u8>>) {
         self.starts.insert(words[0].clone());
 
@@ -142,7 +164,7 @@ impl Markov {
             .insert(token);
     }
 
-    fn next_word<R: ?Sized + Rng>(&self, rng: &mut R, context: &[Vec<u8>]) -> Token {
+    pub(crate) fn next_word<R: ?Sized + Rng>(&self, rng: &mut R, context: &[Vec<u8>]) -> Token {
         let upper = std::cmp::min(self.depth, context.len());
         let mut link_sets = (1..=upper)
             .filter_map(|width| {
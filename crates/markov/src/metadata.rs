@@ -0,0 +1,24 @@
+use crate::*;
+
+/// What a brain file records about the brain, besides its chain
+///
+/// Times are unix time in seconds. They are `None` for brains from files
+/// saved before metadata was stored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub created: Option<u64>,
+    pub last_trained: Option<u64>,
+    pub last_saved: Option<u64>,
+    /// Lines trained since it was created
+    pub lines: u64,
+    /// What the brain was generated from
+    pub source: Option<String>,
+}
+
+/// Seconds since the unix epoch
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
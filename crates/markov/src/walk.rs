@@ -0,0 +1,166 @@
+use crate::*;
+
+/// A walk over the chain, producing one word at a time
+///
+/// Created by [`Markov::walk`]. This makes the same choices as
+/// [`Markov::generate`], which is just a collected walk.
+pub struct Walk<'a, R: ?Sized> {
+    markov: &'a Markov,
+    rng: &'a mut R,
+    min: usize,
+    max: usize,
+    query: Option<&'a str>,
+
+    chances: [f64; 2],
+    desired: usize,
+    last: bool,
+
+    words: Vec<Vec<u8>>,
+    // how many words have been yielded
+    emitted: usize,
+    // how many words there were at the end of the last pass
+    count: usize,
+    state: State,
+    stuck: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Start,
+    Walking,
+    Check,
+    Done,
+}
+
+impl<'a, R: ?Sized + Rng> Walk<'a, R> {
+    pub(crate) fn new(
+        markov: &'a Markov,
+        rng: &'a mut R,
+        min: usize,
+        max: usize,
+        query: Option<&'a str>,
+    ) -> Self {
+        let chances = [rng.gen_range(0.10, 0.40), rng.gen_range(0.10, 0.40)];
+        let desired = rng.gen_range(1, 3);
+
+        log::trace!(target: "brain", "min: {}, max: {}, query: {:?}", min, max, query);
+
+        Self {
+            markov,
+            rng,
+            min,
+            max,
+            query,
+            chances,
+            desired,
+            last: false,
+            words: vec![],
+            emitted: 0,
+            count: 0,
+            state: State::Start,
+            stuck: false,
+        }
+    }
+
+    /// Whether the walk ran out of places to start from
+    pub fn is_stuck(&self) -> bool {
+        self.stuck
+    }
+
+    // advances the walk by at most one link. returns false once it is done
+    fn step(&mut self) -> bool {
+        match self.state {
+            State::Start => {
+                match self.query {
+                    Some(query)
+                        if self.rng.gen_bool(self.chances[0]) && self.desired > 0 && !self.last =>
+                    {
+                        self.words.push(query.as_bytes().to_vec());
+                        self.desired -= 1;
+                        self.last = true;
+                    }
+                    _ => match self.markov.starts.iter().choose(&mut *self.rng) {
+                        Some(start) => {
+                            self.words.push(start.clone());
+                            self.last = false;
+                        }
+                        None => {
+                            self.stuck = true;
+                            self.state = State::Done;
+                            return false;
+                        }
+                    },
+                }
+
+                if self.words.len() >= self.max {
+                    log::trace!(target: "brain", "exceeding max, words: {}, max: {}", self.words.len(), self.max);
+                    self.state = State::Done;
+                } else {
+                    self.state = State::Walking;
+                }
+            }
+
+            State::Walking => {
+                let context = &self.words[self.words.len().saturating_sub(self.markov.depth)..];
+                let word = match self.markov.next_word(&mut *self.rng, context) {
+                    Token::Word(word) => word,
+                    Token::End => {
+                        self.state = State::Check;
+                        return true;
+                    }
+                };
+
+                if let Some(query) = self.query {
+                    if self.rng.gen_bool(self.chances[1]) && self.desired > 0 && !self.last {
+                        self.words.push(query.as_bytes().to_vec());
+                        self.desired -= 1;
+                    }
+                }
+
+                self.words.push(word);
+                self.last = false;
+                if self.words.len() >= self.max {
+                    log::trace!(target: "brain", "exceeding max, inner: words: {}, max: {}", self.words.len(), self.max);
+                    self.state = State::Check;
+                }
+            }
+
+            State::Check => {
+                if self.words.len() >= self.min {
+                    log::trace!(target: "brain", "exceeding min, words: {}, min: {}", self.words.len(), self.min);
+                    self.state = State::Done;
+                } else if self.count == self.words.len() {
+                    log::trace!(target: "brain", "no progress, words: {}, count: {}", self.words.len(), self.count);
+                    self.state = State::Done;
+                } else {
+                    self.count = self.words.len();
+                    self.state = State::Start;
+                }
+            }
+
+            State::Done => return false,
+        }
+        true
+    }
+}
+
+impl<'a, R: ?Sized + Rng> Iterator for Walk<'a, R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(word) = self.words.get(self.emitted) {
+                self.emitted += 1;
+                // words that aren't utf-8 are kept for the context, but skipped
+                match std::str::from_utf8(word) {
+                    Ok(word) => return Some(word.to_string()),
+                    Err(..) => continue,
+                }
+            }
+
+            if !self.step() {
+                return None;
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 // TODO this isn't a real error
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "error")]
 pub enum Error {
     ReadOnly,
@@ -9,4 +9,49 @@ pub enum Error {
     CannotRotate { file: String, reason: String },
     CannotSave { file: String, reason: String },
     AlreadyExists { name: String },
+    CannotLoad { file: String, reason: String },
+    CannotDelete { file: String, reason: String },
+    CannotUpdateConfig { file: String, reason: String },
+    InvalidConfig { file: String, reason: String },
+    NotFound { name: String },
+    Loading { name: String },
+    JobNotFound { id: u64 },
+    UnknownRoute,
+    InvalidBody { reason: String },
+    InvalidQuery { reason: String },
+    InvalidHeader { reason: String },
+    PayloadTooLarge,
+    MethodNotAllowed,
+    UnsupportedMediaType,
+    Unauthorized,
+    Forbidden { reason: String },
+    RateLimited { retry_after: f64 },
+    Internal { reason: String },
+}
+
+impl Error {
+    /// The HTTP status code the server responds with for this error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Error::ReadOnly | Error::Forbidden { .. } => 403,
+            Error::Unauthorized => 401,
+            Error::RateLimited { .. } => 429,
+            Error::NotEnoughState => 422,
+            Error::AlreadyExists { .. } => 409,
+            Error::NotFound { .. } | Error::JobNotFound { .. } | Error::UnknownRoute => 404,
+            Error::InvalidBody { .. } | Error::InvalidQuery { .. } => 400,
+            Error::InvalidHeader { .. } => 400,
+            Error::InvalidConfig { .. } => 400,
+            Error::PayloadTooLarge => 413,
+            Error::MethodNotAllowed => 405,
+            Error::UnsupportedMediaType => 415,
+            Error::Loading { .. } => 503,
+            Error::CannotRotate { .. }
+            | Error::CannotSave { .. }
+            | Error::CannotLoad { .. }
+            | Error::CannotDelete { .. }
+            | Error::CannotUpdateConfig { .. }
+            | Error::Internal { .. } => 500,
+        }
+    }
 }
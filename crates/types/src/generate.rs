@@ -0,0 +1,125 @@
+//! How sentences are generated, shared by the server and the local client
+use crate::{input::GenerateOptions, Error};
+use serde::{Deserialize, Serialize};
+
+/// The words generated when a request doesn't say, and how many it may ask for
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GenerateDefaults {
+    pub min: usize,
+    pub max: usize,
+    /// Requests asking for more words get this many instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_words: Option<usize>,
+    /// Requests asking for more words are refused
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hard_max_words: Option<usize>,
+}
+
+impl Default for GenerateDefaults {
+    fn default() -> Self {
+        Self {
+            min: 5,
+            max: 30,
+            max_words: None,
+            hard_max_words: None,
+        }
+    }
+}
+
+/// A brain's generation defaults and limits
+///
+/// Anything missing falls back to `server.defaults`, or the server's
+/// `batch_limit`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GenerateConfig {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+    pub max_words: Option<usize>,
+    pub hard_max_words: Option<usize>,
+    /// The context used when a request doesn't have one
+    pub context: Option<String>,
+    /// Always use `context`, even when a request has its own
+    pub ignore_context: bool,
+    pub batch_limit: Option<usize>,
+}
+
+/// Where a request's options came from, which decides the kind of error
+/// invalid ones get
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionsSource {
+    Query,
+    /// A JSON body, or a websocket message
+    Body,
+}
+
+impl OptionsSource {
+    fn invalid(self, reason: String) -> Error {
+        match self {
+            OptionsSource::Query => Error::InvalidQuery { reason },
+            OptionsSource::Body => Error::InvalidBody { reason },
+        }
+    }
+}
+
+/// The options a sentence is generated with, after the brain's defaults and
+/// limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub min: usize,
+    pub max: usize,
+    pub context: Option<String>,
+}
+
+impl GenerateConfig {
+    /// Fills in a request's options from these defaults, then applies the
+    /// limits
+    ///
+    /// Asking for more words than `max_words` gets that many instead, and asking
+    /// for more than `hard_max_words` is an error, whose kind depends on
+    /// `source`.
+    pub fn options(
+        &self,
+        opts: &GenerateOptions,
+        defaults: &GenerateDefaults,
+        source: OptionsSource,
+    ) -> Result<Generation, Error> {
+        if let Some(hard) = self.hard_max_words.or(defaults.hard_max_words) {
+            for (name, value) in &[("min", opts.min), ("max", opts.max)] {
+                match value {
+                    Some(value) if *value > hard => {
+                        let reason = format!("{} must be at most {}", name, hard);
+                        return Err(source.invalid(reason));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut min = opts.min.or(self.min).unwrap_or(defaults.min);
+        let mut max = opts.max.or(self.max).unwrap_or(defaults.max);
+        if let Some(limit) = self.max_words.or(defaults.max_words) {
+            min = min.min(limit);
+            max = max.min(limit);
+        }
+
+        let context = match &opts.context {
+            Some(context) if !self.ignore_context => Some(context.clone()),
+            _ => self.context.clone(),
+        };
+        Ok(Generation { min, max, context })
+    }
+
+    /// Checks how many sentences a batch asks for, against this brain's limit
+    /// or else `batch_limit`
+    pub fn check_batch(&self, count: usize, batch_limit: usize) -> Result<(), Error> {
+        let limit = self.batch_limit.unwrap_or(batch_limit);
+        if count == 0 || count > limit {
+            return Err(Error::InvalidQuery {
+                reason: format!("count must be between 1 and {}", limit),
+            });
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct GenerateOptions {
     pub context: Option<String>,
     pub min: Option<usize>,
@@ -17,3 +17,46 @@ pub struct NewBrain {
     pub brain_file: String,
     pub depth: usize,
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenameBrain {
+    pub name: String,
+}
+
+/// Copies a brain to a new name and file, at a lower `depth` if there's one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CopyBrain {
+    pub name: String,
+    pub brain_file: String,
+    pub depth: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeleteOptions {
+    pub delete_file: Option<bool>,
+    pub keep_config: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerateBatchOptions {
+    pub count: usize,
+    pub context: Option<String>,
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+impl GenerateBatchOptions {
+    /// The options for each sentence in the batch
+    pub fn options(&self) -> GenerateOptions {
+        GenerateOptions {
+            context: self.context.clone(),
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainBatch {
+    pub data: Vec<String>,
+}
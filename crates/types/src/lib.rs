@@ -1,5 +1,16 @@
 mod error;
 pub use error::Error;
 
+pub mod generate;
 pub mod input;
 pub mod responses;
+pub mod ws;
+
+/// Whether a brain can be called `name`, which has to be letters, digits, `-`
+/// and `_`
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -1,3 +1,4 @@
+use crate::Error;
 use hashbrown::HashMap;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -9,6 +10,38 @@ pub struct Generated {
     pub data: String,
 }
 
+/// The result for one item of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItem<T> {
+    Ok(T),
+    Error(Error),
+}
+
+impl<T> BatchItem<T> {
+    pub fn into_result(self) -> Result<T, Error> {
+        match self {
+            BatchItem::Ok(item) => Ok(item),
+            BatchItem::Error(err) => Err(err),
+        }
+    }
+}
+
+impl<T> From<Result<T, Error>> for BatchItem<T> {
+    fn from(result: Result<T, Error>) -> Self {
+        match result {
+            Ok(item) => BatchItem::Ok(item),
+            Err(err) => BatchItem::Error(err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedBatch {
+    pub name: String,
+    pub results: Vec<BatchItem<String>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Saved {
     pub name: String,
@@ -33,22 +66,169 @@ impl PartialEq for Trained {
     }
 }
 
+/// The results are in the same order as the lines that were sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainedBatch {
+    pub results: Vec<BatchItem<Trained>>,
+    pub time: Duration,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Created {
     pub name: String,
     pub brain_file: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Renamed {
+    pub from: String,
+    pub name: String,
+    pub config_updated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Copied {
+    pub from: String,
+    pub name: String,
+    pub brain_file: String,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deleted {
+    pub name: String,
+    pub brain_file: PathBuf,
+    pub file_deleted: bool,
+    pub config_updated: bool,
+    /// What couldn't be done after the brain was unloaded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<Error>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Unloaded {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reloaded {
+    pub name: String,
+    pub brain_file: PathBuf,
+    pub time: Duration,
+}
+
+impl PartialEq for Reloaded {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.brain_file == other.brain_file
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigReloaded {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+    pub failed: HashMap<String, String>,
+    /// Brains that would have been unloaded or replaced, but were kept because
+    /// their unsaved training couldn't be saved
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub unsaved: HashMap<String, Error>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct List {
     pub brains: HashMap<String, ListItem>,
+    #[serde(default)]
+    pub failed: HashMap<String, Failed>,
+    /// Brains that are still being loaded
+    #[serde(default)]
+    pub loading: HashMap<String, ListItem>,
     pub config_path: PathBuf,
 }
 
+/// How many brains have been loaded
+///
+/// The server is ready once none of them are loading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub ready: bool,
+    pub loaded: usize,
+    pub loading: usize,
+    pub failed: usize,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "brain")]
 pub struct ListItem {
     pub name: String,
     pub brain_file: PathBuf,
     pub read_only: bool,
+    /// Missing for brains that are still loading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}
+
+/// What the brain file records about a brain, and its current state
+///
+/// Times are unix time in seconds. They are missing for brains from files
+/// saved before they were recorded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub depth: usize,
+    pub created: Option<u64>,
+    pub last_trained: Option<u64>,
+    pub last_saved: Option<u64>,
+    /// Lines trained since it was created
+    pub lines: u64,
+    /// What it was generated from
+    pub source: Option<String>,
+    /// Size of the brain file in bytes, missing when there isn't one
+    pub file_size: Option<u64>,
+    /// A rough estimate of the chain's size in memory, in bytes
+    pub memory_size: u64,
+    /// Whether it was trained since it was last saved
+    pub dirty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Failed {
+    pub name: String,
+    pub brain_file: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    /// Cancelled, but still training the chunk it was on
+    Cancelling,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        match self {
+            JobState::Running | JobState::Cancelling => false,
+            JobState::Done | JobState::Cancelled | JobState::Failed => true,
+        }
+    }
+}
+
+/// A background training job, and how far along it is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub brain: String,
+    pub state: JobState,
+    /// Lines trained so far
+    pub lines: usize,
+    /// Lines in the upload
+    pub total: usize,
+    pub lines_per_sec: f64,
+    pub elapsed: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
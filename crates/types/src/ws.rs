@@ -0,0 +1,93 @@
+//! The messages sent over the websocket
+//!
+//! Every request has an `id` chosen by the client, and the response to it has
+//! the same `id`. Responses can arrive in any order.
+use crate::input::{GenerateOptions, TrainData};
+use crate::responses::{Generated, Trained};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Request {
+    Generate {
+        id: u64,
+        brain: String,
+        #[serde(flatten)]
+        opts: GenerateOptions,
+    },
+    Train {
+        id: u64,
+        brain: String,
+        #[serde(flatten)]
+        data: TrainData,
+    },
+    /// Sends a `training` message whenever the brain is trained
+    Subscribe {
+        id: u64,
+        brain: String,
+    },
+    Unsubscribe {
+        id: u64,
+        brain: String,
+    },
+}
+
+impl Request {
+    pub fn id(&self) -> u64 {
+        match self {
+            Request::Generate { id, .. }
+            | Request::Train { id, .. }
+            | Request::Subscribe { id, .. }
+            | Request::Unsubscribe { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Response {
+    Generated {
+        id: u64,
+        #[serde(flatten)]
+        generated: Generated,
+    },
+    Trained {
+        id: u64,
+        #[serde(flatten)]
+        trained: Trained,
+    },
+    Subscribed {
+        id: u64,
+        brain: String,
+    },
+    Unsubscribed {
+        id: u64,
+        brain: String,
+    },
+    /// The request failed. There's no `id` if the request couldn't be read
+    Error {
+        id: Option<u64>,
+        error: Error,
+    },
+    /// A subscribed brain was trained, by any client
+    Training {
+        brain: String,
+        #[serde(flatten)]
+        trained: Trained,
+    },
+}
+
+impl Response {
+    /// The id of the request this responds to
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            Response::Generated { id, .. }
+            | Response::Trained { id, .. }
+            | Response::Subscribed { id, .. }
+            | Response::Unsubscribed { id, .. } => Some(*id),
+            Response::Error { id, .. } => *id,
+            Response::Training { .. } => None,
+        }
+    }
+}